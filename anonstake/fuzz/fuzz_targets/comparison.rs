@@ -0,0 +1,30 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use anonstake::circuit::comparison::testing;
+
+/*
+data[0]: width - 1, data[1] & 1: right = left, then the little endian bytes of left and of right
+missing bytes are zero. for width 255 bit 254 of right is cleared so that it is a field element
+*/
+fn read_bits(data: &[u8], width: usize) -> Vec<bool> {
+    (0..width).map(|i| data.get(i / 8).map_or(false, |byte| byte >> (i % 8) & 1 == 1)).collect()
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+
+    let width = data[0] as usize % 255 + 1;
+    let num_bytes = (width + 7) / 8;
+    let rest = &data[2..];
+
+    let left = read_bits(rest, width);
+    let mut right = if data[1] & 1 == 1 { left.clone() } else { read_bits(rest.get(num_bytes..).unwrap_or(&[]), width) };
+    if width == 255 {
+        right[254] = false;
+    }
+
+    testing::check(&left, &right);
+});
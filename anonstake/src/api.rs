@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use rand::RngCore;
+
+use bellman::Circuit;
+use bellman::groth16::{create_random_proof_with_input, generate_random_parameters, verify_proof};
+pub use bellman::groth16::{Parameters, PreparedVerifyingKey, prepare_verifying_key, Proof};
+pub use pairing::bls12_381::{Bls12, Fr};
+
+use crate::circuit::AnonStake;
+use crate::circuit::hash::CircuitHash;
+use crate::circuit::public_inputs::PublicInputs;
+use crate::error::Error;
+
+/*
+params, proving and verifying for any of the circuits (AnonStake, Transfer, Mint, ...) on bls12
+
+inputs are always passed and returned without the leading one, i.e. as verify_proof takes them
+prove_sortition and verify_sortition additionally read them as PublicInputs
+*/
+
+pub fn generate_params<C: Circuit<Bls12>, R: RngCore>(circuit: C, rng: &mut R) -> Result<Parameters<Bls12>, Error> {
+    generate_random_parameters(circuit, rng).map_err(Error::Synthesis)
+}
+
+//check_params checks that the points in the file are on the curve, slow for the large circuits
+pub fn read_params(path: &Path, check_params: bool) -> Result<Parameters<Bls12>, Error> {
+    let file = File::open(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => Error::ParamsMissing(path.to_path_buf()),
+        _ => Error::ParamsRead(path.to_path_buf(), e)
+    })?;
+
+    Parameters::<Bls12>::read(file, check_params).map_err(|e| Error::ParamsRead(path.to_path_buf(), e))
+}
+
+pub fn write_params(params: &Parameters<Bls12>, path: &Path) -> Result<(), Error> {
+    let file = File::create(path).map_err(|e| Error::ParamsWrite(path.to_path_buf(), e))?;
+    params.write(file).map_err(|e| Error::ParamsWrite(path.to_path_buf(), e))
+}
+
+pub fn prove<C: Circuit<Bls12>, R: RngCore>(params: &Parameters<Bls12>, circuit: C, rng: &mut R) -> Result<(Proof<Bls12>, Vec<Fr>), Error> {
+    let (proof, mut input) = create_random_proof_with_input(circuit, params, rng).map_err(Error::Proving)?;
+    input.remove(0);
+
+    Ok((proof, input))
+}
+
+//false from verify_proof is an error too, so that every caller has to handle it
+pub fn verify(pvk: &PreparedVerifyingKey<Bls12>, proof: &Proof<Bls12>, input: &[Fr]) -> Result<(), Error> {
+    let result = verify_proof(pvk, proof, input).map_err(Error::Verification)?;
+    if !result {
+        return Err(Error::InvalidProof(String::from("the proof")));
+    }
+
+    Ok(())
+}
+
+pub fn prove_sortition<H: CircuitHash<Bls12>, R: RngCore>(params: &Parameters<Bls12>, anonstake: AnonStake<Bls12, H>, rng: &mut R) -> Result<(Proof<Bls12>, PublicInputs<Bls12>), Error> {
+    let is_bp = anonstake.is_bp;
+    let (proof, input) = prove(params, anonstake, rng)?;
+
+    let public_inputs = PublicInputs::from_inputs(&input, is_bp)
+        .ok_or(Error::Config(format!("the circuit has {} public inputs, which is not the layout of PublicInputs", input.len())))?;
+
+    Ok((proof, public_inputs))
+}
+
+pub fn verify_sortition(pvk: &PreparedVerifyingKey<Bls12>, proof: &Proof<Bls12>, public_inputs: &PublicInputs<Bls12>) -> Result<(), Error> {
+    verify(pvk, proof, &public_inputs.to_inputs())
+}
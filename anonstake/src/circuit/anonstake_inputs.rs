@@ -1,9 +1,12 @@
+use std::marker::PhantomData;
+
 use rand::{Rng, thread_rng};
 
 use ff::Field;
 use zcash_primitives::jubjub::{JubjubEngine};
 
 use crate::circuit::AnonStake;
+use crate::circuit::hash::CircuitHash;
 use crate::constants::Constants;
 
 #[derive(Clone)]
@@ -43,6 +46,8 @@ pub struct AuxInput<E: JubjubEngine> {
     pub sn_less_diff: Option<E::Fr>,
     pub sn_plus_diff: Option<E::Fr>,
     pub j_i: Option<u64>,
+    //only used for delegated coins, a_sk is then the participation key of the delegate
+    pub a_pk_owner: Option<E::Fr>,
 }
 
 #[derive(Clone)]
@@ -55,27 +60,68 @@ pub struct BlockProposerPubInput {
 #[derive(Clone)]
 pub struct BlockProposerAuxInput;
 
+/*
+heights of the coin commitment tree and of the serial number tree, the two are independent
+init_empty, init_pure_random and init_testing use the same height for both, the _with_heights versions take them separately
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MerkleHeights {
+    pub cm: usize,
+    pub sn: usize,
+}
 
-impl<'a, E: JubjubEngine> AnonStake<'_, E> {
-    pub fn init_empty(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize, use_poseidon: bool) -> AnonStake<'a, E> {
-        let mut cm_merkle_path = vec![];
-        let mut sn_merkle_path = vec![];
-        for _i in 0..merkle_height {
-            cm_merkle_path.push(None);
-            sn_merkle_path.push(None);
-        }
+impl MerkleHeights {
+    pub fn new(cm: usize, sn: usize) -> MerkleHeights {
+        MerkleHeights { cm, sn }
+    }
 
-        let mut cm_poseidon_path = vec![];
-        let mut sn_poseidon_path = vec![];
-        for _ in 0..merkle_height {
-            cm_poseidon_path.push(None);
-            sn_poseidon_path.push(None);
+    //8-ary poseidon trees of height 10 and binary pedersen trees of height 29 hold about 2^30 leaves
+    pub fn default_for(use_poseidon: bool) -> MerkleHeights {
+        let height = if use_poseidon { 10 } else { 29 };
+        MerkleHeights::new(height, height)
+    }
+
+    //the position in a level takes 1 bit (pedersen) or 3 bits (poseidon) of a 64 bit leaf position
+    pub fn max_for(use_poseidon: bool) -> usize {
+        if use_poseidon { 21 } else { 64 }
+    }
+
+    pub fn check(&self, use_poseidon: bool) -> Result<(), String> {
+        let max = MerkleHeights::max_for(use_poseidon);
+        let hash = if use_poseidon { "poseidon" } else { "pedersen" };
+        for (name, height) in &[("cm", self.cm), ("sn", self.sn)] {
+            if *height == 0 || *height > max {
+                return Err(format!("{} tree height {} is not between 1 and {} for {} trees", name, height, max, hash));
+            }
         }
 
+        Ok(())
+    }
+}
+
+impl From<usize> for MerkleHeights {
+    fn from(height: usize) -> MerkleHeights {
+        MerkleHeights::new(height, height)
+    }
+}
+
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> AnonStake<'a, E, H> {
+    pub fn init_empty(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize) -> AnonStake<'a, E, H> {
+        Self::init_empty_with_heights(constants, is_bp, MerkleHeights::from(merkle_height))
+    }
+
+    pub fn init_empty_with_heights(constants: &'a Constants<E>, is_bp: bool, heights: MerkleHeights) -> AnonStake<'a, E, H> {
+        let cm_merkle_path = vec![None; heights.cm];
+        let sn_merkle_path = vec![None; heights.sn];
+
+        let cm_poseidon_path = vec![None; heights.cm];
+        let sn_poseidon_path = vec![None; heights.sn];
+
         AnonStake {
             constants: &constants,
             is_bp,
-            use_poseidon,
+            delegated: false,
             pub_input: PubInput {
 //                root_cm: None,
 //                root_sn: None,
@@ -104,6 +150,7 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
                 sn_less_diff: None,
                 sn_plus_diff: None,
                 j_i: None,
+                a_pk_owner: None,
             },
             bp_pub_input: BlockProposerPubInput {
                 r: None,
@@ -111,18 +158,25 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
 //                seed_comp: None,
             },
             bp_aux_input: BlockProposerAuxInput,
+            hash: PhantomData,
         }
     }
 
-    pub fn init_pure_random(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize, use_poseidon: bool) -> AnonStake<'a, E> {
+    pub fn init_pure_random(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize) -> AnonStake<'a, E, H> {
+        Self::init_pure_random_with_heights(constants, is_bp, MerkleHeights::from(merkle_height))
+    }
+
+    pub fn init_pure_random_with_heights(constants: &'a Constants<E>, is_bp: bool, heights: MerkleHeights) -> AnonStake<'a, E, H> {
         let rng = &mut thread_rng();
 
         let mut cm_merkle_path = vec![];
         let mut sn_merkle_path = vec![];
 
-        for _i in 0..merkle_height {
+        for _i in 0..heights.cm {
             let val = (E::Fr::random(rng), rng.gen());
             cm_merkle_path.push(Some(val));
+        }
+        for _i in 0..heights.sn {
             let val = (E::Fr::random(rng), rng.gen());
             sn_merkle_path.push(Some(val));
         }
@@ -130,12 +184,13 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
         let mut cm_poseidon_path = vec![];
         let mut sn_poseidon_path = vec![];
 
-        for _ in 0..merkle_height {
+        for _ in 0..heights.cm {
             let t: u8 = rng.gen::<u8>() % 8;
 
             let a = [E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng)];
             cm_poseidon_path.push(Some((a, t)));
-
+        }
+        for _ in 0..heights.sn {
             let t: u8 = rng.gen::<u8>() % 8;
 
             let a = [E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng)];
@@ -158,7 +213,7 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
         AnonStake {
             constants: &constants,
             is_bp,
-            use_poseidon,
+            delegated: false,
             pub_input: PubInput {
 //                root_cm: Some(E::Fr::random(rng)),
 //                root_sn: Some(E::Fr::random(rng)),
@@ -187,6 +242,7 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
                 sn_less_diff: Some(E::Fr::random(rng)),
                 sn_plus_diff: Some(E::Fr::random(rng)),
                 j_i: Some(1),
+                a_pk_owner: Some(E::Fr::random(rng)),
             },
             bp_pub_input: BlockProposerPubInput {
                 r: Some(rng.gen()),
@@ -194,29 +250,37 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
 //                seed_comp: Some(E::Fr::random(rng)),
             },
             bp_aux_input: BlockProposerAuxInput,
+            hash: PhantomData,
         }
     }
 
-    pub fn init_testing(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize, j_i: u64, use_poseidon: bool) -> AnonStake<'a, E> {
-        let rng = &mut thread_rng();
+    pub fn init_testing(constants: &'a Constants<E>, is_bp: bool, merkle_height: usize, j_i: u64) -> AnonStake<'a, E, H> {
+        Self::init_testing_with_rng(constants, is_bp, MerkleHeights::from(merkle_height), j_i, &mut thread_rng())
+    }
+
+    //same witness as init_testing, but reproducible from a seeded rng (the seed of a benchmark plan, see plan.rs)
+    pub fn init_testing_with_rng<R: Rng>(constants: &'a Constants<E>, is_bp: bool, heights: MerkleHeights, j_i: u64, rng: &mut R) -> AnonStake<'a, E, H> {
 
         let mut cm_merkle_path = vec![];
         let mut sn_merkle_path = vec![];
-        for _i in 0..merkle_height {
+        for _i in 0..heights.cm {
             let val = (E::Fr::random(rng), rng.gen());
             cm_merkle_path.push(Some(val));
+        }
+        for _i in 0..heights.sn {
             let val = (E::Fr::random(rng), rng.gen());
             sn_merkle_path.push(Some(val));
         }
 
         let mut cm_poseidon_path = vec![];
         let mut sn_poseidon_path = vec![];
-        for _ in 0..merkle_height {
+        for _ in 0..heights.cm {
             let t: u8 = rng.gen::<u8>() % 8;
 
             let a = [E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng)];
             cm_poseidon_path.push(Some((a, t)));
-
+        }
+        for _ in 0..heights.sn {
             let t: u8 = rng.gen::<u8>() % 8;
 
             let a = [E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng)];
@@ -236,18 +300,16 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
             fs_main_tree
         };
 
-        let role = rng.gen();
-        let fs_tree_start = if role < (1 << 36) {
-            rng.gen::<u64>() % role
-        } else {
-            role - (rng.gen::<u64>() % (1 << 36))
-        };
+        //any fs_tree_start with role - 2^36 < fs_tree_start <= role, see protocol::role
+        let role: u64 = rng.gen();
+        let max_time = if role < (1 << 36) { role + 1 } else { 1 << 36 };
+        let fs_tree_start = role - (rng.gen::<u64>() % max_time);
 
 
         AnonStake {
             constants: &constants,
             is_bp,
-            use_poseidon,
+            delegated: false,
             pub_input: PubInput {
 //                root_cm: Some(E::Fr::random(rng)),
 //                root_sn: Some(E::Fr::random(rng)),
@@ -275,6 +337,7 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
                 sn_less_diff: Some(E::Fr::one()),
                 sn_plus_diff: Some(E::Fr::one()),
                 j_i: Some(j_i),
+                a_pk_owner: Some(E::Fr::random(rng)),
             },
             bp_pub_input: BlockProposerPubInput {
                 r: Some(rng.gen()),
@@ -282,6 +345,14 @@ impl<'a, E: JubjubEngine> AnonStake<'_, E> {
 //                seed_comp: Some(E::Fr::random(rng)),
             },
             bp_aux_input: BlockProposerAuxInput,
+            hash: PhantomData,
         }
     }
+
+    //the coin's full_pk commits to a_pk_owner and to the a_pk of the delegate, whose key is a_sk
+    pub fn into_delegated(mut self, a_pk_owner: Option<E::Fr>) -> AnonStake<'a, E, H> {
+        self.delegated = true;
+        self.aux_input.a_pk_owner = a_pk_owner;
+        self
+    }
 }
@@ -0,0 +1,412 @@
+use bellman::{ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use bellman::gadgets::boolean::{AllocatedBit, Boolean};
+use bellman::gadgets::num::{AllocatedNum, Num};
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+
+/*
+comparisons of little endian bit vectors, used by sortition and the range checks of the sortition circuit
+leq_* enforce left <= right, assignment_not_leq_* return the bit left > right and are always satisfiable
+
+testing checks the gadgets against a native comparison, it is shared by the tests below and the fuzz target in fuzz/
+*/
+
+//the num_bits lowest bits of value, little endian. None if value does not fit into num_bits
+fn fixed_value_bits<F: PrimeField>(value: F, num_bits: usize) -> Option<Vec<bool>> {
+    let mut res = vec![];
+    let mut value = value.into_repr();
+
+    for _i in 0..num_bits {
+        res.push(value.is_odd());
+        value.div2();
+    }
+
+    if value.is_zero() {
+        Some(res)
+    } else {
+        None
+    }
+}
+
+/*
+allocates the bits and enforces that they pack to actual_value_var and that the number is <= value
+bits above the highest bit of value are not allocated, so the packing fails if any of them is set
+a value that does not fit into num_bits is treated as 2^num_bits - 1
+*/
+pub fn leq_fixed<E, CS>(mut cs: CS, namespace: &str, mut bits: Vec<Option<E::Fr>>, value: E::Fr, mut num_bits: usize, actual_value_var: Variable) -> Result<Vec<Variable>, SynthesisError>
+    where E: ScalarEngine, CS: ConstraintSystem<E>
+{
+    let value: Vec<bool> = fixed_value_bits(value, num_bits).unwrap_or(vec![true; num_bits]);
+
+    while num_bits > 0 && !value[num_bits - 1] {
+        num_bits -= 1;
+    }
+
+    let num_bits = num_bits;
+
+    assert!(bits.len() >= num_bits);
+    bits.truncate(num_bits);
+
+    //for value = 0 no bits are left and the packing makes the number zero
+    let mut prev_pi_val = if num_bits > 0 { bits[num_bits - 1] } else { None };
+    let mut prev_pi_var: Option<Variable> = None;
+
+    let mut res_bits = vec![];
+
+    for i in (0..num_bits).rev() {
+        let new_bit = cs.alloc(|| format!("{}: leq_fixed allocate bit: {}", namespace, i),
+                               || bits[i].ok_or(SynthesisError::AssignmentMissing))?;
+
+        res_bits.push(new_bit);
+
+        if value[i] {
+            if i == num_bits - 1 {
+                prev_pi_var = Some(new_bit);
+            } else {
+                let new_pi = cs.alloc(|| format!("{}: leq_fixed allocate pi_i: {}", namespace, i),
+                                      || {
+                                          let mut x = prev_pi_val.ok_or(SynthesisError::AssignmentMissing)?;
+                                          x.mul_assign(&bits[i].ok_or(SynthesisError::AssignmentMissing)?);
+                                          Ok(x)
+                                      })?;
+
+                if let Some(prev_pi) = prev_pi_var {
+                    cs.enforce(|| format!("{}: constrain pi_i: {}", namespace, i),
+                               |lc| lc + prev_pi,
+                               |lc| lc + new_bit,
+                               |lc| lc + new_pi);
+                } else {
+                    return Err(SynthesisError::Unsatisfiable);
+                }
+
+                prev_pi_var = Some(new_pi);
+                prev_pi_val = {
+                    match bits[i] {
+                        Some(mut t) => {
+                            t.mul_assign(&prev_pi_val.ok_or(SynthesisError::Unsatisfiable)?);
+                            Some(t)
+                        }
+                        None => None
+                    }
+                };
+            }
+
+            cs.enforce(|| format!("{}: constrain bit {}", namespace, i),
+                       |lc| lc + CS::one() - new_bit,
+                       |lc| lc + new_bit,
+                       |lc| lc);
+        } else {
+            if let Some(pi_var) = prev_pi_var {
+                cs.enforce(|| format!("{}: constrain bit {}", namespace, i),
+                           |lc| lc + CS::one() - pi_var - new_bit,
+                           |lc| lc + new_bit,
+                           |lc| lc);
+            } else {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+    }
+    res_bits.reverse();
+
+    let mut lc = LinearCombination::zero();
+    let mut coeff = E::Fr::one();
+    for i in &res_bits {
+        lc = lc + (coeff, *i);
+        coeff.double();
+    }
+
+    cs.enforce(|| format!("{}: constraining bits to value", namespace),
+               |_| lc,
+               |lc| lc + CS::one(),
+               |lc| lc + actual_value_var);
+
+    Ok(res_bits)
+}
+
+//enforces left <= right
+pub fn leq_not_fixed<E, CS>(mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, right_bits: &Vec<Boolean>) -> Result<(), SynthesisError>
+    where E: ScalarEngine, CS: ConstraintSystem<E>
+{
+    assert_eq!(left_bits.len(), right_bits.len());
+
+    let mut pi = Boolean::Constant(true);
+
+    for i in (0..right_bits.len()).rev() {
+        let t = Boolean::and(cs.namespace(|| format!("{}: calc t_i: {}", namespace, i)),
+                             &left_bits[i].not(),
+                             &right_bits[i])?.not();
+
+        pi = Boolean::and(cs.namespace(|| format!("{}: calc pi__i: {}", namespace, i)), &t, &pi)?;
+        let d = Boolean::and(cs.namespace(|| format!("{}: calc d_i: {}", namespace, i)), &pi, &left_bits[i])?;
+        cs.enforce(|| format!("{}: constrain to 0: {}", namespace, i),
+                   |_| right_bits[i].not().lc(CS::one(), E::Fr::one()),
+                   |_| d.lc(CS::one(), E::Fr::one()),
+                   |lc| lc);
+    }
+
+    Ok(())
+}
+
+//bit = e_0 or e_1 or ..., with bit * sum_inv = sum(e_i)
+fn any_bit<E, CS>(mut cs: CS, namespace: &str, e_arr: &[Boolean]) -> Result<Boolean, SynthesisError>
+    where E: ScalarEngine, CS: ConstraintSystem<E>
+{
+    let mut sum: Num<E> = Num::zero();
+    for e in e_arr {
+        sum = sum.add_bool_with_coeff(CS::one(), e, E::Fr::one());
+    }
+
+    let bit = {
+        let b_val: Option<bool> = {
+            let calc = || {
+                for bit in e_arr {
+                    if let Some(e) = bit.get_value() {
+                        if e {
+                            return Some(true);
+                        }
+                    } else {
+                        return None;
+                    }
+                }
+
+                return Some(false);
+            };
+
+            calc()
+        };
+
+        AllocatedBit::alloc(cs.namespace(|| format!("{} allocate bit", namespace)),
+                            b_val,
+        )?
+    };
+
+    let sum_inv = AllocatedNum::alloc(cs.namespace(|| namespace.to_owned() + ": sum inv"), || {
+        if bit.get_value().ok_or(SynthesisError::AssignmentMissing)? {
+            let sum_val = sum.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            return sum_val.inverse().ok_or(SynthesisError::DivisionByZero);
+        } else {
+            return Ok(E::Fr::one());
+        }
+    })?;
+
+    sum_inv.assert_nonzero(cs.namespace(|| namespace.to_owned() + "assert sum_inv non-zero"))?;
+
+    cs.enforce(|| namespace.to_owned() + "assert b correct",
+               |_| sum.lc(E::Fr::one()),
+               |lc| lc + sum_inv.get_variable(),
+               |lc| lc + bit.get_variable(),
+    );
+
+    Ok(Boolean::Is(bit))
+}
+
+/*
+left > right
+pi: no bit so far where left is smaller, e_i: pi and left is larger at bit i
+*/
+pub fn assignment_not_leq_not_fixed<E, CS>(mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, right_bits: &Vec<Boolean>) -> Result<Boolean, SynthesisError>
+    where E: ScalarEngine, CS: ConstraintSystem<E>
+{
+    assert_eq!(left_bits.len(), right_bits.len());
+
+    let mut pi = Boolean::Constant(true);
+    let mut e_arr = vec![];
+
+    for i in (0..right_bits.len()).rev() {
+        let t = Boolean::and(cs.namespace(|| format!("{}: calc t_i: {}", namespace, i)), &left_bits[i].not(), &right_bits[i])?.not();
+        pi = Boolean::and(cs.namespace(|| format!("{}: calc pi__i: {}", namespace, i)), &t, &pi)?;
+        let d = Boolean::and(cs.namespace(|| format!("{}: calc d_i: {}", namespace, i)), &pi, &left_bits[i])?;
+        let e = Boolean::and(cs.namespace(|| format!("{}: calc e_i: {}", namespace, i)), &right_bits[i].not(), &d)?;
+
+        e_arr.push(e);
+    }
+
+    any_bit(cs, namespace, &e_arr)
+}
+
+/*
+the first num_bits of left_bits > value
+pi: left has all the set bits of value so far, e_i: pi and left has bit i but value does not
+bits of left above the highest bit of value are compared too, there value is zero
+*/
+pub fn assignment_not_leq_fixed<E, CS>(mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, value: E::Fr, num_bits: usize) -> Result<Boolean, SynthesisError>
+    where E: ScalarEngine, CS: ConstraintSystem<E>
+{
+    //no num_bits number is larger than a value that does not fit
+    let value: Vec<bool> = match fixed_value_bits(value, num_bits) {
+        Some(value) => value,
+        None => return Ok(Boolean::Constant(false)),
+    };
+
+    assert!(left_bits.len() >= num_bits);
+
+    let mut pi = Boolean::Constant(true);
+    let mut e_arr = vec![];
+
+    for i in (0..num_bits).rev() {
+        if value[i] {
+            pi = Boolean::and(cs.namespace(|| format!("{}: calc pi_i: {}", namespace, i)), &left_bits[i], &pi)?;
+        } else {
+            let e = Boolean::and(cs.namespace(|| format!("{}: calc e_i: {}", namespace, i)), &pi, &left_bits[i])?;
+            e_arr.push(e);
+        }
+    }
+
+    any_bit(cs, namespace, &e_arr)
+}
+
+#[doc(hidden)]
+pub mod testing {
+    use std::cmp::Ordering;
+
+    use bellman::ConstraintSystem;
+    use bellman::gadgets::boolean::{AllocatedBit, Boolean};
+    use bellman::gadgets::test::TestConstraintSystem;
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use super::*;
+
+    //native comparison of little endian bit vectors of the same length
+    pub fn native_cmp(left: &[bool], right: &[bool]) -> Ordering {
+        left.iter().rev().cmp(right.iter().rev())
+    }
+
+    pub fn to_fr(bits: &[bool]) -> Fr {
+        let mut value = Fr::zero();
+        for bit in bits.iter().rev() {
+            value.double();
+            if *bit {
+                value.add_assign(&Fr::one());
+            }
+        }
+
+        value
+    }
+
+    pub fn alloc_bits(cs: &mut TestConstraintSystem<Bls12>, name: &str, bits: &[bool]) -> Vec<Boolean> {
+        bits.iter().enumerate()
+            .map(|(i, b)| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("{} {}", name, i)), Some(*b)).unwrap()))
+            .collect()
+    }
+
+    //checks all four gadgets against native_cmp, right has to be smaller than the modulus
+    pub fn check(left: &[bool], right: &[bool]) {
+        let width = left.len();
+        let greater = native_cmp(left, right) == Ordering::Greater;
+        let value = to_fr(right);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let left_bits = alloc_bits(&mut cs, "left", left);
+        let right_bits = alloc_bits(&mut cs, "right", right);
+        leq_not_fixed(cs.namespace(|| "leq"), "leq", &left_bits, &right_bits).unwrap();
+        assert_eq!(cs.is_satisfied(), !greater, "leq_not_fixed width {}", width);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let left_bits = alloc_bits(&mut cs, "left", left);
+        let right_bits = alloc_bits(&mut cs, "right", right);
+        let bit = assignment_not_leq_not_fixed(cs.namespace(|| "not leq"), "not leq", &left_bits, &right_bits).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(bit.get_value(), Some(greater), "assignment_not_leq_not_fixed width {}", width);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let left_bits = alloc_bits(&mut cs, "left", left);
+        let bit = assignment_not_leq_fixed(cs.namespace(|| "not leq fixed"), "not leq fixed", &left_bits, value, width).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(bit.get_value(), Some(greater), "assignment_not_leq_fixed width {}", width);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let actual = to_fr(left);
+        let actual_var = cs.alloc(|| "actual", || Ok(actual)).unwrap();
+        let bits = left.iter().map(|b| Some(if *b { Fr::one() } else { Fr::zero() })).collect();
+        leq_fixed(cs.namespace(|| "leq fixed"), "leq fixed", bits, value, width, actual_var).unwrap();
+        assert_eq!(cs.is_satisfied(), !greater, "leq_fixed width {}", width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, thread_rng};
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use super::testing::{alloc_bits, check, native_cmp};
+
+    //random inputs from a printed seed, so a failure can be repeated
+    fn seeded_rng() -> XorShiftRng {
+        let seed: u64 = thread_rng().gen();
+        println!("seed: {}", seed);
+
+        XorShiftRng::seed_from_u64(seed)
+    }
+
+    //width 255 numbers are kept below 2^254 so that they are field elements
+    fn random_bits<R: Rng>(rng: &mut R, width: usize) -> Vec<bool> {
+        (0..width).map(|i| i < 254 && rng.gen()).collect()
+    }
+
+    fn check_width<R: Rng>(rng: &mut R, width: usize) {
+        let ones: Vec<bool> = (0..width).map(|i| i < 254).collect();
+        let zeros = vec![false; width];
+
+        for _ in 0..4 {
+            let left = random_bits(rng, width);
+            let right = random_bits(rng, width);
+            check(&left, &right);
+            check(&left, &left);
+            check(&left, &ones);
+            check(&ones, &left);
+            check(&left, &zeros);
+            check(&zeros, &left);
+
+            //same high bits, only the lowest bit differs
+            let mut close = left.clone();
+            close[0] = !close[0];
+            check(&left, &close);
+        }
+
+        check(&ones, &ones);
+        check(&zeros, &zeros);
+    }
+
+    #[test]
+    fn comparisons_match_native() {
+        let rng = &mut seeded_rng();
+
+        for width in vec![1, 11, 80, 255] {
+            check_width(rng, width);
+        }
+
+        for _ in 0..10 {
+            let width = rng.gen_range(1, 256);
+            check_width(rng, width);
+        }
+    }
+
+    #[test]
+    fn comparisons_match_u64() {
+        let rng = &mut seeded_rng();
+
+        for _ in 0..50 {
+            let (a, b): (u64, u64) = (rng.gen(), rng.gen());
+            let b = if rng.gen() { a.wrapping_add(1) } else { b };
+
+            let left: Vec<bool> = (0..64).map(|i| a >> i & 1 == 1).collect();
+            let right: Vec<bool> = (0..64).map(|i| b >> i & 1 == 1).collect();
+            assert_eq!(native_cmp(&left, &right), a.cmp(&b));
+            check(&left, &right);
+        }
+    }
+
+    #[test]
+    fn value_larger_than_width() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let left_bits = alloc_bits(&mut cs, "left", &vec![true; 80]);
+
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let bit = assignment_not_leq_fixed(cs.namespace(|| "not leq fixed"), "not leq fixed", &left_bits, minus_one, 80).unwrap();
+        assert_eq!(bit.get_value(), Some(false));
+    }
+}
@@ -0,0 +1,244 @@
+use bellman::{Circuit, Index, SynthesisError};
+use bellman::gadgets::test::TestConstraintSystem;
+use ff::Field;
+use rand::thread_rng;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::AnonStake;
+use crate::circuit::exporter::R1csExporter;
+use crate::circuit::hash::CircuitHash;
+use crate::error::Error;
+
+/*
+finds out why a witness does not give a valid proof
+bellman proves an unsatisfied witness without complaining, the proof just does not verify
+this synthesizes into TestConstraintSystem instead and names the first constraint that does not hold
+
+a wrong merkle sibling does not make the circuit unsatisfied, the circuit just computes a different anchor,
+so that shows up as a public input that does not match instead. that is why the checks take the public inputs
+the verifier expects and compare them too, like verify_proof would
+*/
+
+pub struct Unsatisfied {
+    //full namespace path of the constraint, as in TestConstraintSystem
+    pub path: String,
+    //the first levels of the path without the repeated prefixes, e.g. "coin commitment membership / merkle tree hash 3"
+    pub component: String,
+}
+
+/*
+the gadgets build the names of their namespaces from the namespace string they were given,
+so "a/a merkle tree hash 3" is shown as "a / merkle tree hash 3"
+*/
+pub fn component_name(path: &str, depth: usize) -> String {
+    let segments: Vec<&str> = path.split('/').collect();
+    let mut names = vec![];
+
+    for i in 0..segments.len().min(depth) {
+        let mut name = segments[i];
+
+        if i > 0 && name.starts_with(segments[i - 1]) && name.len() > segments[i - 1].len() {
+            name = &name[segments[i - 1].len()..];
+        }
+
+        names.push(name.trim_start_matches(|c: char| c == ':' || c.is_whitespace()).trim_end());
+    }
+
+    names.join(" / ")
+}
+
+pub struct WitnessCheck {
+    pub num_constraints: usize,
+    //None if the witness satisfies every constraint
+    pub unsatisfied: Option<Unsatisfied>,
+    //whether the public inputs of the witness are the expected ones
+    pub inputs_match: bool,
+}
+
+impl WitnessCheck {
+    //a proof made from the witness would verify against the expected public inputs
+    pub fn is_valid(&self) -> bool {
+        self.unsatisfied.is_none() && self.inputs_match
+    }
+}
+
+fn unsatisfied(path: &str) -> Unsatisfied {
+    Unsatisfied {
+        path: path.to_owned(),
+        component: component_name(path, 2),
+    }
+}
+
+//public inputs (without the leading one) the circuit computes from its witness
+pub fn public_inputs<E, C>(circuit: C) -> Result<Vec<E::Fr>, SynthesisError>
+    where E: JubjubEngine, C: Circuit<E>
+{
+    let mut cs = R1csExporter::<E>::new();
+    circuit.synthesize(&mut cs)?;
+
+    cs.inputs[1..].iter().map(|input| input.ok_or(SynthesisError::AssignmentMissing)).collect()
+}
+
+//inputs are the public inputs the verifier expects, without the leading one
+pub fn check_witness<E, C>(circuit: C, inputs: &[E::Fr]) -> Result<WitnessCheck, SynthesisError>
+    where E: JubjubEngine, C: Circuit<E>
+{
+    let mut cs = TestConstraintSystem::<E>::new();
+    circuit.synthesize(&mut cs)?;
+
+    Ok(WitnessCheck {
+        num_constraints: cs.num_constraints(),
+        unsatisfied: cs.which_is_unsatisfied().map(unsatisfied),
+        inputs_match: cs.verify(inputs),
+    })
+}
+
+/*
+same checks for a witness that was not made by init_testing, e.g. a .wtns file from export_witness or another prover
+circuit only gives the constraints, so the empty circuit of the right shape is enough
+witness has every wire in the order of R1csExporter, starting with the constant one
+*/
+pub fn check_loaded_witness<E, C>(circuit: C, witness: &[E::Fr], inputs: &[E::Fr]) -> Result<WitnessCheck, Error>
+    where E: JubjubEngine, C: Circuit<E>
+{
+    let mut cs = R1csExporter::<E>::new();
+    circuit.synthesize(&mut cs).map_err(Error::Synthesis)?;
+
+    if witness.len() != cs.num_wires() {
+        return Err(Error::Config(format!("the witness has {} wires but the circuit has {}, it was made for another circuit", witness.len(), cs.num_wires())));
+    }
+
+    let eval = |lc: &[(Index, E::Fr)]| {
+        let mut sum = E::Fr::zero();
+        for (wire, coeff) in cs.terms(lc) {
+            let mut term = witness[wire];
+            term.mul_assign(&coeff);
+            sum.add_assign(&term);
+        }
+        sum
+    };
+
+    let unsatisfied = cs.constraints.iter().find(|constraint| {
+        let mut ab = eval(&constraint.a);
+        ab.mul_assign(&eval(&constraint.b));
+        ab != eval(&constraint.c)
+    }).map(|constraint| unsatisfied(&constraint.name));
+
+    let num_inputs = cs.inputs.len();
+    Ok(WitnessCheck {
+        num_constraints: cs.constraints.len(),
+        unsatisfied,
+        inputs_match: witness[0] == E::Fr::one() && &witness[1..num_inputs] == inputs,
+    })
+}
+
+pub const CORRUPTIBLE: [&str; 5] = ["a_sk", "j_i", "sn_less_diff", "fs_tree", "cm_path"];
+
+//replaces one part of the witness with garbage, to see what check_witness reports for it
+pub fn corrupt_witness<E: JubjubEngine, H: CircuitHash<E>>(anonstake: &mut AnonStake<E, H>, part: &str) -> bool {
+    let rng = &mut thread_rng();
+    let aux = &mut anonstake.aux_input;
+
+    match part {
+        "a_sk" => aux.a_sk = Some(E::Fr::random(rng)),
+        //larger than any number of selections
+        "j_i" => aux.j_i = Some((1 << 11) - 1),
+        "sn_less_diff" => aux.sn_less_diff = Some(E::Fr::random(rng)),
+        "fs_tree" => aux.fs_main_tree[0][0][0] = Some(E::Fr::random(rng)),
+        "cm_path" => {
+            if let Some(Some(node)) = aux.cm_merkle_path.get_mut(0) {
+                node.0 = E::Fr::random(rng);
+            }
+            if let Some(Some(node)) = aux.cm_poseidon_path.get_mut(0) {
+                node.0[0] = E::Fr::random(rng);
+            }
+        }
+        _ => return false,
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use pairing::bls12_381::{Bls12, Fr};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::exporter::read_wtns;
+    use crate::circuit::hash::Poseidon;
+    use crate::constants::binomial_constants::TauValue;
+    use crate::constants::Constants;
+
+    use super::*;
+
+    #[test]
+    fn component_names() {
+        assert_eq!(component_name("coin commitment membership/coin commitment membershipmerkle tree hash 3/coin commitment membershipmerkle tree hash 3: 5/x", 2),
+                   "coin commitment membership / merkle tree hash 3");
+        assert_eq!(component_name("serial number/compare sn sn_less/bit 3", 2), "serial number / compare sn sn_less");
+        assert_eq!(component_name("calc a_pk", 2), "calc a_pk");
+    }
+
+    #[test]
+    fn reports_unselected_j_i() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+        let inputs = public_inputs::<Bls12, _>(anonstake.clone()).unwrap();
+        assert!(check_witness::<Bls12, _>(anonstake.clone(), &inputs).unwrap().is_valid());
+
+        let mut anonstake = anonstake;
+        assert!(corrupt_witness(&mut anonstake, "j_i"));
+        let check = check_witness::<Bls12, _>(anonstake, &inputs).unwrap();
+        assert!(check.unsatisfied.unwrap().component.starts_with("enforce j_i selected"));
+    }
+
+    #[test]
+    fn reports_wrong_anchor() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+        let inputs = public_inputs::<Bls12, _>(anonstake.clone()).unwrap();
+
+        //the circuit is still satisfied, only the anchor it computes differs
+        let mut anonstake = anonstake;
+        assert!(corrupt_witness(&mut anonstake, "cm_path"));
+        let check = check_witness::<Bls12, _>(anonstake, &inputs).unwrap();
+        assert!(check.unsatisfied.is_none());
+        assert!(!check.inputs_match);
+        assert!(!check.is_valid());
+    }
+
+    #[test]
+    fn loaded_witness() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+        let inputs = public_inputs::<Bls12, _>(anonstake.clone()).unwrap();
+
+        let mut cs = R1csExporter::<Bls12>::new();
+        anonstake.synthesize(&mut cs).unwrap();
+        let mut bytes = vec![];
+        cs.write_wtns(&mut bytes).unwrap();
+        let mut witness = read_wtns::<Fr, _>(&bytes[..]).unwrap();
+
+        let empty = || AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 10);
+        let check = check_loaded_witness::<Bls12, _>(empty(), &witness, &inputs).unwrap();
+        assert_eq!(check.num_constraints, cs.constraints.len());
+        assert!(check.is_valid());
+
+        let mut other_inputs = inputs.clone();
+        other_inputs[0].add_assign(&Fr::one());
+        assert!(!check_loaded_witness::<Bls12, _>(empty(), &witness, &other_inputs).unwrap().inputs_match);
+
+        //breaks the constraint that copies the first public input from its aux variable
+        witness[1].add_assign(&Fr::one());
+        assert!(check_loaded_witness::<Bls12, _>(empty(), &witness, &inputs).unwrap().unsatisfied.is_some());
+
+        witness.pop();
+        assert!(check_loaded_witness::<Bls12, _>(empty(), &witness, &inputs).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+
+/*
+constraint system that records the whole r1cs and the assignment so it can be written out for other tools
+
+binary formats are the ones of circom (iden3/r1csfile and iden3/wtnsfile), all integers little endian:
+.r1cs: "r1cs", version 1, 3 sections
+    section 1 (header): n8 = 32, prime (n8 bytes), number of wires, public outputs (0), public inputs, private inputs (0),
+                        number of labels (u64), number of constraints
+    section 2 (constraints): for each of a, b and c: number of terms, then (wire u32, coefficient n8 bytes) per term
+    section 3 (wire to label): label u64 per wire, here the label is the wire itself
+.wtns: "wtns", version 2, 2 sections
+    section 1 (header): n8, prime, number of wires
+    section 2 (values): n8 bytes per wire
+every section starts with its type (u32) and its size in bytes (u64), field elements are in normal (not montgomery) form
+
+wire 0 is the constant one, wires 1 .. inputs are the public inputs of the circuit in the order they were inputized,
+and the aux variables come after that. bellman does not know which aux variables are private inputs, so all of
+them are written as internal wires
+the json versions have the same wires, with the namespace of every constraint to make them easier to read
+*/
+
+pub struct ExportedConstraint<E: ScalarEngine> {
+    pub name: String,
+    pub a: Vec<(Index, E::Fr)>,
+    pub b: Vec<(Index, E::Fr)>,
+    pub c: Vec<(Index, E::Fr)>,
+}
+
+pub struct R1csExporter<E: ScalarEngine> {
+    //inputs[0] is the constant one
+    pub inputs: Vec<Option<E::Fr>>,
+    pub aux: Vec<Option<E::Fr>>,
+    pub constraints: Vec<ExportedConstraint<E>>,
+    stack: Vec<String>,
+}
+
+fn write_fr<F: PrimeField, W: Write>(writer: &mut W, value: &F) -> io::Result<()> {
+    value.into_repr().write_le(writer)
+}
+
+fn write_section<W: Write>(writer: &mut W, section_type: u32, content: &[u8]) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(section_type)?;
+    writer.write_u64::<LittleEndian>(content.len() as u64)?;
+    writer.write_all(content)
+}
+
+//bytes per field element
+fn n8<F: PrimeField>() -> u32 {
+    (F::char().as_ref().len() * 8) as u32
+}
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/*
+reads the wire values of a .wtns file, as written by write_wtns or by circom
+sections can come in any order, sections other than the header and the values are skipped
+*/
+pub fn read_wtns<F: PrimeField, R: Read>(mut reader: R) -> io::Result<Vec<F>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"wtns" {
+        return Err(invalid_data("not a wtns file".to_owned()));
+    }
+
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version != 2 {
+        return Err(invalid_data(format!("unsupported wtns version {}", version)));
+    }
+
+    let num_sections = reader.read_u32::<LittleEndian>()?;
+    let mut num_wires = None;
+    let mut values = None;
+
+    for _ in 0..num_sections {
+        let section_type = reader.read_u32::<LittleEndian>()?;
+        let size = reader.read_u64::<LittleEndian>()?;
+        let mut content = vec![0u8; size as usize];
+        reader.read_exact(&mut content)?;
+        let mut content = &content[..];
+
+        match section_type {
+            1 => {
+                if content.read_u32::<LittleEndian>()? != n8::<F>() {
+                    return Err(invalid_data("field element size does not match the scalar field".to_owned()));
+                }
+                let mut prime = F::Repr::default();
+                prime.read_le(&mut content)?;
+                if prime != F::char() {
+                    return Err(invalid_data("prime does not match the scalar field".to_owned()));
+                }
+                num_wires = Some(content.read_u32::<LittleEndian>()? as usize);
+            }
+            2 => values = Some(content.to_vec()),
+            _ => {}
+        }
+    }
+
+    let num_wires = num_wires.ok_or(invalid_data("wtns file has no header section".to_owned()))?;
+    let values = values.ok_or(invalid_data("wtns file has no values section".to_owned()))?;
+    if values.len() != num_wires * n8::<F>() as usize {
+        return Err(invalid_data(format!("wtns file has {} bytes of values for {} wires", values.len(), num_wires)));
+    }
+
+    let mut values = &values[..];
+    (0..num_wires).map(|wire| {
+        let mut repr = F::Repr::default();
+        repr.read_le(&mut values)?;
+        F::from_repr(repr).map_err(|_| invalid_data(format!("value of wire {} is not a field element", wire)))
+    }).collect()
+}
+
+impl<E: ScalarEngine> R1csExporter<E> {
+    pub fn new() -> R1csExporter<E> {
+        R1csExporter {
+            inputs: vec![Some(E::Fr::one())],
+            aux: vec![],
+            constraints: vec![],
+            stack: vec![],
+        }
+    }
+
+    pub fn num_wires(&self) -> usize {
+        self.inputs.len() + self.aux.len()
+    }
+
+    pub fn wire(&self, index: Index) -> usize {
+        match index {
+            Index::Input(i) => i,
+            Index::Aux(i) => self.inputs.len() + i,
+        }
+    }
+
+    //merges repeated variables and drops zero coefficients, sorted by wire
+    pub fn terms(&self, lc: &[(Index, E::Fr)]) -> Vec<(usize, E::Fr)> {
+        let mut terms: BTreeMap<usize, E::Fr> = BTreeMap::new();
+
+        for (index, coeff) in lc {
+            terms.entry(self.wire(*index)).or_insert(E::Fr::zero()).add_assign(coeff);
+        }
+
+        terms.into_iter().filter(|(_, coeff)| !coeff.is_zero()).collect()
+    }
+
+    //all wire values, fails if the circuit was synthesized without a witness
+    pub fn witness(&self) -> io::Result<Vec<E::Fr>> {
+        self.inputs.iter().chain(self.aux.iter())
+            .enumerate()
+            .map(|(wire, value)| value.ok_or(invalid_data(format!("no assignment for wire {}", wire))))
+            .collect()
+    }
+
+    pub fn write_r1cs<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut header = vec![];
+        header.write_u32::<LittleEndian>(n8::<E::Fr>())?;
+        E::Fr::char().write_le(&mut header)?;
+        header.write_u32::<LittleEndian>(self.num_wires() as u32)?;
+        header.write_u32::<LittleEndian>(0)?;
+        header.write_u32::<LittleEndian>((self.inputs.len() - 1) as u32)?;
+        header.write_u32::<LittleEndian>(0)?;
+        header.write_u64::<LittleEndian>(self.num_wires() as u64)?;
+        header.write_u32::<LittleEndian>(self.constraints.len() as u32)?;
+
+        let mut constraints = vec![];
+        for constraint in &self.constraints {
+            for lc in [&constraint.a, &constraint.b, &constraint.c].iter() {
+                let terms = self.terms(lc);
+                constraints.write_u32::<LittleEndian>(terms.len() as u32)?;
+
+                for (wire, coeff) in terms {
+                    constraints.write_u32::<LittleEndian>(wire as u32)?;
+                    write_fr(&mut constraints, &coeff)?;
+                }
+            }
+        }
+
+        let mut labels = vec![];
+        for wire in 0..self.num_wires() {
+            labels.write_u64::<LittleEndian>(wire as u64)?;
+        }
+
+        writer.write_all(b"r1cs")?;
+        writer.write_u32::<LittleEndian>(1)?;
+        writer.write_u32::<LittleEndian>(3)?;
+        write_section(&mut writer, 1, &header)?;
+        write_section(&mut writer, 2, &constraints)?;
+        write_section(&mut writer, 3, &labels)
+    }
+
+    pub fn write_wtns<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let witness = self.witness()?;
+
+        let mut header = vec![];
+        header.write_u32::<LittleEndian>(n8::<E::Fr>())?;
+        E::Fr::char().write_le(&mut header)?;
+        header.write_u32::<LittleEndian>(witness.len() as u32)?;
+
+        let mut values = vec![];
+        for value in &witness {
+            write_fr(&mut values, value)?;
+        }
+
+        writer.write_all(b"wtns")?;
+        writer.write_u32::<LittleEndian>(2)?;
+        writer.write_u32::<LittleEndian>(2)?;
+        write_section(&mut writer, 1, &header)?;
+        write_section(&mut writer, 2, &values)
+    }
+
+    pub fn write_r1cs_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(format!("{{\n\"prime\": \"{}\",\n\"n_wires\": {},\n\"n_pub_inputs\": {},\n\"constraints\": [\n",
+                                 E::Fr::char(), self.num_wires(), self.inputs.len() - 1).as_ref())?;
+
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            let lcs: Vec<String> = [&constraint.a, &constraint.b, &constraint.c].iter()
+                .map(|lc| {
+                    let terms: Vec<String> = self.terms(lc).iter()
+                        .map(|(wire, coeff)| format!("\"{}\": \"{}\"", wire, coeff.into_repr()))
+                        .collect();
+                    format!("{{{}}}", terms.join(", "))
+                })
+                .collect();
+
+            let separator = if i + 1 == self.constraints.len() { "" } else { "," };
+            writer.write_all(format!("{{\"name\": \"{}\", \"a\": {}, \"b\": {}, \"c\": {}}}{}\n",
+                                     json_escape(&constraint.name), lcs[0], lcs[1], lcs[2], separator).as_ref())?;
+        }
+
+        writer.write_all(b"]\n}\n")
+    }
+
+    pub fn write_wtns_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let values: Vec<String> = self.witness()?.iter()
+            .map(|value| format!("\"{}\"", value.into_repr()))
+            .collect();
+
+        writer.write_all(format!("[\n{}\n]\n", values.join(",\n")).as_ref())
+    }
+
+    fn path(&self, name: String) -> String {
+        let mut path = self.stack.clone();
+        path.push(name);
+        path.join("/")
+    }
+}
+
+impl<E: ScalarEngine> ConstraintSystem<E> for R1csExporter<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let index = self.aux.len();
+        self.aux.push(f().ok());
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let index = self.inputs.len();
+        self.inputs.push(f().ok());
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+              LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+              LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+              LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>
+    {
+        let terms = |lc: LinearCombination<E>| -> Vec<(Index, E::Fr)> {
+            lc.as_ref().iter().map(|(var, coeff)| (var.get_unchecked(), *coeff)).collect()
+        };
+
+        let name = self.path(annotation().into());
+
+        self.constraints.push(ExportedConstraint {
+            name,
+            a: terms(a(LinearCombination::zero())),
+            b: terms(b(LinearCombination::zero())),
+            c: terms(c(LinearCombination::zero())),
+        });
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+        where NR: Into<String>, N: FnOnce() -> NR
+    {
+        self.stack.push(name_fn().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        self.stack.pop();
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::num::AllocatedNum;
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use crate::circuit::public_inputs::u64_to_fr;
+
+    use super::*;
+
+    fn small_circuit() -> R1csExporter<Bls12> {
+        let mut cs = R1csExporter::<Bls12>::new();
+
+        let x = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(u64_to_fr::<Fr>(3))).unwrap();
+        let y = x.square(cs.namespace(|| "square")).unwrap();
+        y.inputize(cs.namespace(|| "inputize y")).unwrap();
+
+        cs
+    }
+
+    #[test]
+    fn r1cs_layout() {
+        let cs = small_circuit();
+        assert_eq!(cs.num_wires(), 4);
+        assert_eq!(cs.constraints.len(), 2);
+        assert_eq!(cs.constraints[0].name, "square/squaring constraint");
+
+        let mut bytes = vec![];
+        cs.write_r1cs(&mut bytes).unwrap();
+
+        let mut reader = &bytes[..];
+        let mut magic = [0u8; 4];
+        std::io::Read::read_exact(&mut reader, &mut magic).unwrap();
+        assert_eq!(&magic, b"r1cs");
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 1);
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 3);
+
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 1);
+        assert_eq!(reader.read_u64::<LittleEndian>().unwrap(), 4 + 32 + 4 * 4 + 8 + 4);
+        assert_eq!(reader.read_u32::<LittleEndian>().unwrap(), 32);
+
+        //header + constraints (x * x = y and input * 1 = y, one term of 4 + 4 + 32 bytes per a, b, c) + labels
+        assert_eq!(bytes.len(), 12 + (12 + 64) + (12 + 6 * 40) + (12 + 4 * 8));
+    }
+
+    #[test]
+    fn witness_layout() {
+        let cs = small_circuit();
+        assert_eq!(cs.witness().unwrap(), vec![Fr::one(), u64_to_fr(9), u64_to_fr(3), u64_to_fr(9)]);
+
+        let mut bytes = vec![];
+        cs.write_wtns(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 12 + (12 + 40) + (12 + 4 * 32));
+
+        assert_eq!(read_wtns::<Fr, _>(&bytes[..]).unwrap(), cs.witness().unwrap());
+        assert!(read_wtns::<Fr, _>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut json = vec![];
+        cs.write_r1cs_json(&mut json).unwrap();
+        assert!(String::from_utf8(json).unwrap().contains("\"name\": \"inputize y/enforce input is correct\""));
+    }
+}
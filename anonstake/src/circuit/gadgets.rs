@@ -1,6 +1,6 @@
 use std::io::Cursor;
 
-use bellman::{ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use bellman::{ConstraintSystem, LinearCombination, SynthesisError};
 use bellman::gadgets::{Assignment, boolean, num};
 use bellman::gadgets::boolean::{AllocatedBit, Boolean};
 use bellman::gadgets::num::{AllocatedNum, Num};
@@ -12,7 +12,62 @@ use zcash_proofs::circuit::ecc;
 use zcash_proofs::circuit::ecc::{EdwardsPoint, fixed_base_multiplication};
 use zcash_proofs::circuit::pedersen_hash::pedersen_hash;
 
-impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
+use super::comparison;
+use super::hash::{CircuitHash, HashInput};
+use crate::constants::{FULL_PK_DELEGATED_TAG, FULL_PK_TAG, FULL_PK_TAG_BITS, PrfDomain, PrfKind};
+use crate::poseidon::{sponge_blocks, sponge_tag};
+
+//allocates the value of a linear combination, the one constraint enforces that it is equal to it
+pub fn alloc_num<E, CS>(mut cs: CS, num: &Num<E>) -> Result<AllocatedNum<E>, SynthesisError>
+    where E: JubjubEngine, CS: ConstraintSystem<E>
+{
+    let allocated_num = AllocatedNum::alloc(cs.namespace(|| "allocate"), || num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+
+    cs.enforce(|| "value = allocated num",
+               |_| num.lc(E::Fr::one()),
+               |lc| lc + CS::one(),
+               |lc| lc + allocated_num.get_variable());
+
+    Ok(allocated_num)
+}
+
+/*
+the 36 bits of the time a forward secure key is used at, time is a witness of the prover
+time + fs_start = role, so the key of a later period can not sign for this role, and fs_start <= role
+*/
+pub fn fs_time_bits<E, CS>(mut cs: CS, namespace: &str, fs_start_bits: &Vec<Boolean>, role_bits: &Vec<Boolean>, time: Option<u64>) -> Result<Vec<Boolean>, SynthesisError>
+    where E: JubjubEngine, CS: ConstraintSystem<E> {
+    comparison::leq_not_fixed(cs.namespace(|| format!("{} fs_start less than role", namespace)),
+                              &format!("{} fs_start less than role", namespace),
+                              fs_start_bits, role_bits)?;
+
+    let mut time_bits = boolean::u64_into_boolean_vec_le(
+        cs.namespace(|| format!("{} get time diff bits", namespace)), time)?;
+    time_bits.truncate(36);
+
+    let mut time_num = Num::<E>::zero();
+    let mut role_num = Num::<E>::zero();
+    let mut fs_start_num = Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+
+    for i in 0..64 {
+        if i < time_bits.len() {
+            time_num = time_num.add_bool_with_coeff(CS::one(), &time_bits[i], coeff);
+        }
+        role_num = role_num.add_bool_with_coeff(CS::one(), &role_bits[i], coeff);
+        fs_start_num = fs_start_num.add_bool_with_coeff(CS::one(), &fs_start_bits[i], coeff);
+        coeff.double();
+    }
+
+    cs.enforce(|| format!("{} enforce time valid", namespace),
+               |_| time_num.lc(E::Fr::one()) + &fs_start_num.lc(E::Fr::one()),
+               |lc| lc + CS::one(),
+               |_| role_num.lc(E::Fr::one()));
+
+    Ok(time_bits)
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> super::AnonStake<'a, E, H> {
     pub fn poseidon_sbox<CS>(&self, mut cs: CS, namespace: &str, num: &Num<E>) -> Result<AllocatedNum<E>, SynthesisError>
         where CS: ConstraintSystem<E>
     {
@@ -82,77 +137,62 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
         Ok(output)
     }
 
-    pub fn poseidon<CS>(&self, mut cs: CS, namespace: &str, input: [Num<E>; 8]) -> Result<Num<E>, SynthesisError>
+    pub fn poseidon_permutation<CS>(&self, mut cs: CS, namespace: &str, mut state: [Num<E>; 9]) -> Result<[Num<E>; 9], SynthesisError>
         where CS: ConstraintSystem<E>
     {
-        let mut state: [Num<E>; 9] = [Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()];
-        for i in 0..8 {
-            state[i] = input[i].clone();
-        }
-
         for round in 0..(self.constants.poseidon.r_f + self.constants.poseidon.r_p) {
             let namespace = format!("{}: {}", namespace, round);
             state = self.poseidon_round(cs.namespace(|| namespace.clone()), namespace.as_str(), state, round)?;
         }
 
-        Ok(state[0].clone())
+        Ok(state)
     }
 
-    pub fn crh_poseidon_or_pedersen_elem_with_zero<CS>(&self, mut cs: CS, namespace: &str, num: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
-        where CS: ConstraintSystem<E> {
-        if self.use_poseidon {
-            let num: Num<E> = num.into();
-            let result = self.poseidon(
-                cs.namespace(|| format!("{} poseidon", namespace)),
-                &format!("{} poseidon", namespace),
-                [num, Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()],
-            )?;
+    //same as poseidon::poseidon_sponge_with_tag, the first block keeps the old namespace of the fixed length hash
+    pub fn poseidon_sponge_with_tag<CS>(&self, mut cs: CS, namespace: &str, tag: E::Fr, input: &[Num<E>]) -> Result<Num<E>, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let capacity = if tag.is_zero() {
+            Num::zero()
+        } else {
+            Num::zero().add_bool_with_coeff(CS::one(), &Boolean::constant(true), tag)
+        };
 
-            let allocated_num = AllocatedNum::alloc(
-                cs.namespace(|| format!("{} allocate result", namespace)),
-                || result.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+        let mut state: [Num<E>; 9] = [Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), capacity];
 
-            cs.enforce(|| format!("{} ensure poseidon hash value = allocated num", namespace),
-                       |_| result.lc(E::Fr::one()),
-                       |lc| lc + CS::one(),
-                       |lc| lc + allocated_num.get_variable());
+        for block in 0..sponge_blocks(input.len()) {
+            for (i, elem) in input.iter().skip(8 * block).take(8).enumerate() {
+                state[i] = state[i].clone() + elem.clone();
+            }
 
-            return Ok(allocated_num);
-        } else {
-            let bits = num.to_bits_le(
-                cs.namespace(|| format!("{} to bits", namespace)))?;
-            return self.crh(cs, namespace, bits.as_slice());
+            let namespace = if block == 0 { namespace.to_owned() } else { format!("{} block {}", namespace, block) };
+            state = self.poseidon_permutation(cs.namespace(|| namespace.clone()), namespace.as_str(), state)?;
         }
+
+        Ok(state[0].clone())
     }
 
-    pub fn crh_poseidon_or_pedersen_elems<CS>(&self, mut cs: CS, namespace: &str, num1: AllocatedNum<E>, num2: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
-        where CS: ConstraintSystem<E> {
-        if self.use_poseidon {
-            let num1: Num<E> = num1.into();
-            let num2: Num<E> = num2.into();
-            let result = self.poseidon(
-                cs.namespace(|| format!("{} poseidon", namespace)),
-                &format!("{} poseidon", namespace),
-                [num1, num2, Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()],
-            )?;
+    pub fn poseidon_sponge<CS>(&self, cs: CS, namespace: &str, domain: u32, input: &[Num<E>]) -> Result<Num<E>, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        self.poseidon_sponge_with_tag(cs, namespace, sponge_tag::<E::Fr>(domain, input.len()), input)
+    }
 
-            let allocated_num = AllocatedNum::alloc(
-                cs.namespace(|| format!("{} allocate result", namespace)),
-                || result.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+    //fixed length special case of the sponge
+    pub fn poseidon<CS>(&self, cs: CS, namespace: &str, input: [Num<E>; 8]) -> Result<Num<E>, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        self.poseidon_sponge_with_tag(cs, namespace, E::Fr::zero(), &input)
+    }
 
-            cs.enforce(|| format!("{} ensure poseidon hash value = allocated num", namespace),
-                       |_| result.lc(E::Fr::one()),
-                       |lc| lc + CS::one(),
-                       |lc| lc + allocated_num.get_variable());
+    pub fn hash_elem<CS>(&self, cs: CS, namespace: &str, num: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        H::hash(self, cs, namespace, vec![HashInput::Num(num)])
+    }
 
-            return Ok(allocated_num);
-        } else {
-            let mut bits = num1.to_bits_le(
-                cs.namespace(|| format!("{} to bits", namespace)))?;
-            bits.extend(num2.to_bits_le(
-                cs.namespace(|| format!("{} to bits 2", namespace)))?);
-            return self.crh(cs, namespace, bits.as_slice());
-        }
+    pub fn hash_elems<CS>(&self, cs: CS, namespace: &str, num1: AllocatedNum<E>, num2: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        H::hash(self, cs, namespace, vec![HashInput::Num(num1), HashInput::Num(num2)])
     }
 
     pub fn get_role_bits<CS>(&self, mut cs: CS, namespace: &str) -> Result<(AllocatedNum<E>, Vec<Boolean>), SynthesisError>
@@ -252,16 +292,25 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
         Ok((cm, value_num, value_bits))
     }
 
-    pub fn constrain_full_pk<CS>(&self, mut cs: CS, namespace: &str, fs_start_bits: Vec<Boolean>, fs_pk: AllocatedNum<E>, a_pk: AllocatedNum<E>) -> Result<(AllocatedNum<E>), SynthesisError>
+    //delegate_pk is the a_pk of the delegate for delegated coins, which are hashed with a different tag
+    pub fn constrain_full_pk<CS>(&self, mut cs: CS, namespace: &str, fs_start_bits: Vec<Boolean>, fs_pk: AllocatedNum<E>, a_pk: AllocatedNum<E>, delegate_pk: Option<AllocatedNum<E>>) -> Result<(AllocatedNum<E>), SynthesisError>
         where CS: ConstraintSystem<E> {
         let fs_pk_bits = fs_pk.to_bits_le(cs.namespace(|| "fs_pk_bits"))?;
 
         let a_pk_bits = a_pk.to_bits_le(cs.namespace(|| "a_pk_bits"))?;
 
-        let mut bits = fs_start_bits;
+        let tag = if delegate_pk.is_some() { FULL_PK_DELEGATED_TAG } else { FULL_PK_TAG };
+        let mut bits: Vec<Boolean> = (0..FULL_PK_TAG_BITS).map(|i| Boolean::constant((tag >> i) & 1 == 1)).collect();
+
+        bits.extend(fs_start_bits);
         bits.extend(fs_pk_bits);
         bits.extend(a_pk_bits);
 
+        if let Some(delegate_pk) = delegate_pk {
+            let delegate_pk_bits = delegate_pk.to_bits_le(cs.namespace(|| "delegate_pk_bits"))?;
+            bits.extend(delegate_pk_bits);
+        }
+
         let hash_point: EdwardsPoint<E> = pedersen_hash(
             cs.namespace(|| namespace.to_owned() + "compute hash"),
             Personalization::NoteCommitment,
@@ -475,11 +524,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
     pub fn coin_commitment_membership<CS>(&self, cs: CS, namespace: &str, cm: EdwardsPoint<E>) -> Result<(), SynthesisError>
         where CS: ConstraintSystem<E>
     {
-        if self.use_poseidon {
-            self.coin_commitment_membership2(cs, namespace, cm)
-        } else {
-            self.coin_commitment_membership1(cs, namespace, cm)
-        }
+        H::coin_commitment_membership(self, cs, namespace, cm)
     }
 
     //modified from above
@@ -675,45 +720,13 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
     pub fn serial_number_nonmembership<CS>(&self, cs: CS, namespace: &str, sn_box: AllocatedNum<E>) -> Result<(), SynthesisError>
         where CS: ConstraintSystem<E>
     {
-        if self.use_poseidon {
-            self.serial_number_nonmembership2(cs, namespace, sn_box)
-        } else {
-            self.serial_number_nonmembership1(cs, namespace, sn_box)
-        }
+        H::serial_number_nonmembership(self, cs, namespace, sn_box)
     }
 
 
-    pub fn forward_secure_tree_main<CS>(&self, mut cs: CS, namespace: &str, time: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
+    //time_bits are the 36 bits of role - fs_tree_start, see fs_time_window
+    pub fn forward_secure_tree_main<CS>(&self, mut cs: CS, namespace: &str, time_bits: &[Boolean]) -> Result<AllocatedNum<E>, SynthesisError>
         where CS: ConstraintSystem<E>, E::Fs: ToUniform {
-        let time_bits: Vec<Boolean> = {
-            let tmp = if let (Some(a), Some(b)) = (self.pub_input.role, self.aux_input.fs_tree_start) {
-                Some(a - b)
-            } else {
-                None
-            };
-
-            let mut bits = boolean::u64_into_boolean_vec_le(
-                cs.namespace(|| format!("{} get time diff bits", namespace)), tmp)?;
-            bits.truncate(36);
-
-            let time_bits = bits;
-
-            let mut time_num = num::Num::<E>::zero();
-
-            let mut coeff = E::Fr::one();
-            for bit in &time_bits {
-                time_num = time_num.add_bool_with_coeff(CS::one(), bit, coeff);
-                coeff.double();
-            }
-
-            cs.enforce(|| format!("{} enforce time valid", namespace),
-                       |_| time_num.lc(E::Fr::one()),
-                       |lc| lc + CS::one(),
-                       |lc| lc + time.get_variable());
-
-            time_bits
-        };
-
         let mut final_pks: Vec<AllocatedNum<E>> = vec![];
 
         //TODO: refactor this code
@@ -721,26 +734,13 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
             let bits = &time_bits[9 * i..9 * (i + 1)];
 
             let tree_pk = if i == 0 {
-                let sk: Option<E::Fr> = {
-                    let s = self.aux_input.fs_sk[i].as_ref();
-                    if let Some(s) = s {
-                        let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-                        s.into_repr().write_le(cursor.get_mut()).unwrap();
-                        let mut read_elem = E::Fr::zero().into_repr();
-
-                        //TODO: avoid calling unwrap
-                        read_elem.read_le(cursor).unwrap();
-                        Some(E::Fr::from_repr(read_elem).unwrap())
-                    } else {
-                        None
-                    }
-                };
+                let sk: Option<E::Fr> = self.aux_input.fs_sk[i].as_ref().map(fs_to_fr::<E>).transpose()?;
 
                 let sk = AllocatedNum::alloc(
                     cs.namespace(|| format!("{} allocate secret key {}", namespace, i)),
                     || sk.ok_or(SynthesisError::AssignmentMissing))?;
 
-                let pk = self.crh_poseidon_or_pedersen_elem_with_zero(
+                let pk = self.hash_elem(
                     cs.namespace(|| format!("{} calculate public key {}", namespace, i)),
                     &format!("{} calculate public key {}", namespace, i),
                     sk)?;
@@ -782,7 +782,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                     r
                 };
 
-                let e = self.crh_poseidon_or_pedersen_elems(
+                let e = self.hash_elems(
                     cs.namespace(|| format!("{} main check {}", namespace, i)),
                     &format!("{} main check {}", namespace, i),
                     r.get_x().clone(), final_pks[i - 1].clone())?;
@@ -795,7 +795,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                         if let (Some(k), Some(mut x), Some(e)) =
                         (k, x, e.get_value()) {
                             let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-                            e.into_repr().write_le(cursor.get_mut()).unwrap();
+                            e.into_repr().write_le(cursor.get_mut())?;
 
                             let mut vec8 = cursor.into_inner();
                             while vec8.len() < 64 {
@@ -808,13 +808,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                             x.mul_assign(&e);
                             k.sub_assign(&x);
 
-                            let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-                            k.into_repr().write_le(cursor.get_mut()).unwrap();
-                            let mut read_elem = E::Fr::zero().into_repr();
-
-                            //TODO: avoid calling unwrap
-                            read_elem.read_le(cursor).unwrap();
-                            Ok(E::Fr::from_repr(read_elem).unwrap())
+                            fs_to_fr::<E>(&k)
                         } else {
                             Err(SynthesisError::AssignmentMissing)
                         }
@@ -919,68 +913,11 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                                |lc| lc + cur_value.get_variable());
                 }
 
-                if self.use_poseidon {
-                    let t: [Num<E>; 8] = [cur_path[0].clone().into(), cur_path[1].clone().into(), cur_path[2].clone().into(), cur_path[3].clone().into(), cur_path[4].clone().into(), cur_path[5].clone().into(), cur_path[6].clone().into(), cur_path[7].clone().into()];
-
-                    let result = self.poseidon(
-                        cs.namespace(|| format!("{} tree hash {} {}", namespace, i, j)),
-                        &format!("{} tree hash {} {}", namespace, i, j),
-                        t)?;
-
-                    cur_value = AllocatedNum::alloc(
-                        cs.namespace(|| format!("{} allocate result {} {}", namespace, i, j)),
-                        || result.get_value().ok_or(SynthesisError::AssignmentMissing))?;
-
-                    cs.enforce(|| format!("{} ensure poseidon hash value = allocated num {} {}", namespace, i, j),
-                               |_| result.lc(E::Fr::one()),
-                               |lc| lc + CS::one(),
-                               |lc| lc + cur_value.get_variable());
-                } else {
-                    //really should use for loops...
-
-                    let t0 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 0)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 0),
-                        cur_path[0].clone(),
-                        cur_path[1].clone(),
-                    )?;
-
-                    let t1 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 1)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 1),
-                        cur_path[2].clone(),
-                        cur_path[3].clone(),
-                    )?;
-
-                    let t2 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 2)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 2),
-                        cur_path[4].clone(),
-                        cur_path[5].clone(),
-                    )?;
-
-                    let t3 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 3)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 3),
-                        cur_path[6].clone(),
-                        cur_path[7].clone(),
-                    )?;
-
-                    let t4 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 4)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 4),
-                        t0, t1)?;
-
-                    let t5 = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 5)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 5),
-                        t2, t3)?;
-
-                    cur_value = self.crh_poseidon_or_pedersen_elems(
-                        cs.namespace(|| format!("{} tree hash {} {} {}", namespace, i, j, 6)),
-                        &format!("{} tree hash {} {} {}", namespace, i, j, 6),
-                        t4, t5)?;
-                }
+                cur_value = H::hash_fs_node(
+                    self,
+                    cs.namespace(|| format!("{} tree hash {} {}", namespace, i, j)),
+                    &format!("{} tree hash {} {}", namespace, i, j),
+                    &cur_path)?;
 
                 final_pks.push(cur_value.clone());
             }
@@ -1001,37 +938,33 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
             self.aux_input.fs_tree_start,
         )?;
 
-        self.leq_not_fixed(cs.namespace(|| format!("{} fs_start less than current role", namespace)),
-                           &format!("{} fs_start less than current role", namespace),
-                           &fs_start_bits, &role_bits)?;
-
-        let time_diff = {
-            let mut num = Num::<E>::zero();
-            let mut coeff = E::Fr::one();
-
-            for bit in &fs_start_bits {
-                num = num.add_bool_with_coeff(CS::one(), bit, coeff);
-                coeff.double();
-            }
-
-            AllocatedNum::alloc(cs.namespace(|| format!("{} get time diff", namespace)),
-                                || {
-                                    let mut value = role.get_value().ok_or(SynthesisError::AssignmentMissing)?;
-                                    value.sub_assign(&num.get_value().ok_or(SynthesisError::AssignmentMissing)?);
-                                    Ok(value)
-                                })?
-        };
+        let time_bits = self.fs_time_window(
+            cs.namespace(|| format!("{} time window", namespace)),
+            &format!("{} time window", namespace),
+            &fs_start_bits, &role_bits)?;
 
         let fs_pk = self.forward_secure_tree_main(
             cs.namespace(|| format!("{} main forward secure tree", namespace)),
             &format!("{} main forward secure tree", namespace),
-            time_diff.clone())?;
-//        let fs_pk = time_diff.clone();
-
+            &time_bits)?;
 
         Ok((role, role_bits, fs_start_bits, fs_pk))
     }
 
+    //the time of the forward secure tree for the role of self, fs_start <= role < fs_start + 2^36
+    //also used by circuits that use one forward secure key for several roles
+    pub fn fs_time_window<CS>(&self, cs: CS, namespace: &str, fs_start_bits: &Vec<Boolean>, role_bits: &Vec<Boolean>) -> Result<Vec<Boolean>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        //wrapping so that a witness with fs_tree_start > role gives an unsatisfied circuit instead of a panic
+        let time = if let (Some(a), Some(b)) = (self.pub_input.role, self.aux_input.fs_tree_start) {
+            Some(a.wrapping_sub(b))
+        } else {
+            None
+        };
+
+        fs_time_bits(cs, namespace, fs_start_bits, role_bits, time)
+    }
+
 
     //input: already allocated
 //sk: already allocated
@@ -1198,285 +1131,28 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
         }
     }
 
-    pub fn leq_fixed<CS>(&self, mut cs: CS, namespace: &str, mut bits: Vec<Option<E::Fr>>, value: E::Fr, mut num_bits: usize, actual_value_var: Variable) -> Result<Vec<Variable>, SynthesisError>
-        where CS: ConstraintSystem<E>
-    {
-        let value: Vec<bool> = {
-            let mut res = vec![];
-            let mut value = value.into_repr();
-
-            for _i in 0..num_bits {
-                res.push(value.is_odd());
-                value.div2();
-            }
-
-            res
-        };
-
-        while num_bits > 0 && !value[num_bits - 1] {
-            num_bits -= 1;
-        }
-
-        if num_bits == 0 {
-            return Err(SynthesisError::Unsatisfiable);
-        }
-
-        let num_bits = num_bits;
-
-        assert!(bits.len() >= num_bits);
-        bits.truncate(num_bits);
-
-
-        let mut prev_pi_val = bits[num_bits - 1];
-        let mut prev_pi_var: Option<Variable> = None;
-
-        let mut res_bits = vec![];
-
-        for i in (0..num_bits).rev() {
-            let new_bit = cs.alloc(|| format!("{}: leq_fixed allocate bit: {}", namespace, i),
-                                   || bits[i].ok_or(SynthesisError::AssignmentMissing))?;
-
-            res_bits.push(new_bit);
-
-            if value[i] {
-                if i == num_bits - 1 {
-                    prev_pi_var = Some(new_bit);
-                } else {
-                    let new_pi = cs.alloc(|| format!("{}: leq_fixed allocate pi_i: {}", namespace, i),
-                                          || {
-                                              let mut x = prev_pi_val.ok_or(SynthesisError::AssignmentMissing)?;
-                                              x.mul_assign(&bits[i].ok_or(SynthesisError::AssignmentMissing)?);
-                                              Ok(x)
-                                          })?;
-
-                    if let Some(prev_pi) = prev_pi_var {
-                        cs.enforce(|| format!("{}: constrain pi_i: {}", namespace, i),
-                                   |lc| lc + prev_pi,
-                                   |lc| lc + new_bit,
-                                   |lc| lc + new_pi);
-                    } else {
-                        return Err(SynthesisError::Unsatisfiable);
-                    }
-
-                    prev_pi_var = Some(new_pi);
-                    prev_pi_val = {
-                        match bits[i] {
-                            Some(mut t) => {
-                                t.mul_assign(&prev_pi_val.ok_or(SynthesisError::Unsatisfiable)?);
-                                Some(t)
-                            }
-                            None => None
-                        }
-                    };
-                }
-
-                cs.enforce(|| format!("{}: constrain bit {}", namespace, i),
-                           |lc| lc + CS::one() - new_bit,
-                           |lc| lc + new_bit,
-                           |lc| lc);
-            } else {
-                if let Some(pi_var) = prev_pi_var {
-                    cs.enforce(|| format!("{}: constrain bit {}", namespace, i),
-                               |lc| lc + CS::one() - pi_var - new_bit,
-                               |lc| lc + new_bit,
-                               |lc| lc);
-                } else {
-                    return Err(SynthesisError::Unsatisfiable);
-                }
-            }
-        }
-        res_bits.reverse();
-
-        let mut lc = LinearCombination::zero();
-        let mut coeff = E::Fr::one();
-        for i in &res_bits {
-            lc = lc + (coeff, *i);
-            coeff.double();
-        }
-
-        cs.enforce(|| format!("{}: constraining bits to value", namespace),
-                   |_| lc,
-                   |lc| lc + CS::one(),
-                   |lc| lc + actual_value_var);
-
-        Ok(res_bits)
-    }
-
-    pub fn leq_not_fixed<CS>(&self, mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, right_bits: &Vec<Boolean>) -> Result<(), SynthesisError>
+    //PRF_key(input) for the prf chosen in the constants, see constants::PrfKind
+    pub fn prf<CS>(&self, cs: CS, namespace: &str, key: AllocatedNum<E>, input: AllocatedNum<E>, domain: PrfDomain) -> Result<AllocatedNum<E>, SynthesisError>
         where CS: ConstraintSystem<E>
     {
-        assert_eq!(left_bits.len(), right_bits.len());
-
-        let mut pi = Boolean::Constant(true);
-
-        for i in (0..right_bits.len()).rev() {
-            let t = Boolean::and(cs.namespace(|| format!("{}: calc t_i: {}", namespace, i)),
-                                 &left_bits[i].not(),
-                                 &right_bits[i])?.not();
-
-            pi = Boolean::and(cs.namespace(|| format!("{}: calc pi__i: {}", namespace, i)), &t, &pi)?;
-            let d = Boolean::and(cs.namespace(|| format!("{}: calc d_i: {}", namespace, i)), &pi, &left_bits[i])?;
-            cs.enforce(|| format!("{}: constrain to 0: {}", namespace, i),
-                       |_| right_bits[i].not().lc(CS::one(), E::Fr::one()),
-                       |_| d.lc(CS::one(), E::Fr::one()),
-                       |lc| lc);
+        match self.constants.prf {
+            PrfKind::MiMC => self.mimc_prf(cs, namespace, key, input, self.constants.mimc.domain(domain)),
+            PrfKind::Poseidon => self.poseidon_prf(cs, namespace, key, input, domain),
         }
-
-        Ok(())
     }
 
-    pub fn assignment_not_leq_not_fixed<CS>(&self, mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, right_bits: &Vec<Boolean>) -> Result<Boolean, SynthesisError>
+    //poseidon sponge over [key, input] in the sponge domain of the prf domain, see native::poseidon_prf
+    pub fn poseidon_prf<CS>(&self, mut cs: CS, namespace: &str, key: AllocatedNum<E>, input: AllocatedNum<E>, domain: PrfDomain) -> Result<AllocatedNum<E>, SynthesisError>
         where CS: ConstraintSystem<E>
     {
-        assert_eq!(left_bits.len(), right_bits.len());
-
-        let mut pi = Boolean::Constant(true);
-        let mut e_arr = vec![];
-
-        let mut sum: Num<E> = Num::zero();
-
-        for i in (0..right_bits.len()).rev() {
-            let t = Boolean::and(cs.namespace(|| format!("{}: calc t_i: {}", namespace, i)), &left_bits[i].not(), &right_bits[i])?.not();
-            pi = Boolean::and(cs.namespace(|| format!("{}: calc pi__i: {}", namespace, i)), &t, &pi)?.not();
-            let d = Boolean::and(cs.namespace(|| format!("{}: calc d_i: {}", namespace, i)), &pi, &left_bits[i])?.not();
-            let e = Boolean::and(cs.namespace(|| format!("{}: calc e_i: {}", namespace, i)), &right_bits[i].not(), &d)?.not();
-
-            sum = sum.add_bool_with_coeff(CS::one(), &e, E::Fr::one());
-            e_arr.push(e);
-        }
-
-        let bit = {
-            let b_val: Option<bool> = {
-                let calc = || {
-                    for bit in e_arr.as_slice() {
-                        if let Some(e) = bit.get_value() {
-                            if e {
-                                return Some(true);
-                            }
-                        } else {
-                            return None;
-                        }
-                    }
-
-                    return Some(false);
-                };
-
-                calc()
-            };
-
-            AllocatedBit::alloc(cs.namespace(|| format!("{} allocate bit", namespace)),
-                                b_val,
-            )?
-        };
-
-        let sum_inv = AllocatedNum::alloc(cs.namespace(|| namespace.to_owned() + ": sum inv"), || {
-            if bit.get_value().ok_or(SynthesisError::AssignmentMissing)? {
-                let sum_val = sum.get_value().ok_or(SynthesisError::AssignmentMissing)?;
-                return sum_val.inverse().ok_or(SynthesisError::DivisionByZero);
-            } else {
-                return Ok(E::Fr::one());
-            }
-        })?;
-
-        sum_inv.assert_nonzero(cs.namespace(|| namespace.to_owned() + "assert sum_inv non-zero"))?;
-
-        cs.enforce(|| namespace.to_owned() + "assert b correct",
-                   |_| sum.lc(E::Fr::one()),
-                   |lc| lc + sum_inv.get_variable(),
-                   |lc| lc + bit.get_variable(),
-        );
-
-
-        Ok(Boolean::Is(bit))
-    }
-
-    pub fn assignment_not_leq_fixed<CS>(&self, mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, value: E::Fr, mut num_bits: usize) -> Result<Boolean, SynthesisError>
-        where CS: ConstraintSystem<E>
-    {
-        let value: Vec<bool> = {
-            let mut res = vec![];
-            let mut value = value.into_repr();
-
-            for _i in 0..num_bits {
-                res.push(value.is_odd());
-                value.div2();
-            }
-
-            res
-        };
-
-        while num_bits > 0 && !value[num_bits - 1] {
-            num_bits -= 1;
-        }
-
-        if num_bits == 0 {
-            return Ok(Boolean::Constant(true));
-//            return Err(SynthesisError::Unsatisfiable);
-        }
-
-        let num_bits = num_bits;
-        let left_bits = left_bits;
-
-        assert!(left_bits.len() >= num_bits);
-
-        let mut pi = Boolean::Constant(true);
-        let mut e_arr = vec![];
-
-        let mut sum: Num<E> = Num::zero();
-
-        for i in (0..num_bits).rev() {
-            if value[i] {
-                pi = Boolean::and(cs.namespace(|| format!("{}: calc pi_i: {}", namespace, i)), &left_bits[i], &pi)?.not();
-            } else {
-                let e = Boolean::and(cs.namespace(|| format!("{}: calc e_i: {}", namespace, i)), &pi.not(), &left_bits[i])?.not();
-                sum = sum.add_bool_with_coeff(CS::one(), &e, E::Fr::one());
-                e_arr.push(e);
-            }
-        }
-
-        let bit = {
-            let b_val: Option<bool> = {
-                let calc = || {
-                    for bit in e_arr.as_slice() {
-                        if let Some(e) = bit.get_value() {
-                            if e {
-                                return Some(true);
-                            }
-                        } else {
-                            return None;
-                        }
-                    }
-
-                    return Some(false);
-                };
-
-                calc()
-            };
-
-            AllocatedBit::alloc(cs.namespace(|| format!("{} allocate bit", namespace)),
-                                b_val,
-            )?
-        };
-
-        let sum_inv = AllocatedNum::alloc(cs.namespace(|| namespace.to_owned() + ": sum inv"), || {
-            if bit.get_value().ok_or(SynthesisError::AssignmentMissing)? {
-                let sum_val = sum.get_value().ok_or(SynthesisError::AssignmentMissing)?;
-                return sum_val.inverse().ok_or(SynthesisError::DivisionByZero);
-            } else {
-                return Ok(E::Fr::one());
-            }
-        })?;
-
-        sum_inv.assert_nonzero(cs.namespace(|| namespace.to_owned() + "assert sum_inv non-zero"))?;
-
-        cs.enforce(|| namespace.to_owned() + "assert b correct",
-                   |_| sum.lc(E::Fr::one()),
-                   |lc| lc + sum_inv.get_variable(),
-                   |lc| lc + bit.get_variable(),
-        );
-
+        let result = self.poseidon_sponge(
+            cs.namespace(|| format!("{} poseidon prf", namespace)),
+            &format!("{} poseidon prf", namespace),
+            domain.sponge_domain(),
+            &[key.into(), input.into()],
+        )?;
 
-        Ok(Boolean::Is(bit))
+        alloc_num(cs.namespace(|| format!("{} allocate prf output", namespace)), &result)
     }
 
     pub fn crh<CS>(&self, mut cs: CS, namespace: &str, bits: &[Boolean]) -> Result<AllocatedNum<E>, SynthesisError>
@@ -1502,7 +1178,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
         for i in 0..self.constants.binomial.0[idx].len() {
             let mut c = self.constants.binomial.0[idx][i].clone();
             c.sub_assign(&E::Fr::one());
-            let bit = self.assignment_not_leq_fixed(cs.namespace(|| format!("{}: {} comparison # {}", namespace, idx, i)), format!("{}: {} comparison # {}", namespace, idx, i).as_ref(), rand_bits, c, 80)?;
+            let bit = comparison::assignment_not_leq_fixed(cs.namespace(|| format!("{}: {} comparison # {}", namespace, idx, i)), format!("{}: {} comparison # {}", namespace, idx, i).as_ref(), rand_bits, c, 80)?;
             num = num.add_bool_with_coeff(CS::one(), &bit, E::Fr::one());
         }
 
@@ -1512,11 +1188,18 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
     pub fn sub_binomial_binary_search<CS>(&self, mut cs: CS, namespace: &str, idx: usize, rand_bits: &Vec<Boolean>) -> Result<Num<E>, SynthesisError>
         where CS: ConstraintSystem<E>
     {
+        //number of bits of the result, the count of constants that rand reaches is between 0 and len
         let log_num_values = {
-            let num_binom_values = (self.constants.binomial.0[idx].len() + 1) as f64;
-            num_binom_values.log2() as usize
+            let num_binom_values = self.constants.binomial.0[idx].len();
+            let mut log = 0;
+            while (1 << log) - 1 < num_binom_values {
+                log += 1;
+            }
+
+            log
         };
 
+        //padded with 2^80 - 1, which no 80 bit rand is larger than
         let constants = {
             let mut constants = self.constants.binomial.0[idx].clone();
 
@@ -1524,18 +1207,21 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                 constants[i].sub_assign(&E::Fr::one());
             }
 
+            let mut max = E::Fr::one();
+            for _i in 0..80 {
+                max.double();
+            }
+            max.sub_assign(&E::Fr::one());
 
-            while constants.len() != (2 << log_num_values) - 1 {
-                let mut a = E::Fr::one();
-                a.negate();
-                constants.push(a);
+            while constants.len() != (1 << log_num_values) - 1 {
+                constants.push(max);
             }
 
             constants
         };
 
         let mut covered = vec![];
-        for _i in 0..((2 << log_num_values) - 1) {
+        for _i in 0..((1 << log_num_values) - 1) {
             covered.push(false);
         }
 
@@ -1546,8 +1232,9 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
             for step in 0..log_num_values {
                 let list_start = {
                     let mut list = vec![];
+                    //the constants that decide bit step, i.e. the ones whose index + 1 has lowest set bit step
                     for i in 1..(1 + constants.len()) {
-                        if (i & (2 << step) != 0) && !covered[i - 1] {
+                        if (i & (1 << step) != 0) && !covered[i - 1] {
                             covered[i - 1] = true;
                             list.push(i - 1);
                         }
@@ -1567,7 +1254,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
 
             //first round should be different
             if step == log_num_values - 1 {
-                let bit = self.assignment_not_leq_fixed(cs.namespace(|| format!("{}: first bit comparision", namespace)), format!("{}: first bit comparision", namespace).as_ref(), rand_bits, constants[list_start[0]], 80)?;
+                let bit = comparison::assignment_not_leq_fixed(cs.namespace(|| format!("{}: first bit comparision", namespace)), format!("{}: first bit comparision", namespace).as_ref(), rand_bits, constants[list_start[0]], 80)?;
                 bits.push(bit);
                 continue;
             }
@@ -1664,14 +1351,16 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
                        |lc| lc + CS::one(),
                        |_| num.lc(E::Fr::one()));
 
-            //assignment_not_leq_not_fixed<CS>(&self, mut cs: CS, namespace: &str, left_bits: &Vec<Boolean>, right_bits: &Vec<Boolean>)
-            let new_bit = self.assignment_not_leq_not_fixed(cs.namespace(|| format!("{}{} actual comparision", namespace, step)), format!("{}{} actual comparision", namespace, step).as_ref(), &rand_bits, &right_bits)?;
+            //left > right, see comparison.rs
+            let new_bit = comparison::assignment_not_leq_not_fixed(cs.namespace(|| format!("{}{} actual comparision", namespace, step)), format!("{}{} actual comparision", namespace, step).as_ref(), &rand_bits, &right_bits)?;
             bits.push(new_bit);
         }
 
+        //same offset as sub_binomial_regular and native::num_selections
         let mut num = Num::zero();
-        let mut coeff = E::Fr::one();
+        num = num.add_bool_with_coeff(CS::one(), &Boolean::Constant(true), self.constants.binomial.1[idx].clone());
 
+        let mut coeff = E::Fr::one();
         for bit in bits.iter().rev() {
             num = num.add_bool_with_coeff(CS::one(), &bit, coeff);
             coeff.double();
@@ -1691,7 +1380,7 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
             let mut rand_values = vec![];
             for i in 0..num_prfs {
                 let namespace2 = || format!("{}: mimc prf {}", namespace, i);
-                let random = self.mimc_prf(cs.namespace(namespace2), namespace2().as_ref(), a_sk.clone(), hash.clone(), &self.constants.mimc.prf_sel[i])?;
+                let random = self.prf(cs.namespace(namespace2), namespace2().as_ref(), a_sk.clone(), hash.clone(), PrfDomain::Sel(i))?;
                 rand_values.push(random);
             }
 
@@ -1782,3 +1471,13 @@ impl<'a, E: JubjubEngine> super::AnonStake<'a, E> {
 
 
 
+
+//the jubjub scalar field is smaller than the bls12 scalar field, so from_repr only fails on a bad repr
+fn fs_to_fr<E: JubjubEngine>(s: &E::Fs) -> Result<E::Fr, SynthesisError> {
+    let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    s.into_repr().write_le(cursor.get_mut())?;
+
+    let mut read_elem = E::Fr::zero().into_repr();
+    read_elem.read_le(cursor)?;
+    E::Fr::from_repr(read_elem).map_err(|_| SynthesisError::Unsatisfiable)
+}
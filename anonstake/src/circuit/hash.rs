@@ -0,0 +1,317 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use bellman::gadgets::boolean::Boolean;
+use bellman::gadgets::num::{AllocatedNum, Num};
+use ff::PrimeField;
+use zcash_primitives::jubjub::JubjubEngine;
+use zcash_proofs::circuit::ecc::EdwardsPoint;
+
+use crate::circuit::AnonStake;
+use crate::circuit::gadgets::alloc_num;
+use crate::circuit::public_inputs::u64_to_fr;
+use crate::constants::Constants;
+use crate::native;
+use crate::poseidon::poseidon_hash;
+
+/*
+hash function used by the sortition circuit for the merkle trees, the forward secure tree,
+the sn box and the prehashes of tsn, h and priority
+the coin commitment, full_pk, the hash of role and seed and the seed_comp prehash are always pedersen
+
+every CircuitHash has a native twin, native(), that has to agree with hash() bit for bit
+pedersen hashes the little endian bits of all the inputs one after the other,
+poseidon hashes the inputs as field elements padded with zeros to 8
+*/
+pub enum HashInput<E: JubjubEngine> {
+    //a full field element, pedersen uses to_bits_le on it
+    Num(AllocatedNum<E>),
+    //a value that already has its bits, like role or j_i. num has to be the packing of the bits
+    Bits(Num<E>, Vec<Boolean>),
+}
+
+#[derive(Clone, Copy)]
+pub struct NativeHashInput<F: PrimeField> {
+    pub value: F,
+    //number of bits the pedersen version uses
+    pub num_bits: usize,
+}
+
+impl<F: PrimeField> NativeHashInput<F> {
+    pub fn elem(value: F) -> NativeHashInput<F> {
+        NativeHashInput {
+            value,
+            num_bits: F::NUM_BITS as usize,
+        }
+    }
+
+    pub fn u64(value: u64, num_bits: usize) -> NativeHashInput<F> {
+        NativeHashInput {
+            value: u64_to_fr(value),
+            num_bits,
+        }
+    }
+}
+
+pub trait CircuitHash<E: JubjubEngine>: Clone {
+    fn hash<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, inputs: Vec<HashInput<E>>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E>;
+
+    fn native(constants: &Constants<E>, inputs: &[NativeHashInput<E::Fr>]) -> E::Fr;
+
+    //one node of the 8-ary forward secure tree, by default a binary tree of 7 hashes
+    fn hash_fs_node<CS>(circuit: &AnonStake<E, Self>, mut cs: CS, namespace: &str, children: &[AllocatedNum<E>]) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let mut layer = children.to_vec();
+        let mut k = 0;
+
+        while layer.len() > 1 {
+            let mut next = vec![];
+
+            for pair in layer.chunks(2) {
+                let namespace = format!("{} {}", namespace, k);
+                next.push(Self::hash(circuit, cs.namespace(|| namespace.clone()), &namespace,
+                                     vec![HashInput::Num(pair[0].clone()), HashInput::Num(pair[1].clone())])?);
+                k += 1;
+            }
+
+            layer = next;
+        }
+
+        Ok(layer[0].clone())
+    }
+
+    fn coin_commitment_membership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, cm: EdwardsPoint<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E>;
+
+    fn serial_number_nonmembership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, sn_box: AllocatedNum<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E>;
+}
+
+//binary merkle trees, uses cm_merkle_path and sn_merkle_path
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pedersen;
+
+//8-ary merkle trees, uses cm_poseidon_path and sn_poseidon_path
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Poseidon;
+
+impl<E: JubjubEngine> CircuitHash<E> for Pedersen {
+    fn hash<CS>(circuit: &AnonStake<E, Self>, mut cs: CS, namespace: &str, inputs: Vec<HashInput<E>>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let mut bits = vec![];
+
+        for (i, input) in inputs.into_iter().enumerate() {
+            match input {
+                HashInput::Num(num) => bits.extend(num.to_bits_le(cs.namespace(|| format!("{} to bits {}", namespace, i)))?),
+                HashInput::Bits(_, input_bits) => bits.extend(input_bits),
+            }
+        }
+
+        circuit.crh(cs, namespace, bits.as_slice())
+    }
+
+    fn native(constants: &Constants<E>, inputs: &[NativeHashInput<E::Fr>]) -> E::Fr {
+        let mut bits = vec![];
+        for input in inputs {
+            bits.extend(native::fr_bits_le(&input.value).into_iter().take(input.num_bits));
+        }
+
+        native::crh(constants, &bits)
+    }
+
+    fn coin_commitment_membership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, cm: EdwardsPoint<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E> {
+        circuit.coin_commitment_membership1(cs, namespace, cm)
+    }
+
+    fn serial_number_nonmembership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, sn_box: AllocatedNum<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E> {
+        circuit.serial_number_nonmembership1(cs, namespace, sn_box)
+    }
+}
+
+impl<E: JubjubEngine> CircuitHash<E> for Poseidon {
+    fn hash<CS>(circuit: &AnonStake<E, Self>, mut cs: CS, namespace: &str, inputs: Vec<HashInput<E>>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        //the fixed length hash takes at most 8 elements, a longer input is an error of the circuit and not a panic
+        if inputs.len() > 8 {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let mut elems: [Num<E>; 8] = [Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()];
+        for (i, input) in inputs.into_iter().enumerate() {
+            elems[i] = match input {
+                HashInput::Num(num) => num.into(),
+                HashInput::Bits(num, _) => num,
+            };
+        }
+
+        let result = circuit.poseidon(
+            cs.namespace(|| format!("{} poseidon", namespace)),
+            &format!("{} poseidon", namespace),
+            elems,
+        )?;
+
+        alloc_num(cs.namespace(|| format!("{} allocate result", namespace)), &result)
+    }
+
+    fn native(constants: &Constants<E>, inputs: &[NativeHashInput<E::Fr>]) -> E::Fr {
+        let elems: Vec<E::Fr> = inputs.iter().map(|input| input.value).collect();
+
+        poseidon_hash(&constants.poseidon, &native::poseidon_pad::<E>(&elems))
+    }
+
+    //a single poseidon hash of all 8 children
+    fn hash_fs_node<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, children: &[AllocatedNum<E>]) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let inputs = children.iter().map(|child| HashInput::Num(child.clone())).collect();
+
+        Self::hash(circuit, cs, namespace, inputs)
+    }
+
+    fn coin_commitment_membership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, cm: EdwardsPoint<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E> {
+        circuit.coin_commitment_membership2(cs, namespace, cm)
+    }
+
+    fn serial_number_nonmembership<CS>(circuit: &AnonStake<E, Self>, cs: CS, namespace: &str, sn_box: AllocatedNum<E>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E> {
+        circuit.serial_number_nonmembership2(cs, namespace, sn_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::boolean;
+    use bellman::gadgets::test::TestConstraintSystem;
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, thread_rng};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::constants::binomial_constants::TauValue;
+    use crate::constants::{PrfDomain, PrfKind};
+    use crate::poseidon::poseidon_sponge;
+
+    use super::*;
+
+    fn native_matches_circuit<H: CircuitHash<Bls12>>() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+        let anonstake = AnonStake::<Bls12, H>::init_empty(&constants, false, 0);
+
+        let rng = &mut thread_rng();
+        let elem = Fr::random(rng);
+        let value: u64 = rng.gen();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let num = AllocatedNum::alloc(cs.namespace(|| "elem"), || Ok(elem)).unwrap();
+        let bits = boolean::u64_into_boolean_vec_le(cs.namespace(|| "value bits"), Some(value)).unwrap();
+
+        let mut packed = Num::zero();
+        let mut coeff = Fr::one();
+        for bit in &bits {
+            packed = packed.add_bool_with_coeff(TestConstraintSystem::<Bls12>::one(), bit, coeff);
+            coeff.double();
+        }
+
+        let result = H::hash(&anonstake, cs.namespace(|| "hash"), "hash",
+                             vec![HashInput::Num(num), HashInput::Bits(packed, bits)]).unwrap();
+
+        assert!(cs.is_satisfied());
+        assert_eq!(result.get_value().unwrap(),
+                   H::native(&constants, &[NativeHashInput::elem(elem), NativeHashInput::u64(value, 64)]));
+    }
+
+    #[test]
+    fn pedersen_native_matches_circuit() {
+        native_matches_circuit::<Pedersen>();
+    }
+
+    #[test]
+    fn poseidon_native_matches_circuit() {
+        native_matches_circuit::<Poseidon>();
+    }
+
+    #[test]
+    fn poseidon_too_many_inputs() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 0);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let inputs = (0..9).map(|i| HashInput::Num(AllocatedNum::alloc(cs.namespace(|| format!("elem {}", i)), || Ok(Fr::one())).unwrap())).collect();
+
+        match Poseidon::hash(&anonstake, cs.namespace(|| "hash"), "hash", inputs) {
+            Err(SynthesisError::Unsatisfiable) => (),
+            _ => panic!("expected an unsatisfiable circuit for 9 inputs"),
+        }
+    }
+
+    #[test]
+    fn sponge_native_matches_circuit() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 0);
+        let rng = &mut thread_rng();
+
+        for length in vec![0, 3, 8, 11] {
+            let elems: Vec<Fr> = (0..length).map(|_| Fr::random(rng)).collect();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let mut input = vec![];
+            for (i, elem) in elems.iter().enumerate() {
+                let num = AllocatedNum::alloc(cs.namespace(|| format!("elem {}", i)), || Ok(*elem)).unwrap();
+                input.push(num.into());
+            }
+
+            let result = anonstake.poseidon_sponge(cs.namespace(|| "sponge"), "sponge", 5, &input).unwrap();
+
+            assert!(cs.is_satisfied());
+            assert_eq!(result.get_value().unwrap(), poseidon_sponge(&constants.poseidon, 5, &elems));
+        }
+    }
+
+    #[test]
+    fn prf_native_matches_circuit() {
+        let jubjub = JubjubBls12::new();
+        let rng = &mut thread_rng();
+        let key = Fr::random(rng);
+        let input = Fr::random(rng);
+
+        for prf in vec![PrfKind::MiMC, PrfKind::Poseidon] {
+            let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20).with_prf(prf);
+            let anonstake = AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 0);
+
+            let mut outputs = vec![];
+            for domain in vec![PrfDomain::Sn, PrfDomain::Tsn, PrfDomain::Sel(3)] {
+                let mut cs = TestConstraintSystem::<Bls12>::new();
+                let key_num = AllocatedNum::alloc(cs.namespace(|| "key"), || Ok(key)).unwrap();
+                let input_num = AllocatedNum::alloc(cs.namespace(|| "input"), || Ok(input)).unwrap();
+
+                let result = anonstake.prf(cs.namespace(|| "prf"), "prf", key_num, input_num, domain).unwrap();
+
+                assert!(cs.is_satisfied());
+                assert_eq!(result.get_value().unwrap(), native::prf(&constants, &key, &input, domain));
+                outputs.push(result.get_value().unwrap());
+            }
+
+            //different domains give different outputs
+            assert_ne!(outputs[0], outputs[1]);
+            assert_ne!(outputs[1], outputs[2]);
+        }
+    }
+
+    //the prf is not the fixed length hash of the merkle nodes and commitments on [key, tag, input, 0, ...]
+    #[test]
+    fn poseidon_prf_is_not_a_node_hash() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20).with_prf(PrfKind::Poseidon);
+        let rng = &mut thread_rng();
+        let key = Fr::random(rng);
+        let input = Fr::random(rng);
+
+        let node_hash = Poseidon::native(&constants, &[NativeHashInput::elem(key), NativeHashInput::elem(u64_to_fr(PrfDomain::Sn.tag())), NativeHashInput::elem(input)]);
+        assert_ne!(native::prf(&constants, &key, &input, PrfDomain::Sn), node_hash);
+        assert_eq!(native::prf(&constants, &key, &input, PrfDomain::Sn), poseidon_sponge(&constants.poseidon, PrfDomain::Sn.sponge_domain(), &[key, input]));
+    }
+}
@@ -0,0 +1,180 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::gadgets::num::AllocatedNum;
+use ff::{Field, PrimeField};
+use rand::{Rng, thread_rng};
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::AnonStake;
+use crate::circuit::hash::Pedersen;
+use crate::circuit::anonstake_inputs::Coin;
+use crate::constants::Constants;
+use crate::native;
+
+/*
+deposit of public stake into the anonymous pool
+proves that cm is a commitment (same layout as constrain_coin_commitment) to the public value
+without revealing full_pk, rho or s
+*/
+#[derive(Clone)]
+pub struct Mint<'a, E: JubjubEngine> {
+    pub constants: &'a Constants<'a, E>,
+    pub full_pk: Option<E::Fr>,
+    pub coin: Coin<E>,
+}
+
+//everything the owner of the new coin needs to know to use it later
+pub struct MintWitness<E: JubjubEngine> {
+    pub full_pk: E::Fr,
+    pub value: u64,
+    pub rho: E::Fr,
+    pub s: E::Fs,
+    pub cm: E::Fr,
+}
+
+impl<E: JubjubEngine> MintWitness<E> {
+    pub fn new(constants: &Constants<E>, full_pk: E::Fr, value: u64) -> MintWitness<E> {
+        let rng = &mut thread_rng();
+        let rho = E::Fr::random(rng);
+        let s = E::Fs::random(rng);
+
+        let cm = native::coin_commitment(constants, &full_pk, value, &rho, &s);
+
+        MintWitness {
+            full_pk,
+            value,
+            rho,
+            s,
+            cm,
+        }
+    }
+
+    //public inputs of the mint circuit, without the leading one
+    pub fn public_inputs(&self) -> Vec<E::Fr> {
+        vec![crate::circuit::public_inputs::u64_to_fr(self.value), self.cm]
+    }
+}
+
+impl<'a, E: JubjubEngine> Mint<'a, E> {
+    pub fn init_empty(constants: &'a Constants<E>) -> Mint<'a, E> {
+        Mint {
+            constants,
+            full_pk: None,
+            coin: Coin {
+                value: None,
+                rho: None,
+                s: None,
+            },
+        }
+    }
+
+    pub fn from_witness(constants: &'a Constants<E>, witness: &MintWitness<E>) -> Mint<'a, E> {
+        Mint {
+            constants,
+            full_pk: Some(witness.full_pk),
+            coin: Coin {
+                value: Some(witness.value),
+                rho: Some(witness.rho),
+                s: Some(witness.s),
+            },
+        }
+    }
+
+    pub fn init_testing(constants: &'a Constants<E>) -> Mint<'a, E> {
+        let rng = &mut thread_rng();
+        let witness = MintWitness::new(constants, E::Fr::random(rng), rng.gen::<u64>() % (1 << 60));
+
+        Self::from_witness(constants, &witness)
+    }
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for Mint<'a, E> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        //merkle height and hash do not matter, the mint circuit does not touch the trees and the commitment is always pedersen
+        let mut view = AnonStake::<E, Pedersen>::init_empty(self.constants, false, 0);
+        view.aux_input.coin = self.coin.clone();
+
+        let value = AllocatedNum::alloc(cs.namespace(|| "allocate value"), || {
+            let tmp = self.coin.value.ok_or(SynthesisError::AssignmentMissing)?.to_string();
+            E::Fr::from_str(&tmp).ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        value.inputize(cs.namespace(|| "inputize value"))?;
+
+        let full_pk = AllocatedNum::alloc(cs.namespace(|| "full_pk"), || self.full_pk.ok_or(SynthesisError::AssignmentMissing))?;
+        let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"), || self.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
+
+        let (cm, value_num, _) = view.constrain_coin_commitment(
+            cs.namespace(|| "coin commitment computation"),
+            "coin commitment computation", full_pk, rho)?;
+
+        cs.enforce(|| "value in commitment is the public value",
+                   |_| value_num.lc(E::Fr::one()),
+                   |lc| lc + CS::one(),
+                   |lc| lc + value.get_variable());
+
+        cm.get_x().inputize(cs.namespace(|| "inputize cm"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::public_inputs::{fr_from_hex, fr_to_hex};
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    #[test]
+    fn commitment_matches_native() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        for value in vec![0, 1, rng.gen::<u64>() % (1 << 60), (1 << 60) - 1] {
+            let witness = MintWitness::new(&constants, Fr::random(rng), value);
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            Mint::from_witness(&constants, &witness).synthesize(&mut cs).unwrap();
+
+            assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+            assert_eq!(cs.get("inputize cm/input variable"), witness.cm);
+            assert!(cs.verify(&witness.public_inputs()));
+        }
+    }
+
+    #[test]
+    fn wrong_value() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let witness = MintWitness::new(&constants, Fr::random(rng), 1000);
+        let mut wrong = Mint::from_witness(&constants, &witness);
+        wrong.coin.value = Some(1001);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        wrong.synthesize(&mut cs).unwrap();
+        assert!(!cs.verify(&witness.public_inputs()));
+    }
+
+    //the full_pk printed by the mint command has to be accepted by --full_pk
+    #[test]
+    fn full_pk_hex() {
+        let rng = &mut thread_rng();
+
+        for full_pk in vec![Fr::zero(), Fr::one(), Fr::random(rng)] {
+            assert_eq!(fr_from_hex::<Fr>(&fr_to_hex(&full_pk)), Some(full_pk));
+        }
+
+        assert_eq!(fr_from_hex::<Fr>("0x2a"), Fr::from_str("42"));
+        assert_eq!(fr_from_hex::<Fr>("42"), Fr::from_str("66"));
+        assert_eq!(fr_from_hex::<Fr>("0x"), None);
+        assert_eq!(fr_from_hex::<Fr>("0xzz"), None);
+        //larger than the modulus
+        assert_eq!(fr_from_hex::<Fr>(&"f".repeat(64)), None);
+    }
+}
@@ -3,65 +3,70 @@ use zcash_primitives::jubjub::JubjubEngine;
 use ff::{Field, PrimeField};
 
 pub mod anonstake_inputs;
+pub mod public_inputs;
 
 use anonstake_inputs::*;
 use bellman::gadgets::boolean::{Boolean, AllocatedBit};
 use bellman::gadgets::{num, boolean};
 use bellman::gadgets::num::{AllocatedNum, Num};
+use std::marker::PhantomData;
 
+pub mod comparison;
+pub mod diagnostics;
+pub mod exporter;
 pub mod gadgets;
+pub mod hash;
+pub mod mint;
+pub mod multi_coin;
+pub mod multi_role;
+pub mod profiler;
+pub mod transfer;
 
+use hash::{CircuitHash, HashInput};
+use crate::constants::PrfDomain;
+
+//H is the hash used for the merkle trees, the forward secure tree and the prehashes, see hash.rs
 #[derive(Clone)]
-pub struct AnonStake<'a, E: JubjubEngine> {
+pub struct AnonStake<'a, E: JubjubEngine, H: CircuitHash<E>> {
     pub constants: &'a crate::constants::Constants<'a, E>,
     pub is_bp: bool,
-    pub use_poseidon: bool,
+    //sortition by a delegate, see participant_full_pk
+    pub delegated: bool,
     pub pub_input: PubInput<E>,
     pub aux_input: AuxInput<E>,
     pub bp_pub_input: BlockProposerPubInput,
     pub bp_aux_input: BlockProposerAuxInput,
+    pub hash: PhantomData<H>,
 }
 
-impl<'a, E: JubjubEngine> Circuit<E> for AnonStake<'a, E> {
-    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-        let a_sk = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || self.aux_input.a_sk.ok_or(SynthesisError::AssignmentMissing))?;
-
-        //kind of hacky but whatever, do not have enough time
-        let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero"), || Ok(E::Fr::zero()))?;
-
-        let a_pk = self.mimc_prf(cs.namespace(|| "calc a_pk"), "calc a_pk", a_sk.clone(), allocated_zero.clone(), &self.constants.mimc.prf_addr)?;
-
-        let (role, role_bits, fs_start_bits, fs_pk) = self.forward_secure_tree(
-            cs.namespace(|| "forward secure tree"), "forward secure tree")?;
-
-        let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"),
-                                            || self.aux_input.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
-
-        let full_pk = self.constrain_full_pk(
-            cs.namespace(|| "constrain packed values"),
-            "constrain packed values", fs_start_bits, fs_pk, a_pk.clone())?;
-
-        let (cm, _value, value_bits) = self.constrain_coin_commitment(
-            cs.namespace(|| "coin commitment computation"),
-            "coin commitment computation", full_pk, rho.clone())?;
-        self.coin_commitment_membership(cs.namespace(|| "coin commitment membership"), "coin commitment membership", cm)?;
-
-        let seed_sel = AllocatedNum::alloc(cs.namespace(|| "allocate seed_sel"), || self.pub_input.seed.ok_or(SynthesisError::AssignmentMissing))?;
-        seed_sel.inputize(cs.namespace(|| "inputize seed_sel"))?;
-
-        let seed_sel_bits = seed_sel.to_bits_le_strict(cs.namespace(|| "bits of seed_sel"))?;
-
-        let hash_role_seed = {
-            let mut a = role_bits.clone();
-            a.extend(seed_sel_bits.clone());
-
-            self.crh(cs.namespace(|| "hash role seed_sel"), "hash role and seed", a.as_slice())?
-        };
-
-        let num_selections = self.calc_num_selections(cs.namespace(|| "calc number selections"), "calc number selections", value_bits.as_slice(), &hash_role_seed, &a_sk)?;
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> AnonStake<'a, E, H> {
+    /*
+    a_pk is derived from the a_sk of the prover
+    for a normal coin that is the owner and full_pk = H(fs_tree_start, fs_pk, a_pk)
+    for a delegated coin the prover is the delegate, and full_pk = H(fs_tree_start, fs_pk, a_pk_owner, a_pk)
+    so the delegate can do sortition (and compute sn) without being able to spend the coin,
+    spending still needs the owner's a_sk, see transfer.rs
+    */
+    pub fn participant_full_pk<CS>(&self, mut cs: CS, fs_start_bits: Vec<Boolean>, fs_pk: AllocatedNum<E>, a_pk: AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        if self.delegated {
+            let a_pk_owner = AllocatedNum::alloc(cs.namespace(|| "a_pk owner"), || self.aux_input.a_pk_owner.ok_or(SynthesisError::AssignmentMissing))?;
+
+            self.constrain_full_pk(
+                cs.namespace(|| "constrain packed values"),
+                "constrain packed values", fs_start_bits, fs_pk, a_pk_owner, Some(a_pk))
+        } else {
+            self.constrain_full_pk(
+                cs.namespace(|| "constrain packed values"),
+                "constrain packed values", fs_start_bits, fs_pk, a_pk, None)
+        }
+    }
 
+    //11 bits of j_i, the index of the selection the proof is for
+    pub fn alloc_j_i<CS>(&self, mut cs: CS, j_i: Option<u64>) -> Result<(Num<E>, Vec<Boolean>), SynthesisError>
+        where CS: ConstraintSystem<E> {
         let j_i_bits = {
-            let values = match self.aux_input.j_i {
+            let values = match j_i {
                 Some(ref value) => {
                     let mut tmp = Vec::with_capacity(11);
 
@@ -86,194 +91,200 @@ impl<'a, E: JubjubEngine> Circuit<E> for AnonStake<'a, E> {
                 .collect::<Result<Vec<_>, SynthesisError>>()?
         };
 
+        //bit i has coefficient 2^i, params generated while every bit had coefficient one do not match this circuit
         let j_i = {
             let mut coeff = E::Fr::one();
             let mut num = Num::zero();
 
             for bit in &j_i_bits {
-                num = num.add_bool_with_coeff(CS::one(), bit, E::Fr::one());
+                num = num.add_bool_with_coeff(CS::one(), bit, coeff);
                 coeff.double();
             }
 
             num
         };
 
-        {
-            let num_selection_bits = {
-                let values = match num_selections.get_value() {
-                    Some(value) => {
-                        let value = value.into_repr().as_ref()[0];
-                        let mut tmp = Vec::with_capacity(11);
+        Ok((j_i, j_i_bits))
+    }
 
-                        for i in 0..11 {
-                            tmp.push(Some(value >> i & 1 == 1));
-                        }
+    //j_i < num_selections
+    pub fn enforce_selected<CS>(&self, mut cs: CS, num_selections: &AllocatedNum<E>, j_i_bits: &Vec<Boolean>) -> Result<(), SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let num_selection_bits = {
+            let values = match num_selections.get_value() {
+                Some(value) => {
+                    let value = value.into_repr().as_ref()[0];
+                    let mut tmp = Vec::with_capacity(11);
 
-                        tmp
+                    for i in 0..11 {
+                        tmp.push(Some(value >> i & 1 == 1));
                     }
-                    None => vec![None; 11],
-                };
-
-                values
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, b)| {
-                        Ok(Boolean::from(AllocatedBit::alloc(
-                            cs.namespace(|| format!("num_selection bit {}", i)),
-                            b,
-                        )?))
-                    })
-                    .collect::<Result<Vec<_>, SynthesisError>>()?
+
+                    tmp
+                }
+                None => vec![None; 11],
             };
 
-            let mut num = num::Num::zero();
-            let mut coeff = E::Fr::one();
-            for bit in &num_selection_bits {
-                num = num.add_bool_with_coeff(CS::one(), bit, coeff);
-                coeff.double();
-            }
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    Ok(Boolean::from(AllocatedBit::alloc(
+                        cs.namespace(|| format!("num_selection bit {}", i)),
+                        b,
+                    )?))
+                })
+                .collect::<Result<Vec<_>, SynthesisError>>()?
+        };
 
-            cs.enforce(|| "num_selection_bits",
-                       |lc| lc + num_selections.get_variable(),
-                       |lc| lc + CS::one(),
-                       |_| num.lc(E::Fr::one()));
+        let mut num = num::Num::zero();
+        let mut coeff = E::Fr::one();
+        for bit in &num_selection_bits {
+            num = num.add_bool_with_coeff(CS::one(), bit, coeff);
+            coeff.double();
+        }
 
+        cs.enforce(|| "num_selection_bits",
+                   |lc| lc + num_selections.get_variable(),
+                   |lc| lc + CS::one(),
+                   |_| num.lc(E::Fr::one()));
 
-            self.leq_not_fixed(cs.namespace(|| "j_i less than"), "j_i less than", &j_i_bits, &num_selection_bits)?;
-        }
 
-        {
-            let sn = self.mimc_prf(cs.namespace(|| "calc serial number"), "calc serial number", a_sk.clone(), rho.clone(), &self.constants.mimc.prf_sn)?;
+        comparison::leq_not_fixed(cs.namespace(|| "j_i less than"), "j_i less than", j_i_bits, &num_selection_bits)
+    }
 
-            let sn_less_diff = AllocatedNum::<E>::alloc(cs.namespace(|| "allocate sn_less"), || {
-                self.aux_input.sn_less_diff.ok_or(SynthesisError::AssignmentMissing)
-            })?;
+    //sn = PRF_{a_sk}(rho) lies strictly inside a box (sn_less, sn_plus) that is in the sn tree, returns sn
+    pub fn serial_number_checks<CS>(&self, mut cs: CS, a_sk: &AllocatedNum<E>, rho: &AllocatedNum<E>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let sn = self.prf(cs.namespace(|| "calc serial number"), "calc serial number", a_sk.clone(), rho.clone(), PrfDomain::Sn)?;
 
-            let sn_plus_diff = AllocatedNum::<E>::alloc(cs.namespace(|| "allocate sn_plus"), || {
-                self.aux_input.sn_plus_diff.ok_or(SynthesisError::AssignmentMissing)
-            })?;
+        let sn_less_diff = AllocatedNum::<E>::alloc(cs.namespace(|| "allocate sn_less"), || {
+            self.aux_input.sn_less_diff.ok_or(SynthesisError::AssignmentMissing)
+        })?;
 
-            sn_less_diff.assert_nonzero(cs.namespace(|| "assert sn_less_sub nonzero"))?;
-            sn_plus_diff.assert_nonzero(cs.namespace(|| "assert sn_plus_sub nonzero"))?;
+        let sn_plus_diff = AllocatedNum::<E>::alloc(cs.namespace(|| "allocate sn_plus"), || {
+            self.aux_input.sn_plus_diff.ok_or(SynthesisError::AssignmentMissing)
+        })?;
 
-            //could avoid allocating new numbers but this is so much easier to program
-            let sn_less = AllocatedNum::alloc(cs.namespace(|| "assert sn_less_none_zero"), || {
-                let mut tmp = sn.get_value().ok_or(SynthesisError::AssignmentMissing)?;
-                tmp.sub_assign(&sn_less_diff.get_value().ok_or(SynthesisError::AssignmentMissing)?);
-                Ok(tmp)
-            })?;
+        sn_less_diff.assert_nonzero(cs.namespace(|| "assert sn_less_sub nonzero"))?;
+        sn_plus_diff.assert_nonzero(cs.namespace(|| "assert sn_plus_sub nonzero"))?;
 
-            let sn_plus = AllocatedNum::alloc(cs.namespace(|| "assert sn_plus_none_zero"), || {
-                let mut tmp = sn.get_value().ok_or(SynthesisError::AssignmentMissing)?;
-                tmp.add_assign(&sn_plus_diff.get_value().ok_or(SynthesisError::AssignmentMissing)?);
-                Ok(tmp)
-            })?;
+        //could avoid allocating new numbers but this is so much easier to program
+        let sn_less = AllocatedNum::alloc(cs.namespace(|| "assert sn_less_none_zero"), || {
+            let mut tmp = sn.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            tmp.sub_assign(&sn_less_diff.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(tmp)
+        })?;
 
-            let sn_bits = sn.to_bits_le_strict(cs.namespace(|| "sn bits"))?;
-            let sn_less_bits = sn_less.to_bits_le_strict(cs.namespace(|| "sn_less bits"))?;
-            let sn_plus_bits = sn_plus.to_bits_le_strict(cs.namespace(|| "sn_plus bits"))?;
+        let sn_plus = AllocatedNum::alloc(cs.namespace(|| "assert sn_plus_none_zero"), || {
+            let mut tmp = sn.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            tmp.add_assign(&sn_plus_diff.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(tmp)
+        })?;
 
-            self.leq_not_fixed(cs.namespace(|| "compare sn sn_less"), "compare sn sn_less", &sn_less_bits, &sn_bits)?;
+        let sn_bits = sn.to_bits_le_strict(cs.namespace(|| "sn bits"))?;
+        let sn_less_bits = sn_less.to_bits_le_strict(cs.namespace(|| "sn_less bits"))?;
+        let sn_plus_bits = sn_plus.to_bits_le_strict(cs.namespace(|| "sn_plus bits"))?;
 
-            self.leq_not_fixed(cs.namespace(|| "compare sn sn_plus"), "compare sn sn_plus", &sn_bits, &sn_plus_bits)?;
+        comparison::leq_not_fixed(cs.namespace(|| "compare sn sn_less"), "compare sn sn_less", &sn_less_bits, &sn_bits)?;
 
-            let sn_box = if self.use_poseidon {
-                self.crh_poseidon_or_pedersen_elems(cs.namespace(|| "calc sn box"), "calc sn_box", sn_less, sn_plus)?
-            } else {
-                let mut all_bits = sn_less_bits;
-                all_bits.extend(sn_plus_bits);
-                self.crh(cs.namespace(|| "calc sn box"), "calc sn_box", all_bits.as_ref())?
-            };
+        comparison::leq_not_fixed(cs.namespace(|| "compare sn sn_plus"), "compare sn sn_plus", &sn_bits, &sn_plus_bits)?;
 
-            self.serial_number_nonmembership(cs.namespace(|| "sn merkle"), "sn merkle", sn_box)?;
-        }
+        let sn_box = H::hash(self, cs.namespace(|| "calc sn box"), "calc sn_box",
+                             vec![HashInput::Bits(sn_less.into(), sn_less_bits), HashInput::Bits(sn_plus.into(), sn_plus_bits)])?;
 
-        {
-            let hash = if self.use_poseidon {
-                let a = [Num::from(rho.clone()), Num::from(role.clone()), j_i.clone(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()];
+        self.serial_number_nonmembership(cs.namespace(|| "sn merkle"), "sn merkle", sn_box)?;
 
-                let hash = self.poseidon(cs.namespace(|| "prehash calc tsn"), "prehash calc tsn", a)?;
+        Ok(sn)
+    }
 
-                let allocated_num = AllocatedNum::alloc(
-                    cs.namespace(|| "allocate hash for tsn calc"),
-                    || hash.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+    //PRF_{a_sk}(H(rho, role, j_i))
+    pub fn calc_tsn<CS>(&self, mut cs: CS, a_sk: &AllocatedNum<E>, rho: &AllocatedNum<E>, role: &AllocatedNum<E>, role_bits: &Vec<Boolean>, j_i: &Num<E>, j_i_bits: &Vec<Boolean>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let hash = H::hash(self, cs.namespace(|| "prehash calc tsn"), "prehash calc tsn", vec![
+            HashInput::Num(rho.clone()),
+            HashInput::Bits(Num::from(role.clone()), role_bits.clone()),
+            HashInput::Bits(j_i.clone(), j_i_bits.clone()),
+        ])?;
 
-                cs.enforce(|| "ensure poseidon hash value = allocated num for h calc",
-                           |_| hash.lc(E::Fr::one()),
-                           |lc| lc + CS::one(),
-                           |lc| lc + allocated_num.get_variable());
+        self.prf(cs.namespace(|| "calc tsn"), "calc tsn", a_sk.clone(), hash, PrfDomain::Tsn)
+    }
 
-                allocated_num
-            }
-            else {
-                let mut all_bits = rho.to_bits_le(cs.namespace(|| "rho bits"))?;
+    //PRF_{a_sk}(H(h_sig, role))
+    pub fn calc_h<CS>(&self, mut cs: CS, a_sk: &AllocatedNum<E>, h_sig: &AllocatedNum<E>, role: &AllocatedNum<E>, role_bits: &Vec<Boolean>) -> Result<AllocatedNum<E>, SynthesisError>
+        where CS: ConstraintSystem<E> {
+        let hash = H::hash(self, cs.namespace(|| "prehash calc h"), "prehash calc h", vec![
+            HashInput::Num(h_sig.clone()),
+            HashInput::Bits(Num::from(role.clone()), role_bits.clone()),
+        ])?;
 
-                all_bits.extend(role_bits.clone());
-                all_bits.extend(j_i_bits.clone());
-                self.crh(cs.namespace(|| "prehash calc tsn"), "prehash calc tsn", all_bits.as_ref())?
-            };
+        self.prf(cs.namespace(|| "calc h"), "calc h", a_sk.clone(), hash, PrfDomain::Pk)
+    }
+}
 
-            let tsn = self.mimc_prf(cs.namespace(|| "calc tsn"), "calc tsn", a_sk.clone(), hash, &self.constants.mimc.prf_tsn)?;
-            tsn.inputize(cs.namespace(|| "inputize tsn"))?;
-        }
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Circuit<E> for AnonStake<'a, E, H> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let a_sk = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || self.aux_input.a_sk.ok_or(SynthesisError::AssignmentMissing))?;
 
-        {
-            let h_sig = AllocatedNum::alloc(cs.namespace(|| "allocate h_sig"), || self.pub_input.h_sig.ok_or(SynthesisError::AssignmentMissing))?;
-            h_sig.inputize(cs.namespace(|| "inputize h_sig"))?;
+        //kind of hacky but whatever, do not have enough time
+        let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero"), || Ok(E::Fr::zero()))?;
 
-            let hash = if self.use_poseidon {
-                let a = [Num::from(h_sig), Num::from(role.clone()), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()];
+        let a_pk = self.prf(cs.namespace(|| "calc a_pk"), "calc a_pk", a_sk.clone(), allocated_zero.clone(), PrfDomain::Addr)?;
 
-                let hash = self.poseidon(cs.namespace(|| "prehash calc for h"), "prehash calc for h", a)?;
+        let (role, role_bits, fs_start_bits, fs_pk) = self.forward_secure_tree(
+            cs.namespace(|| "forward secure tree"), "forward secure tree")?;
 
-                let allocated_num = AllocatedNum::alloc(
-                    cs.namespace(|| "allocate hash for h calc"),
-                    || hash.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+        let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"),
+                                            || self.aux_input.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
 
-                cs.enforce(|| "ensure poseidon hash value = allocated num for tsn calc",
-                           |_| hash.lc(E::Fr::one()),
-                           |lc| lc + CS::one(),
-                           |lc| lc + allocated_num.get_variable());
+        let full_pk = self.participant_full_pk(cs.namespace(|| "full_pk"), fs_start_bits, fs_pk, a_pk.clone())?;
 
-                allocated_num
-            }
-            else {
-                let mut all_bits = h_sig.to_bits_le(cs.namespace(|| "h_sig to bits"))?;
-                all_bits.extend(role_bits.clone());
+        let (cm, _value, value_bits) = self.constrain_coin_commitment(
+            cs.namespace(|| "coin commitment computation"),
+            "coin commitment computation", full_pk, rho.clone())?;
+        self.coin_commitment_membership(cs.namespace(|| "coin commitment membership"), "coin commitment membership", cm)?;
 
-                self.crh(cs.namespace(|| "hash for calc h"), "hash for calc h", all_bits.as_ref())?
-            };
+        let seed_sel = AllocatedNum::alloc(cs.namespace(|| "allocate seed_sel"), || self.pub_input.seed.ok_or(SynthesisError::AssignmentMissing))?;
+        seed_sel.inputize(cs.namespace(|| "inputize seed_sel"))?;
 
+        let seed_sel_bits = seed_sel.to_bits_le_strict(cs.namespace(|| "bits of seed_sel"))?;
 
-            let h = self.mimc_prf(cs.namespace(|| "calc h"), "calc h", a_sk.clone(), hash, &self.constants.mimc.prf_pk)?;
-            h.inputize(cs.namespace(|| "inputize h"))?;
-        }
+        let hash_role_seed = {
+            let mut a = role_bits.clone();
+            a.extend(seed_sel_bits.clone());
 
-        if self.is_bp {
-            let hash = if self.use_poseidon {
-                let a = [Num::from(role.clone()), Num::from(seed_sel.clone()), j_i.clone(), Num::zero(), Num::zero(), Num::zero(), Num::zero(), Num::zero()];
+            self.crh(cs.namespace(|| "hash role seed_sel"), "hash role and seed", a.as_slice())?
+        };
 
-                let hash = self.poseidon(cs.namespace(|| "prehash calc priority"), "prehash calc tsn", a)?;
+        let num_selections = self.calc_num_selections(cs.namespace(|| "calc number selections"), "calc number selections", value_bits.as_slice(), &hash_role_seed, &a_sk)?;
 
-                let allocated_num = AllocatedNum::alloc(
-                    cs.namespace(|| "allocate hash for priority"),
-                    || hash.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+        let (j_i, j_i_bits) = self.alloc_j_i(cs.namespace(|| "allocate j_i"), self.aux_input.j_i)?;
 
-                cs.enforce(|| "ensure poseidon hash value = allocated num for priority calc",
-                           |_| hash.lc(E::Fr::one()),
-                           |lc| lc + CS::one(),
-                           |lc| lc + allocated_num.get_variable());
+        self.enforce_selected(cs.namespace(|| "enforce j_i selected"), &num_selections, &j_i_bits)?;
 
-                allocated_num
-            } else {
-                let mut all_bits = role_bits.clone();
-                all_bits.extend(seed_sel_bits);
-                all_bits.extend(j_i_bits.clone());
+        self.serial_number_checks(cs.namespace(|| "serial number"), &a_sk, &rho)?;
 
-                self.crh(cs.namespace(|| "prehash calc priority"), "prehash calc priority", all_bits.as_ref())?
-            };
+        {
+            let tsn = self.calc_tsn(cs.namespace(|| "tsn"), &a_sk, &rho, &role, &role_bits, &j_i, &j_i_bits)?;
+            tsn.inputize(cs.namespace(|| "inputize tsn"))?;
+        }
+
+        {
+            let h_sig = AllocatedNum::alloc(cs.namespace(|| "allocate h_sig"), || self.pub_input.h_sig.ok_or(SynthesisError::AssignmentMissing))?;
+            h_sig.inputize(cs.namespace(|| "inputize h_sig"))?;
+
+            let h = self.calc_h(cs.namespace(|| "h"), &a_sk, &h_sig, &role, &role_bits)?;
+            h.inputize(cs.namespace(|| "inputize h"))?;
+        }
 
-            let priority = self.mimc_prf(cs.namespace(|| "calc priority"), "calc priority", a_sk.clone(), hash, &self.constants.mimc.prf_priority)?;
+        if self.is_bp {
+            let hash = H::hash(self, cs.namespace(|| "prehash calc priority"), "prehash calc priority", vec![
+                HashInput::Bits(Num::from(role.clone()), role_bits.clone()),
+                HashInput::Bits(Num::from(seed_sel.clone()), seed_sel_bits),
+                HashInput::Bits(j_i.clone(), j_i_bits.clone()),
+            ])?;
+
+            let priority = self.prf(cs.namespace(|| "calc priority"), "calc priority", a_sk.clone(), hash, PrfDomain::Priority)?;
             priority.inputize(cs.namespace(|| "inputize priority"))?;
 
             let round = AllocatedNum::alloc(cs.namespace(|| "allocate round"), || {
@@ -310,7 +321,7 @@ impl<'a, E: JubjubEngine> Circuit<E> for AnonStake<'a, E> {
 
             let hash = self.crh(cs.namespace(|| "prehash calc seed_comp"), "prehash calc seed_comp", all_bits.as_ref())?;
 
-            let seed_comp = self.mimc_prf(cs.namespace(|| "calc seed_comp"), "calc seed_comp", a_sk.clone(), hash, &self.constants.mimc.prf_seed)?;
+            let seed_comp = self.prf(cs.namespace(|| "calc seed_comp"), "calc seed_comp", a_sk.clone(), hash, PrfDomain::Seed)?;
             seed_comp.inputize(cs.namespace(|| "inputize seed_comp"))?;
         }
 
@@ -318,19 +329,19 @@ impl<'a, E: JubjubEngine> Circuit<E> for AnonStake<'a, E> {
     }
 }
 
-pub struct AnonStakeIterator<'a, E: JubjubEngine> {
-    a: AnonStake<'a, E>
+pub struct AnonStakeIterator<'a, E: JubjubEngine, H: CircuitHash<E>> {
+    a: AnonStake<'a, E, H>
 }
 
-impl<'a, E: JubjubEngine> AnonStakeIterator<'a, E> {
-    pub fn get_copy(&self) -> Option<AnonStake<'a, E>> {
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> AnonStakeIterator<'a, E, H> {
+    pub fn get_copy(&self) -> Option<AnonStake<'a, E, H>> {
         Some(self.a.clone())
     }
 }
 
 
-impl<'a, E: JubjubEngine> Iterator for AnonStakeIterator<'a, E> {
-    type Item = AnonStake<'a, E>;
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Iterator for AnonStakeIterator<'a, E, H> {
+    type Item = AnonStake<'a, E, H>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let ret = self.a.clone();
@@ -343,13 +354,285 @@ impl<'a, E: JubjubEngine> Iterator for AnonStakeIterator<'a, E> {
     }
 }
 
-impl<'a, E: JubjubEngine> IntoIterator for AnonStake<'a, E> {
-    type Item = AnonStake<'a, E>;
-    type IntoIter = AnonStakeIterator<'a, E>;
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> IntoIterator for AnonStake<'a, E, H> {
+    type Item = AnonStake<'a, E, H>;
+    type IntoIter = AnonStakeIterator<'a, E, H>;
 
     fn into_iter(self) -> Self::IntoIter {
         AnonStakeIterator {
             a: self
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::thread_rng;
+    use zcash_primitives::jubjub::JubjubBls12;
+    use zcash_primitives::jubjub::fs::Fs;
+
+    use crate::circuit::anonstake_inputs::MerkleHeights;
+    use crate::circuit::exporter::R1csExporter;
+    use crate::circuit::hash::{Pedersen, Poseidon};
+    use crate::circuit::public_inputs::{fr_to_u64, PublicInputs, NUM_BP_INPUTS, NUM_INPUTS};
+    use crate::constants::binomial_constants::TauValue;
+    use crate::constants::Constants;
+
+    use super::*;
+
+    /*
+    init_testing gives a consistent witness, but the merkle roots are whatever the paths hash to,
+    so a wrong path or a wrong coin still satisfies the circuit with a different anchor
+    the negative tests therefore check the corrupted witness against the public inputs of the honest one,
+    like a verifier would
+    */
+
+    fn variants() -> Vec<(TauValue, bool)> {
+        vec![(TauValue::Tau20, true), (TauValue::Tau1500, false), (TauValue::Tau2990, false), (TauValue::Tau5000, false)]
+    }
+
+    //public inputs without the leading one
+    fn public_inputs<H: CircuitHash<Bls12>>(anonstake: AnonStake<Bls12, H>) -> Vec<Fr> {
+        let mut cs = R1csExporter::<Bls12>::new();
+        anonstake.synthesize(&mut cs).unwrap();
+
+        cs.inputs[1..].iter().map(|input| input.unwrap()).collect()
+    }
+
+    fn accepts<H: CircuitHash<Bls12>>(anonstake: AnonStake<Bls12, H>, expected: &[Fr]) -> bool {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        anonstake.synthesize(&mut cs).unwrap();
+
+        cs.is_satisfied() && cs.verify(expected)
+    }
+
+    fn all_variants_satisfied<H: CircuitHash<Bls12>>(merkle_height: usize) {
+        let jubjub = JubjubBls12::new();
+
+        for (tau, is_bp) in variants() {
+            let constants = Constants::<Bls12>::get(&jubjub, tau);
+            let anonstake = AnonStake::<Bls12, H>::init_testing(&constants, is_bp, merkle_height, 1);
+            let role = anonstake.pub_input.role.unwrap();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            anonstake.clone().synthesize(&mut cs).unwrap();
+
+            assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+            let expected = if is_bp { NUM_BP_INPUTS } else { NUM_INPUTS };
+            assert_eq!(cs.num_inputs(), expected + 1);
+
+            let inputs = PublicInputs::<Bls12>::from_inputs(&public_inputs(anonstake), is_bp).unwrap();
+            assert_eq!(inputs.role, role);
+            assert!(cs.verify(&inputs.to_inputs()));
+        }
+    }
+
+    #[test]
+    fn all_variants_satisfied_poseidon() {
+        all_variants_satisfied::<Poseidon>(10);
+    }
+
+    #[test]
+    fn all_variants_satisfied_pedersen() {
+        all_variants_satisfied::<Pedersen>(29);
+    }
+
+    #[test]
+    fn wrong_coin_value() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+        let expected = public_inputs(anonstake.clone());
+
+        let mut wrong = anonstake.clone();
+        wrong.aux_input.coin.value = Some(anonstake.aux_input.coin.value.unwrap() + 1);
+        assert!(!accepts(wrong, &expected));
+
+        assert!(accepts(anonstake, &expected));
+    }
+
+    #[test]
+    fn j_i_greater_than_num_selections() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        anonstake.clone().synthesize(&mut cs).unwrap();
+        let num_selections = fr_to_u64(&cs.get("calc number selections/calc number selections: allocate j/num")).unwrap();
+
+        let mut wrong = anonstake;
+        wrong.aux_input.j_i = Some(num_selections + 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        wrong.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("enforce j_i selected"));
+    }
+
+    #[test]
+    fn sn_not_inside_box() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+
+        //sn is one of the ends of the box, so it is in the sn tree. assert_nonzero has no witness for that
+        let mut wrong = anonstake.clone();
+        wrong.aux_input.sn_less_diff = Some(Fr::zero());
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        match wrong.synthesize(&mut cs) {
+            Err(SynthesisError::DivisionByZero) => {}
+            _ => panic!("sn_less_diff = 0 should not have a witness"),
+        }
+
+        //sn_less = sn + 1, so sn is outside of the box
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+
+        let mut wrong = anonstake.clone();
+        wrong.aux_input.sn_less_diff = Some(minus_one);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        wrong.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("serial number/compare sn sn_less"));
+    }
+
+    #[test]
+    fn wrong_fs_signature() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+        let expected = public_inputs(anonstake.clone());
+        assert!(accepts(anonstake.clone(), &expected));
+
+        //the second level of the forward secure tree signed with another key
+        //s is computed from the witness, so this is a valid signature of a key that full_pk does not commit to
+        let mut wrong = anonstake.clone();
+        wrong.aux_input.fs_sk[1] = Some(Fs::random(&mut thread_rng()));
+        assert!(!accepts(wrong, &expected));
+
+        //another nonce is just another valid signature
+        let mut nonce = anonstake;
+        nonce.aux_input.fs_rerandomize_public_key[1] = Some(Fs::random(&mut thread_rng()));
+        assert!(accepts(nonce, &expected));
+    }
+
+    #[test]
+    fn fs_tree_start_after_role() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+
+        let mut wrong = anonstake.clone();
+        wrong.aux_input.fs_tree_start = Some(anonstake.pub_input.role.unwrap().checked_add(1).unwrap());
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        wrong.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("forward secure tree/"));
+    }
+
+    //the prover picks the time the forward secure tree is evaluated at, it has to be role - fs_tree_start
+    //otherwise the key of a later period could sign for this role
+    #[test]
+    fn fs_time_other_than_role() {
+        let role: u64 = (1 << 40) + 7;
+        let fs_tree_start = role - 100;
+
+        for (time, satisfied) in vec![(100, true), (101, false), (99, false), (100 + (1 << 20), false)] {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let role_bits = boolean::u64_into_boolean_vec_le(cs.namespace(|| "role"), Some(role)).unwrap();
+            let fs_start_bits = boolean::u64_into_boolean_vec_le(cs.namespace(|| "fs start"), Some(fs_tree_start)).unwrap();
+
+            gadgets::fs_time_bits(cs.namespace(|| "time"), "time", &fs_start_bits, &role_bits, Some(time)).unwrap();
+            assert_eq!(cs.is_satisfied(), satisfied, "time {}", time);
+            if !satisfied {
+                assert!(cs.which_is_unsatisfied().unwrap().starts_with("time/time enforce time valid"));
+            }
+        }
+    }
+
+    fn wrong_merkle_path<H: CircuitHash<Bls12>>(merkle_height: usize) {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, H>::init_testing(&constants, false, merkle_height, 1);
+        let expected = public_inputs(anonstake.clone());
+        let rng = &mut thread_rng();
+
+        //the poseidon circuit only uses the poseidon paths and the pedersen circuit only the binary ones
+        let mut wrong = anonstake.clone();
+        if let Some(node) = wrong.aux_input.cm_merkle_path[merkle_height - 1].as_mut() {
+            node.0 = Fr::random(rng);
+        }
+        //the child at the position of the path is replaced by the current node, so change a different one
+        if let Some(node) = wrong.aux_input.cm_poseidon_path[merkle_height - 1].as_mut() {
+            node.0[(node.1 as usize + 1) % 8] = Fr::random(rng);
+        }
+        assert!(!accepts(wrong, &expected));
+
+        let mut wrong = anonstake.clone();
+        if let Some(node) = wrong.aux_input.sn_merkle_path[0].as_mut() {
+            node.0 = Fr::random(rng);
+        }
+        if let Some(node) = wrong.aux_input.sn_poseidon_path[0].as_mut() {
+            node.0[(node.1 as usize + 1) % 8] = Fr::random(rng);
+        }
+        assert!(!accepts(wrong, &expected));
+    }
+
+    #[test]
+    fn wrong_merkle_path_poseidon() {
+        wrong_merkle_path::<Poseidon>(10);
+    }
+
+    #[test]
+    fn wrong_merkle_path_pedersen() {
+        wrong_merkle_path::<Pedersen>(29);
+    }
+
+    //the cm and sn trees can have different heights, the empty circuit (used for params) has to have the same shape
+    fn different_heights<H: CircuitHash<Bls12>>(heights: MerkleHeights) {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let anonstake = AnonStake::<Bls12, H>::init_testing_with_rng(&constants, false, heights, 1, &mut thread_rng());
+        assert_eq!(anonstake.aux_input.cm_merkle_path.len(), heights.cm);
+        assert_eq!(anonstake.aux_input.sn_poseidon_path.len(), heights.sn);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        anonstake.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+        let mut empty_cs = R1csExporter::<Bls12>::new();
+        AnonStake::<Bls12, H>::init_empty_with_heights(&constants, false, heights).synthesize(&mut empty_cs).unwrap();
+        assert_eq!(empty_cs.constraints.len(), cs.num_constraints());
+    }
+
+    #[test]
+    fn different_heights_poseidon() {
+        different_heights::<Poseidon>(MerkleHeights::new(4, 7));
+    }
+
+    #[test]
+    fn different_heights_pedersen() {
+        different_heights::<Pedersen>(MerkleHeights::new(12, 5));
+    }
+
+    //tsn and priority hash the packed j_i with poseidon, so different selections have to give different tsns
+    #[test]
+    fn tsn_depends_on_j_i() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+        let mut selections = AnonStake::<Bls12, Poseidon>::init_testing(&constants, true, 10, 1).into_iter();
+
+        let first = PublicInputs::<Bls12>::from_inputs(&public_inputs(selections.next().unwrap()), true).unwrap();
+        let second = PublicInputs::<Bls12>::from_inputs(&public_inputs(selections.next().unwrap()), true).unwrap();
+
+        //j_i = 1 and j_i = 2 have the same number of set bits
+        assert_ne!(first.tsn, second.tsn);
+        assert_ne!(first.block_proposer.unwrap().priority, second.block_proposer.unwrap().priority);
+        assert_eq!(first.h, second.h);
+    }
+}
@@ -0,0 +1,328 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::gadgets::boolean;
+use bellman::gadgets::boolean::Boolean;
+use bellman::gadgets::num::{AllocatedNum, Num};
+use ff::{Field, PrimeField};
+use rand::thread_rng;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::{AnonStake, comparison};
+use crate::circuit::hash::CircuitHash;
+use crate::circuit::anonstake_inputs::{Coin, MerkleHeights};
+use crate::constants::{Constants, PrfDomain};
+use crate::native;
+
+/*
+sortition on the total stake of several coins owned by the same full_pk
+every coin is proven to be in the commitment tree and to not be spent, the values are added up in the circuit
+and calc_num_selections runs on the bits of the total, so the proof only reveals one set of tsns
+the total is range checked to be below 2^max_value
+
+every coin exposes its own cm anchor and sn anchor, the verifier has to check all of them
+the sns stay private, a public sn would link the sortition proof to the later spend of the coin
+the coins have to be ordered by strictly increasing sn, so the same coin can not be counted several times
+(the sn box does not care how often a coin is used)
+tsn is computed from the rho of the first coin, the order makes the first coin fixed for a set of coins,
+so the prover can not pick another first coin to get a second set of tsns for the same stake
+
+public inputs (without the leading one):
+role, (cm anchor, sn anchor) for every coin, seed, tsn, h_sig, h
+*/
+#[derive(Clone)]
+pub struct StakeCoin<E: JubjubEngine> {
+    pub coin: Coin<E>,
+    //only used for non-poseidon hash function version
+    pub cm_merkle_path: Vec<Option<(E::Fr, bool)>>,
+    //only used for non-poseidon hash function version
+    pub sn_merkle_path: Vec<Option<(E::Fr, bool)>>,
+    pub cm_poseidon_path: Vec<Option<([E::Fr; 8], u8)>>,
+    pub sn_poseidon_path: Vec<Option<([E::Fr; 8], u8)>>,
+    pub sn_less_diff: Option<E::Fr>,
+    pub sn_plus_diff: Option<E::Fr>,
+}
+
+#[derive(Clone)]
+pub struct MultiCoin<'a, E: JubjubEngine, H: CircuitHash<E>> {
+    //coin, merkle paths and sn box of base are ignored, everything else is shared by all coins
+    pub base: AnonStake<'a, E, H>,
+    pub coins: Vec<StakeCoin<E>>,
+}
+
+impl<E: JubjubEngine> StakeCoin<E> {
+    //takes the coin specific parts of an AnonStake witness
+    pub fn from_anonstake<H: CircuitHash<E>>(anonstake: &AnonStake<E, H>) -> StakeCoin<E> {
+        StakeCoin {
+            coin: anonstake.aux_input.coin.clone(),
+            cm_merkle_path: anonstake.aux_input.cm_merkle_path.clone(),
+            sn_merkle_path: anonstake.aux_input.sn_merkle_path.clone(),
+            cm_poseidon_path: anonstake.aux_input.cm_poseidon_path.clone(),
+            sn_poseidon_path: anonstake.aux_input.sn_poseidon_path.clone(),
+            sn_less_diff: anonstake.aux_input.sn_less_diff,
+            sn_plus_diff: anonstake.aux_input.sn_plus_diff,
+        }
+    }
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> MultiCoin<'a, E, H> {
+    //the gadgets read the coin, merkle paths and sn box from aux_input, so make a copy of base for every coin
+    fn coin_view(&self, i: usize) -> AnonStake<'a, E, H> {
+        let coin = &self.coins[i];
+
+        let mut view = self.base.clone();
+        view.aux_input.coin = coin.coin.clone();
+        view.aux_input.cm_merkle_path = coin.cm_merkle_path.clone();
+        view.aux_input.sn_merkle_path = coin.sn_merkle_path.clone();
+        view.aux_input.cm_poseidon_path = coin.cm_poseidon_path.clone();
+        view.aux_input.sn_poseidon_path = coin.sn_poseidon_path.clone();
+        view.aux_input.sn_less_diff = coin.sn_less_diff;
+        view.aux_input.sn_plus_diff = coin.sn_plus_diff;
+
+        view
+    }
+
+    pub fn total_value(&self) -> Option<u64> {
+        let mut total: u64 = 0;
+        for coin in &self.coins {
+            total = total.checked_add(coin.coin.value?)?;
+        }
+
+        Some(total)
+    }
+
+    pub fn init_empty(constants: &'a Constants<E>, merkle_height: usize, num_coins: usize) -> MultiCoin<'a, E, H> {
+        Self::init_empty_with_heights(constants, MerkleHeights::from(merkle_height), num_coins)
+    }
+
+    pub fn init_empty_with_heights(constants: &'a Constants<E>, heights: MerkleHeights, num_coins: usize) -> MultiCoin<'a, E, H> {
+        let base = AnonStake::init_empty_with_heights(constants, false, heights);
+        let coins = vec![StakeCoin::from_anonstake(&base); num_coins];
+
+        MultiCoin { base, coins }
+    }
+
+    pub fn init_testing(constants: &'a Constants<E>, merkle_height: usize, num_coins: usize, j_i: u64) -> MultiCoin<'a, E, H> {
+        Self::init_testing_with_heights(constants, MerkleHeights::from(merkle_height), num_coins, j_i)
+    }
+
+    //splits the 2^59 stake of AnonStake::init_testing over num_coins coins, ordered by sn
+    pub fn init_testing_with_heights(constants: &'a Constants<E>, heights: MerkleHeights, num_coins: usize, j_i: u64) -> MultiCoin<'a, E, H> {
+        let rng = &mut thread_rng();
+        let base = AnonStake::init_testing_with_rng(constants, false, heights, j_i, rng);
+
+        let mut coins: Vec<StakeCoin<E>> = (0..num_coins).map(|_| {
+            let mut coin = StakeCoin::from_anonstake(&AnonStake::<E, H>::init_testing_with_rng(constants, false, heights, j_i, rng));
+            coin.coin = Coin {
+                value: Some((1u64 << 59) / num_coins as u64),
+                rho: Some(E::Fr::random(rng)),
+                s: Some(E::Fs::random(rng)),
+            };
+
+            coin
+        }).collect();
+
+        let a_sk = base.aux_input.a_sk.unwrap();
+        coins.sort_by_key(|coin| native::sn(constants, &a_sk, &coin.coin.rho.unwrap()).into_repr());
+
+        MultiCoin { base, coins }
+    }
+
+    pub fn num_coins(&self) -> usize {
+        self.coins.len()
+    }
+}
+
+//a != b, by showing that a - b has an inverse
+//the inverse of zero is assigned zero so that a witness with a == b gives an unsatisfied circuit and not an error
+fn enforce_distinct<E, CS>(mut cs: CS, a: &AllocatedNum<E>, b: &AllocatedNum<E>) -> Result<(), SynthesisError>
+    where E: JubjubEngine, CS: ConstraintSystem<E>
+{
+    let inv = AllocatedNum::alloc(cs.namespace(|| "inverse of difference"), || {
+        let mut diff = a.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        diff.sub_assign(&b.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+        Ok(diff.inverse().unwrap_or(E::Fr::zero()))
+    })?;
+
+    cs.enforce(|| "difference is nonzero",
+               |lc| lc + a.get_variable() - b.get_variable(),
+               |lc| lc + inv.get_variable(),
+               |lc| lc + CS::one());
+
+    Ok(())
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Circuit<E> for MultiCoin<'a, E, H> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let base = &self.base;
+
+        let a_sk = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || base.aux_input.a_sk.ok_or(SynthesisError::AssignmentMissing))?;
+
+        let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero"), || Ok(E::Fr::zero()))?;
+
+        let a_pk = base.prf(cs.namespace(|| "calc a_pk"), "calc a_pk", a_sk.clone(), allocated_zero, PrfDomain::Addr)?;
+
+        let (role, role_bits, fs_start_bits, fs_pk) = base.forward_secure_tree(
+            cs.namespace(|| "forward secure tree"), "forward secure tree")?;
+
+        let full_pk = base.participant_full_pk(cs.namespace(|| "full_pk"), fs_start_bits, fs_pk, a_pk)?;
+
+        let mut total = Num::<E>::zero();
+        let mut first_rho = None;
+        let mut prev_sn: Option<(AllocatedNum<E>, Vec<Boolean>)> = None;
+
+        for i in 0..self.num_coins() {
+            let view = self.coin_view(i);
+            let cs = &mut cs.namespace(|| format!("coin {}", i));
+
+            let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"),
+                                          || view.aux_input.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
+
+            let (cm, value, _) = view.constrain_coin_commitment(
+                cs.namespace(|| "coin commitment computation"),
+                "coin commitment computation", full_pk.clone(), rho.clone())?;
+            view.coin_commitment_membership(cs.namespace(|| "coin commitment membership"), "coin commitment membership", cm)?;
+
+            let sn = view.serial_number_checks(cs.namespace(|| "serial number"), &a_sk, &rho)?;
+            let sn_bits = sn.to_bits_le_strict(cs.namespace(|| "sn bits"))?;
+
+            //sn > sn of the previous coin, i.e. previous sn <= sn and sn != previous sn
+            if let Some((prev_sn, prev_sn_bits)) = &prev_sn {
+                comparison::leq_not_fixed(cs.namespace(|| "previous sn not after sn"), "previous sn not after sn", prev_sn_bits, &sn_bits)?;
+                enforce_distinct(cs.namespace(|| "sn differs from previous sn"), &sn, prev_sn)?;
+            }
+            prev_sn = Some((sn, sn_bits));
+
+            total = total + value;
+
+            if first_rho.is_none() {
+                first_rho = Some(rho);
+            }
+        }
+
+        let rho = first_rho.ok_or(SynthesisError::Unsatisfiable)?;
+
+        //range check: the total has to fit in max_value bits
+        let total_bits = {
+            let mut bits = boolean::u64_into_boolean_vec_le(
+                cs.namespace(|| "total value bits"),
+                self.total_value(),
+            )?;
+            bits.truncate(base.constants.max_value);
+
+            let mut num = Num::<E>::zero();
+            let mut coeff = E::Fr::one();
+            for bit in &bits {
+                num = num.add_bool_with_coeff(CS::one(), bit, coeff);
+                coeff.double();
+            }
+
+            cs.enforce(|| "total value fits in max_value bits",
+                       |_| total.lc(E::Fr::one()),
+                       |lc| lc + CS::one(),
+                       |_| num.lc(E::Fr::one()));
+
+            bits
+        };
+
+        let seed_sel = AllocatedNum::alloc(cs.namespace(|| "allocate seed_sel"), || base.pub_input.seed.ok_or(SynthesisError::AssignmentMissing))?;
+        seed_sel.inputize(cs.namespace(|| "inputize seed_sel"))?;
+
+        let seed_sel_bits = seed_sel.to_bits_le_strict(cs.namespace(|| "bits of seed_sel"))?;
+
+        let hash_role_seed = {
+            let mut a = role_bits.clone();
+            a.extend(seed_sel_bits);
+
+            base.crh(cs.namespace(|| "hash role seed_sel"), "hash role and seed", a.as_slice())?
+        };
+
+        let num_selections = base.calc_num_selections(cs.namespace(|| "calc number selections"), "calc number selections", total_bits.as_slice(), &hash_role_seed, &a_sk)?;
+
+        let (j_i, j_i_bits) = base.alloc_j_i(cs.namespace(|| "allocate j_i"), base.aux_input.j_i)?;
+
+        base.enforce_selected(cs.namespace(|| "enforce j_i selected"), &num_selections, &j_i_bits)?;
+
+        let tsn = base.calc_tsn(cs.namespace(|| "tsn"), &a_sk, &rho, &role, &role_bits, &j_i, &j_i_bits)?;
+        tsn.inputize(cs.namespace(|| "inputize tsn"))?;
+
+        let h_sig = AllocatedNum::alloc(cs.namespace(|| "allocate h_sig"), || base.pub_input.h_sig.ok_or(SynthesisError::AssignmentMissing))?;
+        h_sig.inputize(cs.namespace(|| "inputize h_sig"))?;
+
+        let h = base.calc_h(cs.namespace(|| "h"), &a_sk, &h_sig, &role, &role_bits)?;
+        h.inputize(cs.namespace(|| "inputize h"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::Bls12;
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::hash::Poseidon;
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    //the sns are not public inputs: role, 2 anchors per coin, seed, tsn, h_sig, h
+    #[test]
+    fn ordered_coins() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let multi_coin = MultiCoin::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_coin.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+        assert_eq!(cs.num_inputs(), 1 + 1 + 2 * 3 + 4);
+    }
+
+    //the same coin twice would count its stake twice
+    #[test]
+    fn duplicate_coin() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let mut multi_coin = MultiCoin::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+        multi_coin.coins[1] = multi_coin.coins[0].clone();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_coin.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("coin 1/sn differs from previous sn"));
+    }
+
+    //another order would give another first coin and so another set of tsns for the same stake
+    #[test]
+    fn unordered_coins() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let mut multi_coin = MultiCoin::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+        let a_sk = multi_coin.base.aux_input.a_sk.unwrap();
+        multi_coin.coins.swap(0, 1);
+
+        let sn = |coin: &StakeCoin<Bls12>| native::sn(&constants, &a_sk, &coin.coin.rho.unwrap()).into_repr();
+        assert!(sn(&multi_coin.coins[0]) > sn(&multi_coin.coins[1]));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_coin.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("coin 1/previous sn not after sn"));
+    }
+
+    #[test]
+    fn separate_heights() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let multi_coin = MultiCoin::<Bls12, Poseidon>::init_testing_with_heights(&constants, MerkleHeights::new(3, 5), 2, 1);
+
+        for coin in &multi_coin.coins {
+            assert_eq!(coin.cm_poseidon_path.len(), 3);
+            assert_eq!(coin.sn_poseidon_path.len(), 5);
+        }
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_coin.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+    }
+}
@@ -0,0 +1,264 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::gadgets::num::AllocatedNum;
+use ff::Field;
+use rand::{Rng, thread_rng};
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::{AnonStake, comparison};
+use crate::circuit::anonstake_inputs::MerkleHeights;
+use crate::circuit::hash::CircuitHash;
+use crate::constants::{Constants, PrfDomain};
+
+/*
+one proof for several roles of the same participant, e.g. the repeated next-vote steps of a round
+a_pk, the forward secure tree, the coin commitment and its membership and the sn non-membership
+do not depend on the role, so they are only done once
+for every role the circuit computes num_selections, checks j_i < num_selections and exposes (tsn, h)
+
+the forward secure tree is evaluated at roles[0], the other roles have to be inside the time window of the key,
+i.e. fs_tree_start <= role < fs_tree_start + 2^36, which is checked for every role with its own bits
+roles[0] also has to be the earliest role, otherwise a key that is only valid later could prove selection for earlier roles
+all roles share tau (the binomial constants), so they have to be for steps with the same committee size
+
+public inputs (without the leading one):
+roles[0], cm anchor, seed, sn anchor, h_sig, roles[1..], and (tsn, h) for every role
+*/
+#[derive(Clone)]
+pub struct MultiRole<'a, E: JubjubEngine, H: CircuitHash<E>> {
+    //role and j_i of base are ignored, everything else is shared by all roles
+    pub base: AnonStake<'a, E, H>,
+    pub roles: Vec<Option<u64>>,
+    pub j_i: Vec<Option<u64>>,
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> MultiRole<'a, E, H> {
+    //the gadgets read the role from pub_input, so make a copy of base for every role
+    fn role_view(&self, k: usize) -> AnonStake<'a, E, H> {
+        let mut view = self.base.clone();
+        view.pub_input.role = self.roles[k];
+        view.aux_input.j_i = self.j_i[k];
+
+        view
+    }
+
+    pub fn init_empty(constants: &'a Constants<E>, merkle_height: usize, num_roles: usize) -> MultiRole<'a, E, H> {
+        Self::init_empty_with_heights(constants, MerkleHeights::from(merkle_height), num_roles)
+    }
+
+    pub fn init_empty_with_heights(constants: &'a Constants<E>, heights: MerkleHeights, num_roles: usize) -> MultiRole<'a, E, H> {
+        MultiRole {
+            base: AnonStake::init_empty_with_heights(constants, false, heights),
+            roles: vec![None; num_roles],
+            j_i: vec![None; num_roles],
+        }
+    }
+
+    pub fn init_pure_random(constants: &'a Constants<E>, merkle_height: usize, num_roles: usize) -> MultiRole<'a, E, H> {
+        Self::init_pure_random_with_heights(constants, MerkleHeights::from(merkle_height), num_roles)
+    }
+
+    pub fn init_pure_random_with_heights(constants: &'a Constants<E>, heights: MerkleHeights, num_roles: usize) -> MultiRole<'a, E, H> {
+        let rng = &mut thread_rng();
+
+        MultiRole {
+            base: AnonStake::init_pure_random_with_heights(constants, false, heights),
+            roles: (0..num_roles).map(|_| Some(rng.gen())).collect(),
+            j_i: vec![Some(1); num_roles],
+        }
+    }
+
+    pub fn init_testing(constants: &'a Constants<E>, merkle_height: usize, num_roles: usize, j_i: u64) -> MultiRole<'a, E, H> {
+        Self::init_testing_with_heights(constants, MerkleHeights::from(merkle_height), num_roles, j_i)
+    }
+
+    //consecutive roles starting at the role picked by AnonStake::init_testing
+    pub fn init_testing_with_heights(constants: &'a Constants<E>, heights: MerkleHeights, num_roles: usize, j_i: u64) -> MultiRole<'a, E, H> {
+        let base = AnonStake::init_testing_with_rng(constants, false, heights, j_i, &mut thread_rng());
+        let first = base.pub_input.role.unwrap();
+
+        MultiRole {
+            base,
+            roles: (0..num_roles).map(|k| Some(first.wrapping_add(k as u64))).collect(),
+            j_i: vec![Some(j_i); num_roles],
+        }
+    }
+
+    pub fn num_roles(&self) -> usize {
+        self.roles.len()
+    }
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Circuit<E> for MultiRole<'a, E, H> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let first = self.role_view(0);
+
+        let a_sk = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || first.aux_input.a_sk.ok_or(SynthesisError::AssignmentMissing))?;
+
+        let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero"), || Ok(E::Fr::zero()))?;
+
+        let a_pk = first.prf(cs.namespace(|| "calc a_pk"), "calc a_pk", a_sk.clone(), allocated_zero, PrfDomain::Addr)?;
+
+        let (first_role, first_role_bits, fs_start_bits, fs_pk) = first.forward_secure_tree(
+            cs.namespace(|| "forward secure tree"), "forward secure tree")?;
+
+        let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"),
+                                      || first.aux_input.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
+
+        let full_pk = first.participant_full_pk(cs.namespace(|| "full_pk"), fs_start_bits.clone(), fs_pk, a_pk)?;
+
+        let (cm, _value, value_bits) = first.constrain_coin_commitment(
+            cs.namespace(|| "coin commitment computation"),
+            "coin commitment computation", full_pk, rho.clone())?;
+        first.coin_commitment_membership(cs.namespace(|| "coin commitment membership"), "coin commitment membership", cm)?;
+
+        let seed_sel = AllocatedNum::alloc(cs.namespace(|| "allocate seed_sel"), || first.pub_input.seed.ok_or(SynthesisError::AssignmentMissing))?;
+        seed_sel.inputize(cs.namespace(|| "inputize seed_sel"))?;
+
+        let seed_sel_bits = seed_sel.to_bits_le_strict(cs.namespace(|| "bits of seed_sel"))?;
+
+        first.serial_number_checks(cs.namespace(|| "serial number"), &a_sk, &rho)?;
+
+        let h_sig = AllocatedNum::alloc(cs.namespace(|| "allocate h_sig"), || first.pub_input.h_sig.ok_or(SynthesisError::AssignmentMissing))?;
+        h_sig.inputize(cs.namespace(|| "inputize h_sig"))?;
+
+        let mut roles = vec![(first_role, first_role_bits)];
+        for k in 1..self.num_roles() {
+            let view = self.role_view(k);
+            let cs = &mut cs.namespace(|| format!("role {}", k));
+
+            let (role, role_bits) = view.get_role_bits(cs.namespace(|| "get role"), "get role")?;
+
+            //the forward secure tree was evaluated at roles[0], the key also has to be valid at this role
+            view.fs_time_window(cs.namespace(|| "fs time window"), "fs time window", &fs_start_bits, &role_bits)?;
+
+            //and this role can not be before roles[0], the key of roles[0] may be from after the keys of earlier roles were erased
+            comparison::leq_not_fixed(cs.namespace(|| "first role not after role"), "first role not after role", &roles[0].1, &role_bits)?;
+
+            roles.push((role, role_bits));
+        }
+
+        for (k, (role, role_bits)) in roles.iter().enumerate() {
+            let view = self.role_view(k);
+            let cs = &mut cs.namespace(|| format!("selection {}", k));
+
+            let hash_role_seed = {
+                let mut a = role_bits.clone();
+                a.extend(seed_sel_bits.clone());
+
+                view.crh(cs.namespace(|| "hash role seed_sel"), "hash role and seed", a.as_slice())?
+            };
+
+            let num_selections = view.calc_num_selections(cs.namespace(|| "calc number selections"), "calc number selections", value_bits.as_slice(), &hash_role_seed, &a_sk)?;
+
+            let (j_i, j_i_bits) = view.alloc_j_i(cs.namespace(|| "allocate j_i"), view.aux_input.j_i)?;
+
+            view.enforce_selected(cs.namespace(|| "enforce j_i selected"), &num_selections, &j_i_bits)?;
+
+            let tsn = view.calc_tsn(cs.namespace(|| "tsn"), &a_sk, &rho, role, role_bits, &j_i, &j_i_bits)?;
+            tsn.inputize(cs.namespace(|| "inputize tsn"))?;
+
+            let h = view.calc_h(cs.namespace(|| "h"), &a_sk, &h_sig, role, role_bits)?;
+            h.inputize(cs.namespace(|| "inputize h"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::Bls12;
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::hash::Poseidon;
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    #[test]
+    fn roles_inside_window() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let multi_role = MultiRole::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+    }
+
+    //the key is valid for roles[0], but role 2 is after the 2^36 steps the forward secure tree covers
+    #[test]
+    fn role_outside_window() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let mut multi_role = MultiRole::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+
+        let fs_tree_start = multi_role.base.aux_input.fs_tree_start.unwrap();
+        multi_role.roles[2] = Some(fs_tree_start.wrapping_add(1 << 36));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("role 2/fs time window"));
+    }
+
+    //before the start of the key
+    #[test]
+    fn role_before_window() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let mut multi_role = MultiRole::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+
+        let fs_tree_start = multi_role.base.aux_input.fs_tree_start.unwrap();
+        multi_role.roles[1] = Some(fs_tree_start.wrapping_sub(1));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("role 1/fs time window"));
+    }
+
+    //the cm and sn trees keep their own heights, the empty circuit (for the params) has the same shape
+    #[test]
+    fn separate_heights() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let heights = MerkleHeights::new(3, 5);
+
+        let multi_role = MultiRole::<Bls12, Poseidon>::init_testing_with_heights(&constants, heights, 2, 1);
+        assert_eq!(multi_role.base.aux_input.cm_poseidon_path.len(), 3);
+        assert_eq!(multi_role.base.aux_input.sn_poseidon_path.len(), 5);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+        let mut empty_cs = TestConstraintSystem::<Bls12>::new();
+        let multi_role = MultiRole::<Bls12, Poseidon>::init_pure_random_with_heights(&constants, heights, 2);
+        multi_role.synthesize(&mut empty_cs).unwrap();
+        assert_eq!(empty_cs.num_constraints(), cs.num_constraints());
+    }
+
+    //a key that is valid at roles[0] must not prove selection for an earlier role, even inside the time window
+    #[test]
+    fn role_before_first_role() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let mut multi_role = MultiRole::<Bls12, Poseidon>::init_testing(&constants, 4, 3, 1);
+
+        multi_role.base.aux_input.fs_tree_start = Some(1000);
+        multi_role.roles = vec![Some(1005), Some(1008), Some(1010)];
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.clone().synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+        multi_role.roles[1] = Some(1002);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        multi_role.synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert!(cs.which_is_unsatisfied().unwrap().starts_with("role 1/first role not after role"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use ff::ScalarEngine;
+
+/*
+constraint system that only counts
+every namespace is a node in a tree, and each constraint, aux and input is counted in the namespace it was
+made in and in all the namespaces above it, so a node always holds the total of everything below it
+witness values are never computed, so the circuits can be profiled with init_empty like when generating parameters
+*/
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProfileCounts {
+    pub constraints: usize,
+    pub aux: usize,
+    pub inputs: usize,
+}
+
+pub struct ProfileNode {
+    pub name: String,
+    pub depth: usize,
+    pub children: Vec<usize>,
+    //totals, including all children
+    pub counts: ProfileCounts,
+}
+
+pub struct ProfilingConstraintSystem<E: ScalarEngine> {
+    //nodes[0] is the root
+    pub nodes: Vec<ProfileNode>,
+    lookup: HashMap<(usize, String), usize>,
+    stack: Vec<usize>,
+    num_aux: usize,
+    num_inputs: usize,
+    _marker: std::marker::PhantomData<E>,
+}
+
+impl<E: ScalarEngine> ProfilingConstraintSystem<E> {
+    pub fn new() -> ProfilingConstraintSystem<E> {
+        let root = ProfileNode {
+            name: "total".to_owned(),
+            depth: 0,
+            children: vec![],
+            //the input for CS::one()
+            counts: ProfileCounts { constraints: 0, aux: 0, inputs: 1 },
+        };
+
+        ProfilingConstraintSystem {
+            nodes: vec![root],
+            lookup: HashMap::new(),
+            stack: vec![0],
+            num_aux: 0,
+            num_inputs: 1,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn total(&self) -> ProfileCounts {
+        self.nodes[0].counts
+    }
+
+    //counts of the namespace itself, without its children
+    pub fn own_counts(&self, node: usize) -> ProfileCounts {
+        let mut counts = self.nodes[node].counts;
+
+        for &child in &self.nodes[node].children {
+            counts.constraints -= self.nodes[child].counts.constraints;
+            counts.aux -= self.nodes[child].counts.aux;
+            counts.inputs -= self.nodes[child].counts.inputs;
+        }
+
+        counts
+    }
+
+    //nodes down to max_depth in the order they were first entered, with their full path
+    pub fn rows(&self, max_depth: usize) -> Vec<(String, usize)> {
+        let mut rows = vec![];
+        let mut todo = vec![(0, self.nodes[0].name.clone())];
+
+        while let Some((node, path)) = todo.pop() {
+            if self.nodes[node].depth < max_depth {
+                for &child in self.nodes[node].children.iter().rev() {
+                    todo.push((child, format!("{}/{}", path, self.nodes[child].name)));
+                }
+            }
+
+            rows.push((path, node));
+        }
+
+        rows
+    }
+
+    pub fn print_tree(&self, max_depth: usize) {
+        for (_, node) in self.rows(max_depth) {
+            let n = &self.nodes[node];
+            println!("{}{}: constraints: {}, aux: {}, inputs: {}",
+                     "  ".repeat(n.depth), n.name, n.counts.constraints, n.counts.aux, n.counts.inputs);
+        }
+    }
+
+    pub fn write_csv<W: Write>(&self, mut writer: W, variant: &str, max_depth: usize) -> io::Result<()> {
+        writer.write_all(b"variant,namespace,depth,constraints,aux,inputs,own constraints\n")?;
+
+        for (path, node) in self.rows(max_depth) {
+            let n = &self.nodes[node];
+            //namespaces can contain commas
+            let path = path.replace('"', "'");
+
+            writer.write_all(format!("{},\"{}\",{},{},{},{},{}\n", variant, path, n.depth,
+                                     n.counts.constraints, n.counts.aux, n.counts.inputs,
+                                     self.own_counts(node).constraints).as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    fn count<F: Fn(&mut ProfileCounts)>(&mut self, f: F) {
+        for &node in &self.stack {
+            f(&mut self.nodes[node].counts);
+        }
+    }
+}
+
+impl<E: ScalarEngine> ConstraintSystem<E> for ProfilingConstraintSystem<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let index = self.num_aux;
+        self.num_aux += 1;
+        self.count(|counts| counts.aux += 1);
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let index = self.num_inputs;
+        self.num_inputs += 1;
+        self.count(|counts| counts.inputs += 1);
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+              LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+              LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+              LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>
+    {
+        self.count(|counts| counts.constraints += 1);
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+        where NR: Into<String>, N: FnOnce() -> NR
+    {
+        let parent = *self.stack.last().unwrap();
+        let name: String = name_fn().into();
+
+        let node = match self.lookup.get(&(parent, name.clone())) {
+            Some(&node) => node,
+            None => {
+                let node = self.nodes.len();
+                self.nodes.push(ProfileNode {
+                    name: name.clone(),
+                    depth: self.nodes[parent].depth + 1,
+                    children: vec![],
+                    counts: ProfileCounts::default(),
+                });
+
+                self.nodes[parent].children.push(node);
+                self.lookup.insert((parent, name), node);
+                node
+            }
+        };
+
+        self.stack.push(node);
+    }
+
+    fn pop_namespace(&mut self) {
+        assert!(self.stack.len() > 1, "popped the root namespace");
+        self.stack.pop();
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::Circuit;
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::Bls12;
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::AnonStake;
+    use crate::circuit::hash::Poseidon;
+    use crate::constants::binomial_constants::TauValue;
+    use crate::constants::Constants;
+
+    use super::*;
+
+    #[test]
+    fn totals_match_test_constraint_system() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let mut cs = ProfilingConstraintSystem::<Bls12>::new();
+        AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 10).synthesize(&mut cs).unwrap();
+
+        let mut test_cs = TestConstraintSystem::<Bls12>::new();
+        AnonStake::<Bls12, Poseidon>::init_pure_random(&constants, false, 10).synthesize(&mut test_cs).unwrap();
+
+        let total = cs.total();
+        assert_eq!(total.constraints, test_cs.num_constraints());
+        assert_eq!(total.aux, test_cs.num_aux());
+        assert_eq!(total.inputs, test_cs.num_inputs());
+
+        //the top level namespaces add up to the total
+        let top: usize = cs.nodes[0].children.iter().map(|&child| cs.nodes[child].counts.constraints).sum();
+        assert_eq!(top + cs.own_counts(0).constraints, total.constraints);
+
+        let fs_tree = cs.nodes[0].children.iter().find(|&&child| cs.nodes[child].name == "forward secure tree");
+        assert!(cs.nodes[*fs_tree.unwrap()].counts.constraints > 0);
+    }
+}
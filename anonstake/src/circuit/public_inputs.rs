@@ -0,0 +1,190 @@
+use ff::{PrimeField, PrimeFieldRepr};
+use zcash_primitives::jubjub::JubjubEngine;
+
+/*
+public inputs of the AnonStake circuit, in the order they are inputized by synthesize:
+role, cm anchor, seed, sn anchor, tsn, h_sig, h
+and for the block proposer circuit additionally: priority, r, seed_comp
+*/
+#[derive(Clone)]
+pub struct PublicInputs<E: JubjubEngine> {
+    pub role: u64,
+    pub cm_anchor: E::Fr,
+    pub seed: E::Fr,
+    pub sn_anchor: E::Fr,
+    pub tsn: E::Fr,
+    pub h_sig: E::Fr,
+    pub h: E::Fr,
+    pub block_proposer: Option<BlockProposerPublicInputs<E>>,
+}
+
+#[derive(Clone)]
+pub struct BlockProposerPublicInputs<E: JubjubEngine> {
+    pub priority: E::Fr,
+    pub r: u64,
+    pub seed_comp: E::Fr,
+}
+
+pub const NUM_INPUTS: usize = 7;
+pub const NUM_BP_INPUTS: usize = 10;
+
+pub fn fr_to_u64<F: PrimeField>(value: &F) -> Option<u64> {
+    let repr = value.into_repr();
+    let limbs = repr.as_ref();
+
+    for limb in &limbs[1..] {
+        if *limb != 0 {
+            return None;
+        }
+    }
+
+    Some(limbs[0])
+}
+
+pub fn u64_to_fr<F: PrimeField>(value: u64) -> F {
+    F::from_repr(F::Repr::from(value)).expect("u64 always fits in the field")
+}
+
+//same format as the Display of the repr, i.e. 0x followed by 64 big endian hex digits
+pub fn fr_to_hex<F: PrimeField>(value: &F) -> String {
+    format!("{}", value.into_repr())
+}
+
+//reads what fr_to_hex prints, the 0x and leading zeros are optional
+pub fn fr_from_hex<F: PrimeField>(hex: &str) -> Option<F> {
+    let hex = hex.trim_start_matches("0x");
+    if hex.is_empty() || hex.len() > 64 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 32];
+    let offset = 64 - hex.len();
+    for (i, c) in hex.chars().enumerate() {
+        let digit = c.to_digit(16)? as u8;
+        let pos = offset + i;
+        bytes[pos / 2] |= if pos % 2 == 0 { digit << 4 } else { digit };
+    }
+
+    let mut repr = F::Repr::default();
+    repr.read_be(&bytes[..]).ok()?;
+    F::from_repr(repr).ok()
+}
+
+impl<E: JubjubEngine> PublicInputs<E> {
+    //input should not contain the leading one, i.e. the slice passed to verify_proof
+    pub fn from_inputs(input: &[E::Fr], is_bp: bool) -> Option<PublicInputs<E>> {
+        let expected = if is_bp { NUM_BP_INPUTS } else { NUM_INPUTS };
+        if input.len() != expected {
+            return None;
+        }
+
+        let block_proposer = if is_bp {
+            Some(BlockProposerPublicInputs {
+                priority: input[7],
+                r: fr_to_u64(&input[8])?,
+                seed_comp: input[9],
+            })
+        } else {
+            None
+        };
+
+        Some(PublicInputs {
+            role: fr_to_u64(&input[0])?,
+            cm_anchor: input[1],
+            seed: input[2],
+            sn_anchor: input[3],
+            tsn: input[4],
+            h_sig: input[5],
+            h: input[6],
+            block_proposer,
+        })
+    }
+
+    pub fn to_inputs(&self) -> Vec<E::Fr> {
+        let mut input = vec![u64_to_fr(self.role), self.cm_anchor, self.seed, self.sn_anchor, self.tsn, self.h_sig, self.h];
+
+        if let Some(bp) = &self.block_proposer {
+            input.push(bp.priority);
+            input.push(u64_to_fr(bp.r));
+            input.push(bp.seed_comp);
+        }
+
+        input
+    }
+
+    pub fn is_bp(&self) -> bool {
+        self.block_proposer.is_some()
+    }
+}
+
+/*
+public inputs of the MultiRole circuit:
+roles[0], cm anchor, seed, sn anchor, h_sig, roles[1..], and (tsn, h) for every role
+*/
+#[derive(Clone)]
+pub struct MultiRolePublicInputs<E: JubjubEngine> {
+    pub roles: Vec<u64>,
+    pub cm_anchor: E::Fr,
+    pub seed: E::Fr,
+    pub sn_anchor: E::Fr,
+    pub h_sig: E::Fr,
+    pub tsn_h: Vec<(E::Fr, E::Fr)>,
+}
+
+impl<E: JubjubEngine> MultiRolePublicInputs<E> {
+    pub fn num_inputs(num_roles: usize) -> usize {
+        4 + 3 * num_roles
+    }
+
+    pub fn from_inputs(input: &[E::Fr], num_roles: usize) -> Option<MultiRolePublicInputs<E>> {
+        if num_roles == 0 || input.len() != Self::num_inputs(num_roles) {
+            return None;
+        }
+
+        let mut roles = vec![fr_to_u64(&input[0])?];
+        for k in 1..num_roles {
+            roles.push(fr_to_u64(&input[4 + k])?);
+        }
+
+        let start = 4 + num_roles;
+        let tsn_h = (0..num_roles).map(|k| (input[start + 2 * k], input[start + 2 * k + 1])).collect();
+
+        Some(MultiRolePublicInputs {
+            roles,
+            cm_anchor: input[1],
+            seed: input[2],
+            sn_anchor: input[3],
+            h_sig: input[4],
+            tsn_h,
+        })
+    }
+
+    pub fn to_inputs(&self) -> Vec<E::Fr> {
+        let mut input = vec![u64_to_fr(self.roles[0]), self.cm_anchor, self.seed, self.sn_anchor, self.h_sig];
+
+        for role in &self.roles[1..] {
+            input.push(u64_to_fr(*role));
+        }
+
+        for (tsn, h) in &self.tsn_h {
+            input.push(*tsn);
+            input.push(*h);
+        }
+
+        input
+    }
+
+    //the inputs a separate AnonStake proof for the k-th role would have
+    pub fn role_inputs(&self, k: usize) -> PublicInputs<E> {
+        PublicInputs {
+            role: self.roles[k],
+            cm_anchor: self.cm_anchor,
+            seed: self.seed,
+            sn_anchor: self.sn_anchor,
+            tsn: self.tsn_h[k].0,
+            h_sig: self.h_sig,
+            h: self.tsn_h[k].1,
+            block_proposer: None,
+        }
+    }
+}
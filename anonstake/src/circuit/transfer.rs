@@ -0,0 +1,386 @@
+use std::marker::PhantomData;
+
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::gadgets::boolean;
+use bellman::gadgets::num::{AllocatedNum, Num};
+use ff::{Field, PrimeField};
+use rand::{Rng, thread_rng};
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::AnonStake;
+use crate::circuit::hash::{CircuitHash, HashInput};
+use crate::circuit::anonstake_inputs::{Coin, MerkleHeights};
+use crate::constants::{Constants, PrfDomain};
+use crate::native;
+
+/*
+zerocash style pour: spends one or two coins and creates two new ones
+for every old coin: proves knowledge of a_sk, that the coin is in the commitment tree and reveals sn = PRF_{a_sk}(rho)
+for every new coin: exposes cm, computed with the same layout as constrain_coin_commitment
+the sum of the old values has to equal the sum of the new values
+the rho of new coin j is H(sn_0, j), sn_0 being the serial number of the first old coin
+sn_0 can only be revealed once, so the new coins can not reuse the rho (and so the serial number) of another coin,
+otherwise a sender could create two coins of which only one can ever be spent (faerie gold attack in zerocash)
+
+a delegated input coin needs both keys: a_sk of the owner authorizes the spend (a_pk and h)
+and a_sk_del of the delegate gives sn = PRF_{a_sk_del}(rho), the same sn the delegate's sortition proofs use
+
+the transfer only proves membership in the commitment tree, the height of the serial number tree is kept
+so that the coin views are made with the same heights as the sortition circuits
+*/
+#[derive(Clone)]
+pub struct TransferInput<E: JubjubEngine> {
+    pub a_sk: Option<E::Fr>,
+    pub delegated: bool,
+    pub a_sk_del: Option<E::Fr>,
+    pub coin: Coin<E>,
+    pub fs_tree_start: Option<u64>,
+    pub fs_pk: Option<E::Fr>,
+    //only used for non-poseidon hash function version
+    pub cm_merkle_path: Vec<Option<(E::Fr, bool)>>,
+    pub cm_poseidon_path: Vec<Option<([E::Fr; 8], u8)>>,
+}
+
+#[derive(Clone)]
+pub struct TransferOutput<E: JubjubEngine> {
+    pub full_pk: Option<E::Fr>,
+    //coin.rho is not a witness, the circuit computes it, see native::output_rho
+    pub coin: Coin<E>,
+}
+
+#[derive(Clone)]
+pub struct Transfer<'a, E: JubjubEngine, H: CircuitHash<E>> {
+    pub constants: &'a Constants<'a, E>,
+    pub merkle_heights: MerkleHeights,
+    pub h_sig: Option<E::Fr>,
+    pub inputs: Vec<TransferInput<E>>,
+    pub outputs: Vec<TransferOutput<E>>,
+    pub hash: PhantomData<H>,
+}
+
+pub const NUM_OUTPUTS: usize = 2;
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Transfer<'a, E, H> {
+    //the gadgets read the coin and merkle path from an AnonStake, so make one that only holds this coin
+    fn coin_view(&self, coin: &Coin<E>, input: Option<&TransferInput<E>>) -> AnonStake<'a, E, H> {
+        let mut view = AnonStake::init_empty_with_heights(self.constants, false, self.merkle_heights);
+        view.aux_input.coin = coin.clone();
+
+        if let Some(input) = input {
+            view.aux_input.cm_merkle_path = input.cm_merkle_path.clone();
+            view.aux_input.cm_poseidon_path = input.cm_poseidon_path.clone();
+        }
+
+        view
+    }
+
+    //i as a constant, for the prehashes of h and the output rho
+    fn alloc_index<CS: ConstraintSystem<E>>(mut cs: CS, i: usize) -> Result<AllocatedNum<E>, SynthesisError> {
+        let index_value = E::Fr::from_str(&i.to_string()).ok_or(SynthesisError::Unsatisfiable)?;
+        let index = AllocatedNum::alloc(cs.namespace(|| "allocate index"), || Ok(index_value))?;
+
+        cs.enforce(|| "index is constant",
+                   |lc| lc + index.get_variable(),
+                   |lc| lc + CS::one(),
+                   |lc| lc + (index_value, CS::one()));
+
+        Ok(index)
+    }
+
+    fn random_coin<R: Rng>(rng: &mut R, value: u64) -> Coin<E> {
+        Coin {
+            value: Some(value),
+            rho: Some(E::Fr::random(rng)),
+            s: Some(E::Fs::random(rng)),
+        }
+    }
+
+    //delegated inputs have a different circuit, so there are separate params for transfers of delegated coins
+    pub fn init_empty(constants: &'a Constants<E>, merkle_height: usize, num_inputs: usize, delegated: bool) -> Transfer<'a, E, H> {
+        Self::init_empty_with_heights(constants, MerkleHeights::from(merkle_height), num_inputs, delegated)
+    }
+
+    pub fn init_empty_with_heights(constants: &'a Constants<E>, merkle_heights: MerkleHeights, num_inputs: usize, delegated: bool) -> Transfer<'a, E, H> {
+        let empty_coin = Coin {
+            value: None,
+            rho: None,
+            s: None,
+        };
+
+        let inputs = (0..num_inputs).map(|_| TransferInput {
+            a_sk: None,
+            delegated,
+            a_sk_del: None,
+            coin: empty_coin.clone(),
+            fs_tree_start: None,
+            fs_pk: None,
+            cm_merkle_path: vec![None; merkle_heights.cm],
+            cm_poseidon_path: vec![None; merkle_heights.cm],
+        }).collect();
+
+        let outputs = (0..NUM_OUTPUTS).map(|_| TransferOutput {
+            full_pk: None,
+            coin: empty_coin.clone(),
+        }).collect();
+
+        Transfer {
+            constants,
+            merkle_heights,
+            h_sig: None,
+            inputs,
+            outputs,
+            hash: PhantomData,
+        }
+    }
+
+    pub fn init_testing(constants: &'a Constants<E>, merkle_height: usize, num_inputs: usize, delegated: bool) -> Transfer<'a, E, H> {
+        Self::init_testing_with_heights(constants, MerkleHeights::from(merkle_height), num_inputs, delegated)
+    }
+
+    pub fn init_testing_with_heights(constants: &'a Constants<E>, merkle_heights: MerkleHeights, num_inputs: usize, delegated: bool) -> Transfer<'a, E, H> {
+        let rng = &mut thread_rng();
+
+        let mut total = 0;
+        let mut inputs = vec![];
+
+        for _ in 0..num_inputs {
+            let value = rng.gen::<u64>() % (1 << 59);
+            total += value;
+
+            let mut cm_merkle_path = vec![];
+            let mut cm_poseidon_path = vec![];
+            for _ in 0..merkle_heights.cm {
+                cm_merkle_path.push(Some((E::Fr::random(rng), rng.gen())));
+
+                let t: u8 = rng.gen::<u8>() % 8;
+                let a = [E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng), E::Fr::random(rng)];
+                cm_poseidon_path.push(Some((a, t)));
+            }
+
+            inputs.push(TransferInput {
+                a_sk: Some(E::Fr::random(rng)),
+                delegated,
+                a_sk_del: Some(E::Fr::random(rng)),
+                coin: Self::random_coin(rng, value),
+                fs_tree_start: Some(rng.gen()),
+                fs_pk: Some(E::Fr::random(rng)),
+                cm_merkle_path,
+                cm_poseidon_path,
+            });
+        }
+
+        let sn_key = if inputs[0].delegated { inputs[0].a_sk_del } else { inputs[0].a_sk };
+        let first_sn = native::sn(constants, &sn_key.unwrap(), &inputs[0].coin.rho.unwrap());
+
+        let first = rng.gen::<u64>() % (total + 1);
+        let outputs = vec![first, total - first].into_iter().enumerate().map(|(j, value)| TransferOutput {
+            full_pk: Some(E::Fr::random(rng)),
+            coin: Coin {
+                value: Some(value),
+                rho: Some(native::output_rho::<E, H>(constants, &first_sn, j as u64)),
+                s: Some(E::Fs::random(rng)),
+            },
+        }).collect();
+
+        Transfer {
+            constants,
+            merkle_heights,
+            h_sig: Some(E::Fr::random(rng)),
+            inputs,
+            outputs,
+            hash: PhantomData,
+        }
+    }
+}
+
+impl<'a, E: JubjubEngine, H: CircuitHash<E>> Circuit<E> for Transfer<'a, E, H> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let h_sig = AllocatedNum::alloc(cs.namespace(|| "allocate h_sig"), || self.h_sig.ok_or(SynthesisError::AssignmentMissing))?;
+        h_sig.inputize(cs.namespace(|| "inputize h_sig"))?;
+
+        let mut value_in = Num::<E>::zero();
+        let mut value_out = Num::<E>::zero();
+        let mut first_sn = None;
+
+        for (i, input) in self.inputs.iter().enumerate() {
+            let view = self.coin_view(&input.coin, Some(input));
+            let cs = &mut cs.namespace(|| format!("input {}", i));
+
+            let a_sk = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || input.a_sk.ok_or(SynthesisError::AssignmentMissing))?;
+            let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero"), || Ok(E::Fr::zero()))?;
+
+            let a_pk = view.prf(cs.namespace(|| "calc a_pk"), "calc a_pk", a_sk.clone(), allocated_zero, PrfDomain::Addr)?;
+
+            let fs_start_bits = boolean::u64_into_boolean_vec_le(
+                cs.namespace(|| "fs start bits"),
+                input.fs_tree_start,
+            )?;
+
+            let fs_pk = AllocatedNum::alloc(cs.namespace(|| "fs_pk"), || input.fs_pk.ok_or(SynthesisError::AssignmentMissing))?;
+
+            let (full_pk, sn_key) = if input.delegated {
+                let a_sk_del = AllocatedNum::alloc(cs.namespace(|| "a_sk_del"), || input.a_sk_del.ok_or(SynthesisError::AssignmentMissing))?;
+                let allocated_zero = AllocatedNum::alloc(cs.namespace(|| "allocate fake zero for delegate"), || Ok(E::Fr::zero()))?;
+
+                let a_pk_del = view.prf(cs.namespace(|| "calc a_pk_del"), "calc a_pk_del", a_sk_del.clone(), allocated_zero, PrfDomain::Addr)?;
+
+                let full_pk = view.constrain_full_pk(
+                    cs.namespace(|| "constrain packed values"),
+                    "constrain packed values", fs_start_bits, fs_pk, a_pk, Some(a_pk_del))?;
+
+                (full_pk, a_sk_del)
+            } else {
+                let full_pk = view.constrain_full_pk(
+                    cs.namespace(|| "constrain packed values"),
+                    "constrain packed values", fs_start_bits, fs_pk, a_pk, None)?;
+
+                (full_pk, a_sk.clone())
+            };
+
+            let rho = AllocatedNum::alloc(cs.namespace(|| "rho alloc"),
+                                          || input.coin.rho.ok_or(SynthesisError::AssignmentMissing))?;
+
+            let (cm, value, _) = view.constrain_coin_commitment(
+                cs.namespace(|| "coin commitment computation"),
+                "coin commitment computation", full_pk, rho.clone())?;
+            view.coin_commitment_membership(cs.namespace(|| "coin commitment membership"), "coin commitment membership", cm)?;
+
+            let sn = view.prf(cs.namespace(|| "calc serial number"), "calc serial number", sn_key, rho, PrfDomain::Sn)?;
+            sn.inputize(cs.namespace(|| "inputize sn"))?;
+            if first_sn.is_none() {
+                first_sn = Some(sn);
+            }
+
+            // h_i = PRF_{a_sk}(H(h_sig, i)) like in zerocash, so the coin owner authorized this h_sig
+            let index = Self::alloc_index(cs.namespace(|| "index"), i)?;
+
+            let hash = H::hash(&view, cs.namespace(|| "prehash calc h"), "prehash calc h",
+                               vec![HashInput::Num(h_sig.clone()), HashInput::Num(index)])?;
+            let h = view.prf(cs.namespace(|| "calc h"), "calc h", a_sk, hash, PrfDomain::Pk)?;
+            h.inputize(cs.namespace(|| "inputize h"))?;
+
+            value_in = value_in + value;
+        }
+
+        for (i, output) in self.outputs.iter().enumerate() {
+            let view = self.coin_view(&output.coin, None);
+            let cs = &mut cs.namespace(|| format!("output {}", i));
+
+            let full_pk = AllocatedNum::alloc(cs.namespace(|| "full_pk"), || output.full_pk.ok_or(SynthesisError::AssignmentMissing))?;
+
+            //rho_j = H(sn_0, j)
+            let first_sn = first_sn.clone().ok_or(SynthesisError::Unsatisfiable)?;
+            let index = Self::alloc_index(cs.namespace(|| "index"), i)?;
+            let rho = H::hash(&view, cs.namespace(|| "calc rho"), "calc rho",
+                              vec![HashInput::Num(first_sn), HashInput::Num(index)])?;
+
+            let (cm, value, _) = view.constrain_coin_commitment(
+                cs.namespace(|| "coin commitment computation"),
+                "coin commitment computation", full_pk, rho)?;
+
+            cm.get_x().inputize(cs.namespace(|| "inputize cm"))?;
+
+            value_out = value_out + value;
+        }
+
+        cs.enforce(|| "value conservation",
+                   |_| value_in.lc(E::Fr::one()),
+                   |lc| lc + CS::one(),
+                   |_| value_out.lc(E::Fr::one()));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::hash::{NativeHashInput, Poseidon};
+    use crate::circuit::public_inputs::u64_to_fr;
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    fn output_cm(constants: &Constants<Bls12>, output: &TransferOutput<Bls12>) -> Fr {
+        let coin = &output.coin;
+        native::coin_commitment(constants, &output.full_pk.unwrap(), coin.value.unwrap(), &coin.rho.unwrap(), &coin.s.unwrap())
+    }
+
+    #[test]
+    fn output_rho_derived() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        for num_inputs in 1..3 {
+            let transfer = Transfer::<Bls12, Poseidon>::init_testing(&constants, 4, num_inputs, false);
+            let outputs = transfer.outputs.clone();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            transfer.synthesize(&mut cs).unwrap();
+            assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+            //init_testing sets the output rhos with native::output_rho, so the cms only match if the circuit derives the same rho
+            for (j, output) in outputs.iter().enumerate() {
+                assert_eq!(cs.get(&format!("output {}/inputize cm/input variable", j)), output_cm(&constants, output));
+            }
+            assert_ne!(outputs[0].coin.rho, outputs[1].coin.rho);
+        }
+    }
+
+    //two new coins with the same rho would have the same serial number, only one of them could be spent
+    #[test]
+    fn same_rho_rejected() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let transfer = Transfer::<Bls12, Poseidon>::init_testing(&constants, 4, 1, false);
+
+        let mut same_rho = transfer.clone();
+        same_rho.outputs[1].coin.rho = same_rho.outputs[0].coin.rho;
+
+        //the circuit ignores the rho of the witness, so the cm of the second coin is not the one the sender would give its receiver
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        same_rho.clone().synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+        assert_ne!(cs.get("output 1/inputize cm/input variable"), output_cm(&constants, &same_rho.outputs[1]));
+        assert_eq!(cs.get("output 1/inputize cm/input variable"), output_cm(&constants, &transfer.outputs[1]));
+    }
+
+    //the sn of a delegated coin comes from the key of the delegate, h from the key of the owner
+    #[test]
+    fn delegated_inputs() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let transfer = Transfer::<Bls12, Poseidon>::init_testing(&constants, 4, 2, true);
+        let inputs = transfer.inputs.clone();
+        let h_sig = transfer.h_sig.unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        transfer.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+        for (i, input) in inputs.iter().enumerate() {
+            let rho = input.coin.rho.unwrap();
+            let sn = cs.get(&format!("input {}/inputize sn/input variable", i));
+            assert_eq!(sn, native::sn(&constants, &input.a_sk_del.unwrap(), &rho));
+            assert_ne!(sn, native::sn(&constants, &input.a_sk.unwrap(), &rho));
+
+            let index = Poseidon::native(&constants, &[NativeHashInput::elem(h_sig), NativeHashInput::elem(u64_to_fr(i as u64))]);
+            assert_eq!(cs.get(&format!("input {}/inputize h/input variable", i)), native::prf(&constants, &input.a_sk.unwrap(), &index, PrfDomain::Pk));
+        }
+    }
+
+    //params are generated from init_empty, its inputs have to take the same branch of the circuit as the witness
+    #[test]
+    fn delegated_empty() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        for delegated in vec![false, true] {
+            let transfer = Transfer::<Bls12, Poseidon>::init_empty(&constants, 4, 2, delegated);
+            assert!(transfer.inputs.iter().all(|input| input.delegated == delegated));
+        }
+    }
+}
@@ -1,21 +1,17 @@
 use std::env;
 use std::fs::create_dir;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
 
 use num_cpus;
 
-use bellman::multicore::implementation;
+use anonstake::circuit::anonstake_inputs::MerkleHeights;
+use anonstake::constants::binomial_constants::TauValue::{Tau1500, Tau20, Tau2000, Tau2990, Tau5000};
+use anonstake::constants::binomial_constants::TauValue;
+use anonstake::error::Error;
 
-use crate::constants::binomial_constants::TauValue::{Tau1500, Tau20, Tau2000, Tau2990, Tau5000};
-use crate::constants::binomial_constants::TauValue;
+use crate::plan;
 use clap::{App, ArgMatches};
 
-pub enum CLIError {
-    DirectoryCreationFailure,
-    CannotAccessCWD,
-}
-
 #[derive(Clone)]
 pub enum RunMode {
     OnlyGenParams(PathBuf),
@@ -23,31 +19,140 @@ pub enum RunMode {
     Sample(PathBuf),
     Single(PathBuf, PathBuf, u32),
     Batch(PathBuf, PathBuf, u32, u32),
+    //params file, number of input coins, whether the input coins are delegated
+    TransferGenParams(PathBuf, usize, bool),
+    TransferSample(PathBuf, usize, bool),
+    MintGenParams(PathBuf),
+    Mint(PathBuf, PathBuf, u64, Option<String>),
+    MultiRoleInfo(usize),
+    MultiRoleGenParams(PathBuf, usize),
+    MultiRoleSingle(PathBuf, PathBuf, u32, usize),
+    MultiCoinGenParams(PathBuf, usize),
+    MultiCoinSample(PathBuf, usize),
+    PoseidonConstants(usize, usize, bool),
+    MiMCConstants(bool),
+    Profile(usize, bool),
+    ExportR1cs(Option<PathBuf>),
+    ExportWitness(Option<PathBuf>),
+    //part to corrupt, .wtns file to check instead of a generated witness, expected public inputs
+    CheckWitness(Option<String>, Option<PathBuf>, Option<PathBuf>),
 }
 
 #[derive(Clone)]
 pub struct RunConfig {
     pub tau: TauValue,
     pub is_bp: bool,
-    pub merkle_height: usize,
+    pub merkle_heights: MerkleHeights,
     pub test_constraint_system: bool,
     pub check_params: bool,
     pub mode: RunMode,
     pub use_poseidon: bool,
+    pub delegated: bool,
+    //use the keyed poseidon prf instead of mimc, see constants::PrfKind
+    pub poseidon_prf: bool,
+    //number of threads for proving, None keeps the current setting
+    pub threads: Option<usize>,
+    //seed for the witnesses of the sortition proofs, None for fresh randomness
+    pub seed: Option<u64>,
 }
 
-pub fn get_run_config() -> Result<Vec<RunConfig>, CLIError> {
+pub fn param_name(config: &RunConfig) -> String {
+    let tau: &str = (&config.tau).into();
+    let bp = match config.is_bp {
+        true => "_block_proposer",
+        false => ""
+    };
+    let pos = match config.use_poseidon {
+        true => "",
+        false => "_no_poseidon"
+    };
+    let del = match config.delegated {
+        true => "_delegated",
+        false => ""
+    };
+    let prf = match config.poseidon_prf {
+        true => "_poseidon_prf",
+        false => ""
+    };
+
+    let heights = heights_suffix(&config.merkle_heights, config.use_poseidon);
+
+    format!("{}{}{}{}{}{}", tau, bp, pos, del, prf, heights)
+}
+
+//params for the default heights keep their old names
+pub fn heights_suffix(heights: &MerkleHeights, use_poseidon: bool) -> String {
+    if *heights != MerkleHeights::default_for(use_poseidon) {
+        format!("_cm{}_sn{}", heights.cm, heights.sn)
+    } else {
+        String::new()
+    }
+}
+
+//--cm_height and --sn_height, a missing one keeps the default height of the hash
+pub fn get_merkle_heights(matches: Option<&ArgMatches>, use_poseidon: bool) -> Result<MerkleHeights, Error> {
+    let heights = read_merkle_heights(matches, use_poseidon)?;
+    heights.check(use_poseidon).map_err(Error::Config)?;
+    Ok(heights)
+}
+
+//same without checking that the hash supports the heights
+fn read_merkle_heights(matches: Option<&ArgMatches>, use_poseidon: bool) -> Result<MerkleHeights, Error> {
+    let mut heights = MerkleHeights::default_for(use_poseidon);
+
+    if let Some(matches) = matches {
+        if matches.is_present("cm_height") {
+            heights.cm = value_t!(matches, "cm_height", usize).map_err(|e| Error::Config(e.message))?;
+        }
+        if matches.is_present("sn_height") {
+            heights.sn = value_t!(matches, "sn_height", usize).map_err(|e| Error::Config(e.message))?;
+        }
+    }
+
+    Ok(heights)
+}
+
+fn has_heights(matches: Option<&ArgMatches>) -> bool {
+    matches.map_or(false, |m| m.is_present("cm_height") || m.is_present("sn_height"))
+}
+
+/*
+gen_params, test, circuit_info and profile go over the poseidon and the pedersen circuits,
+but a poseidon tree can not be as high as a pedersen tree
+the hashes that can not have the given heights are skipped with a message, it is an error only if neither can
+*/
+fn hashes_with_heights(matches: Option<&ArgMatches>) -> Result<Vec<(bool, MerkleHeights)>, Error> {
+    let mut hashes = vec![];
+    let mut skipped = vec![];
+
+    for use_poseidon in vec![true, false] {
+        let heights = read_merkle_heights(matches, use_poseidon)?;
+        match heights.check(use_poseidon) {
+            Ok(()) => hashes.push((use_poseidon, heights)),
+            Err(msg) => skipped.push((use_poseidon, msg)),
+        }
+    }
+
+    if hashes.is_empty() {
+        let (_, msg) = skipped.pop().unwrap();
+        return Err(Error::Config(msg));
+    }
+
+    for (use_poseidon, msg) in skipped {
+        let hash = if use_poseidon { "poseidon" } else { "pedersen" };
+        println!("skipping the {} circuits: {}", hash, msg);
+    }
+
+    Ok(hashes)
+}
+
+pub fn get_run_config() -> Result<Vec<RunConfig>, Error> {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
     if !Path::new("./prover_params").exists() {
         match create_dir(&Path::new("./prover_params")) {
-            Err(e) => {
-                println!("{}", e.to_string());
-                println!("Error in creating directory ./prover_params");
-                println!("Please try running this program again");
-                return Err(CLIError::DirectoryCreationFailure);
-            }
+            Err(e) => return Err(Error::DirectoryCreation(PathBuf::from("./prover_params"), e)),
             Ok(_) => {
                 println!("Created directory ./prover_params\n");
             }
@@ -56,56 +161,97 @@ pub fn get_run_config() -> Result<Vec<RunConfig>, CLIError> {
 
     if !Path::new("./benchmarks").exists() {
         match create_dir(&Path::new("./benchmarks")) {
-            Err(e) => {
-                println!("{}", e.to_string());
-                println!("Error in creating directory ./benchmarks");
-                println!("Please try running this program again");
-                return Err(CLIError::DirectoryCreationFailure);
-            }
+            Err(e) => return Err(Error::DirectoryCreation(PathBuf::from("./benchmarks"), e)),
             Ok(_) => {
                 println!("Created directory ./benchmarks\n");
             }
         }
     }
 
-    if let Some(_) = matches.subcommand_matches("gen_params") {
-        return get_params_gen();
-    } else if let Some(_) = matches.subcommand_matches("circuit_info") {
-        return get_circuit_info();
-    } else {
-        let gen_params = get_params_gen()?;
+    if let Some(matches) = matches.subcommand_matches("gen_params") {
+        return get_params_gen(Some(matches));
+    } else if let Some(matches) = matches.subcommand_matches("circuit_info") {
+        return get_circuit_info(Some(matches));
+    } else if let Some(matches) = matches.subcommand_matches("profile") {
+        return get_profile(matches);
+    } else if let Some(matches) = matches.subcommand_matches("export_r1cs") {
+        return get_export(matches, false);
+    } else if let Some(matches) = matches.subcommand_matches("export_witness") {
+        return get_export(matches, true);
+    } else if let Some(matches) = matches.subcommand_matches("check_witness") {
+        return get_check_witness(matches);
+    } else if let Some(matches) = matches.subcommand_matches("transfer_gen_params") {
+        return get_transfer_params_gen(matches.is_present("poseidon_prf"));
+    } else if let Some(matches) = matches.subcommand_matches("transfer_test") {
+        let poseidon_prf = matches.is_present("poseidon_prf");
+        let gen_params = get_transfer_params_gen(poseidon_prf)?;
         if gen_params.len() != 0 {
             return Ok(gen_params);
         }
-    }
 
-    if let Some(_) = matches.subcommand_matches("test") {
-        return sample_all_proofs();
+        return sample_all_transfers(poseidon_prf);
+    } else if let Some(matches) = matches.subcommand_matches("mint") {
+        return get_mint(matches);
+    } else if let Some(matches) = matches.subcommand_matches("multi_role") {
+        return get_multi_role(matches);
+    } else if let Some(matches) = matches.subcommand_matches("multi_coin_test") {
+        return get_multi_coin(matches);
+    } else if let Some(matches) = matches.subcommand_matches("poseidon_constants") {
+        return get_poseidon_constants(matches);
+    } else if let Some(matches) = matches.subcommand_matches("mimc_constants") {
+        return get_mimc_constants(matches);
+    } else if let Some(matches) = matches.subcommand_matches("plan") {
+        return plan::get_plan(matches);
+    } else if let Some(matches) = matches.subcommand_matches("test") {
+        //params for other heights are generated together with the samples
+        if !has_heights(Some(matches)) {
+            let gen_params = get_params_gen(None)?;
+            if gen_params.len() != 0 {
+                return Ok(gen_params);
+            }
+        }
+
+        return sample_all_proofs(Some(matches));
+    } else {
+        //single and batch generate the params for other heights themselves
+        if !has_heights(matches.subcommand().1) {
+            let gen_params = get_params_gen(None)?;
+            if gen_params.len() != 0 {
+                return Ok(gen_params);
+            }
+        }
     }
 
     read_command_line_params(matches)
 }
 
-pub fn get_params_gen() -> Result<Vec<RunConfig>, CLIError> {
+pub fn get_params_gen(matches: Option<&ArgMatches>) -> Result<Vec<RunConfig>, Error> {
     let tau_vals = [Tau20, Tau1500, Tau2990, Tau5000];
     let is_bp = ["_block_proposer", "", "", ""];
 
+    //without --poseidon_prf only the mimc params, single and batch generate the others when they need them
+    let poseidon_prf = matches.map_or(false, |matches| matches.is_present("poseidon_prf"));
+
     let mut configs = vec![];
 
+    let hashes = hashes_with_heights(matches)?;
+
     let mut all_exist = true;
     for i in 0..4 {
-        for use_poseidon in vec![true, false] {
+        for &(use_poseidon, merkle_heights) in &hashes {
             let param = {
                 let tau: &str = (&tau_vals[i]).into();
                 let bp = &is_bp[i];
                 let up = if use_poseidon { "" } else { "_no_poseidon" };
-                format!("{}{}{}", tau, bp, up)
+                let prf = if poseidon_prf { "_poseidon_prf" } else { "" };
+                let heights = heights_suffix(&merkle_heights, use_poseidon);
+                format!("{}{}{}{}{}", tau, bp, up, prf, heights)
             };
 
             let path = {
                 let path = env::current_dir();
                 match path {
-                    Err(_) => return Err(CLIError::CannotAccessCWD),
+                    Err(e) => return Err(Error::CurrentDir(e)),
                     Ok(mut path) => {
                         path.push(format!("prover_params/{}.params", &param));
                         path
@@ -124,16 +270,18 @@ pub fn get_params_gen() -> Result<Vec<RunConfig>, CLIError> {
                 let tau = (&tau_vals[i]).clone();
                 let is_bp: bool = i == 0;
 
-                let merkle_height = if use_poseidon { 10 } else { 29 };
-
                 configs.push(RunConfig {
                     tau,
                     is_bp,
-                    merkle_height,
+                    merkle_heights,
                     test_constraint_system: true,
                     check_params: false,
                     mode: RunMode::OnlyGenParams(path),
                     use_poseidon,
+                    delegated: false,
+                    poseidon_prf,
+                    threads: None,
+                    seed: None,
                 });
             }
         }
@@ -146,26 +294,101 @@ pub fn get_params_gen() -> Result<Vec<RunConfig>, CLIError> {
     Ok(configs)
 }
 
-pub fn get_circuit_info() -> Result<Vec<RunConfig>, CLIError> {
+pub fn get_circuit_info(matches: Option<&ArgMatches>) -> Result<Vec<RunConfig>, Error> {
     let tau_vals = [Tau20, Tau1500, Tau2990, Tau5000];
 
+    let hashes = hashes_with_heights(matches)?;
+
     let mut configs = vec![];
 
     for i in 0..4 {
-        for use_poseidon in vec![true, false] {
+        for &(use_poseidon, merkle_heights) in &hashes {
             let tau = (&tau_vals[i]).clone();
             let is_bp: bool = i == 0;
 
-            let merkle_height = if use_poseidon { 10 } else { 29 };
-
             configs.push(RunConfig {
                 tau,
                 is_bp,
-                merkle_height,
+                merkle_heights,
                 test_constraint_system: true,
                 check_params: false,
                 mode: RunMode::OutputCircuitInfo,
                 use_poseidon,
+                delegated: false,
+                poseidon_prf: false,
+                threads: None,
+                seed: None,
+            });
+        }
+    }
+
+    //same circuits with the poseidon prf
+    for i in 0..4 {
+        for &(use_poseidon, merkle_heights) in &hashes {
+            configs.push(RunConfig {
+                tau: (&tau_vals[i]).clone(),
+                is_bp: i == 0,
+                merkle_heights,
+                test_constraint_system: true,
+                check_params: false,
+                mode: RunMode::OutputCircuitInfo,
+                use_poseidon,
+                delegated: false,
+                poseidon_prf: true,
+                threads: None,
+                seed: None,
+            });
+        }
+    }
+
+    //multi role circuits only exist for the voting roles
+    for i in 1..4 {
+        for &(use_poseidon, merkle_heights) in &hashes {
+            for num_roles in vec![2, 4] {
+                configs.push(RunConfig {
+                    tau: (&tau_vals[i]).clone(),
+                    is_bp: false,
+                    merkle_heights,
+                    test_constraint_system: true,
+                    check_params: false,
+                    mode: RunMode::MultiRoleInfo(num_roles),
+                    use_poseidon,
+                    delegated: false,
+                    poseidon_prf: false,
+                    threads: None,
+                    seed: None,
+                });
+            }
+        }
+    }
+
+    Ok(configs)
+}
+
+
+pub fn get_profile(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let tau_vals = [Tau20, Tau1500, Tau2990, Tau5000];
+    let depth = value_t!(matches, "depth", usize).unwrap_or(2);
+    let csv = matches.is_present("csv");
+
+    let hashes = hashes_with_heights(Some(matches))?;
+
+    let mut configs = vec![];
+
+    for i in 0..4 {
+        for &(use_poseidon, merkle_heights) in &hashes {
+            configs.push(RunConfig {
+                tau: (&tau_vals[i]).clone(),
+                is_bp: i == 0,
+                merkle_heights,
+                test_constraint_system: false,
+                check_params: false,
+                mode: RunMode::Profile(depth, csv),
+                use_poseidon,
+                delegated: false,
+                poseidon_prf: false,
+                threads: None,
+                seed: None,
             });
         }
     }
@@ -174,24 +397,28 @@ pub fn get_circuit_info() -> Result<Vec<RunConfig>, CLIError> {
 }
 
 
-pub fn sample_all_proofs() -> Result<Vec<RunConfig>, CLIError> {
+pub fn sample_all_proofs(matches: Option<&ArgMatches>) -> Result<Vec<RunConfig>, Error> {
     let tau_vals = [Tau20, Tau1500, Tau2990, Tau5000];
     let is_bp = ["_block_proposer", "", "", ""];
 
+    let hashes = hashes_with_heights(matches)?;
+
     let mut configs = vec![];
 
     for i in 0..4 {
-        for use_poseidon in vec![true, false] {
+        for &(use_poseidon, merkle_heights) in &hashes {
             let tau: &str = (&tau_vals[i]).into();
             let bp = &is_bp[i];
             let up = if use_poseidon { "" } else { "_no_poseidon" };
 
+            let heights = heights_suffix(&merkle_heights, use_poseidon);
+
             let path = {
-                let param = format!("{}{}{}", tau, bp, up);
+                let param = format!("{}{}{}{}", tau, bp, up, heights);
 
                 let path = env::current_dir();
                 match path {
-                    Err(_) => return Err(CLIError::CannotAccessCWD),
+                    Err(e) => return Err(Error::CurrentDir(e)),
                     Ok(mut path) => {
                         path.push(format!("prover_params/{}.params", &param));
                         path
@@ -202,19 +429,118 @@ pub fn sample_all_proofs() -> Result<Vec<RunConfig>, CLIError> {
             let tau = (&tau_vals[i]).clone();
             let is_bp = i == 0;
 
-            let merkle_height = match use_poseidon {
-                true => 10,
-                false => 29
-            };
+            if !path.exists() {
+                configs.push(RunConfig {
+                    tau: tau.clone(),
+                    is_bp,
+                    merkle_heights,
+                    test_constraint_system: true,
+                    check_params: false,
+                    mode: RunMode::OnlyGenParams(path.clone()),
+                    use_poseidon,
+                    delegated: false,
+                    poseidon_prf: false,
+                    threads: None,
+                    seed: None,
+                });
+            }
 
             configs.push(RunConfig {
                 tau,
                 is_bp,
-                merkle_height,
+                merkle_heights,
                 test_constraint_system: true,
                 check_params: false,
                 mode: RunMode::Sample(path),
                 use_poseidon,
+                delegated: false,
+                poseidon_prf: false,
+                threads: None,
+                seed: None,
+            });
+        }
+    }
+
+    Ok(configs)
+}
+
+pub fn transfer_params_path(num_inputs: usize, delegated: bool, use_poseidon: bool, poseidon_prf: bool) -> Result<PathBuf, Error> {
+    let del = if delegated { "_delegated" } else { "" };
+    let up = if use_poseidon { "" } else { "_no_poseidon" };
+    let prf = if poseidon_prf { "_poseidon_prf" } else { "" };
+    let param = format!("transfer{}{}{}{}", num_inputs, del, up, prf);
+
+    match env::current_dir() {
+        Err(e) => Err(Error::CurrentDir(e)),
+        Ok(mut path) => {
+            path.push(format!("prover_params/{}.params", &param));
+            Ok(path)
+        }
+    }
+}
+
+pub fn get_transfer_params_gen(poseidon_prf: bool) -> Result<Vec<RunConfig>, Error> {
+    let mut configs = vec![];
+
+    let mut all_exist = true;
+    for num_inputs in 1..3 {
+        for (delegated, use_poseidon) in vec![(false, true), (false, false), (true, true), (true, false)] {
+            let path = transfer_params_path(num_inputs, delegated, use_poseidon, poseidon_prf)?;
+
+            if !path.exists() {
+                if all_exist {
+                    println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+                    all_exist = false;
+                }
+
+                println!("{} ", path.file_stem().unwrap().to_str().unwrap());
+
+                let merkle_heights = MerkleHeights::default_for(use_poseidon);
+
+                configs.push(RunConfig {
+                    tau: Tau20,
+                    is_bp: false,
+                    merkle_heights,
+                    test_constraint_system: true,
+                    check_params: false,
+                    mode: RunMode::TransferGenParams(path, num_inputs, delegated),
+                    use_poseidon,
+                    delegated,
+                    poseidon_prf,
+                    threads: None,
+                    seed: None,
+                });
+            }
+        }
+    }
+
+    if !all_exist {
+        println!("\nAfter the parameters have been generated, re-run this program\n\n");
+    }
+
+    Ok(configs)
+}
+
+pub fn sample_all_transfers(poseidon_prf: bool) -> Result<Vec<RunConfig>, Error> {
+    let mut configs = vec![];
+
+    for num_inputs in 1..3 {
+        for (delegated, use_poseidon) in vec![(false, true), (false, false), (true, true), (true, false)] {
+            let path = transfer_params_path(num_inputs, delegated, use_poseidon, poseidon_prf)?;
+            let merkle_heights = MerkleHeights::default_for(use_poseidon);
+
+            configs.push(RunConfig {
+                tau: Tau20,
+                is_bp: false,
+                merkle_heights,
+                test_constraint_system: true,
+                check_params: false,
+                mode: RunMode::TransferSample(path, num_inputs, delegated),
+                use_poseidon,
+                delegated,
+                poseidon_prf,
+                threads: None,
+                seed: None,
             });
         }
     }
@@ -222,7 +548,303 @@ pub fn sample_all_proofs() -> Result<Vec<RunConfig>, CLIError> {
     Ok(configs)
 }
 
-pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, CLIError> {
+pub fn get_mint(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let path = match env::current_dir() {
+        Err(e) => return Err(Error::CurrentDir(e)),
+        Ok(mut path) => {
+            path.push("prover_params/mint.params");
+            path
+        }
+    };
+
+    let mode = if !path.exists() {
+        println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+        println!("mint ");
+        println!("\nAfter the parameters have been generated, re-run this program\n\n");
+
+        RunMode::MintGenParams(path)
+    } else {
+        let value = value_t!(matches, "value", u64).unwrap_or(0);
+        let tree = PathBuf::from(matches.value_of("tree").unwrap_or("tree.txt"));
+        let full_pk = matches.value_of("full_pk").map(|s| s.to_owned());
+
+        RunMode::Mint(path, tree, value, full_pk)
+    };
+
+    Ok(vec![RunConfig {
+        tau: Tau20,
+        is_bp: false,
+        merkle_heights: MerkleHeights::new(0, 0),
+        test_constraint_system: false,
+        check_params: false,
+        mode,
+        use_poseidon: false,
+        delegated: false,
+        poseidon_prf: false,
+        threads: None,
+        seed: None,
+    }])
+}
+
+pub fn get_poseidon_constants(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let width = value_t!(matches, "width", usize).unwrap_or(9);
+    let security = value_t!(matches, "security", usize).unwrap_or(128);
+    let print = matches.is_present("print");
+
+    Ok(vec![RunConfig {
+        tau: Tau20,
+        is_bp: false,
+        merkle_heights: MerkleHeights::new(0, 0),
+        test_constraint_system: false,
+        check_params: false,
+        mode: RunMode::PoseidonConstants(width, security, print),
+        use_poseidon: true,
+        delegated: false,
+        poseidon_prf: false,
+        threads: None,
+        seed: None,
+    }])
+}
+
+pub fn get_mimc_constants(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let print = matches.is_present("print");
+
+    Ok(vec![RunConfig {
+        tau: Tau20,
+        is_bp: false,
+        merkle_heights: MerkleHeights::new(0, 0),
+        test_constraint_system: false,
+        check_params: false,
+        mode: RunMode::MiMCConstants(print),
+        use_poseidon: true,
+        delegated: false,
+        poseidon_prf: false,
+        threads: None,
+        seed: None,
+    }])
+}
+
+//(tau, is_bp, use_poseidon) for the role numbering of single and batch
+pub fn sortition_circuit(matches: &ArgMatches) -> (TauValue, bool, bool) {
+    let num: u32 = value_t!(matches, "role", u32).unwrap_or(0) % 8;
+
+    match num {
+        0 => (Tau20, true, true),
+        1 => (Tau20, true, false),
+        2 => (Tau1500, false, true),
+        3 => (Tau1500, false, false),
+        4 => (Tau2990, false, true),
+        5 => (Tau2990, false, false),
+        6 => (Tau5000, false, true),
+        7 => (Tau5000, false, false),
+        _ => (Tau2000, false, false)
+    }
+}
+
+pub fn get_export(matches: &ArgMatches, witness: bool) -> Result<Vec<RunConfig>, Error> {
+    let (tau, is_bp, use_poseidon) = sortition_circuit(matches);
+    let merkle_heights = get_merkle_heights(Some(matches), use_poseidon)?;
+    let output = matches.value_of("output").map(PathBuf::from);
+
+    let mode = if witness {
+        RunMode::ExportWitness(output)
+    } else {
+        RunMode::ExportR1cs(output)
+    };
+
+    Ok(vec![RunConfig {
+        tau,
+        is_bp,
+        merkle_heights,
+        test_constraint_system: false,
+        check_params: false,
+        mode,
+        use_poseidon,
+        delegated: matches.is_present("delegated"),
+        poseidon_prf: matches.is_present("poseidon_prf"),
+        threads: None,
+        seed: None,
+    }])
+}
+
+pub fn get_check_witness(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let (tau, is_bp, use_poseidon) = sortition_circuit(matches);
+    let merkle_heights = get_merkle_heights(Some(matches), use_poseidon)?;
+    let corrupt = matches.value_of("corrupt").map(|s| s.to_owned());
+    let witness = matches.value_of("witness").map(PathBuf::from);
+    let inputs = matches.value_of("inputs").map(PathBuf::from);
+
+    Ok(vec![RunConfig {
+        tau,
+        is_bp,
+        merkle_heights,
+        test_constraint_system: true,
+        check_params: false,
+        mode: RunMode::CheckWitness(corrupt, witness, inputs),
+        use_poseidon,
+        delegated: matches.is_present("delegated"),
+        poseidon_prf: matches.is_present("poseidon_prf"),
+        threads: None,
+        seed: None,
+    }])
+}
+
+pub fn voting_circuit(matches: &ArgMatches) -> (TauValue, bool) {
+    let num: u32 = value_t!(matches, "role", u32).unwrap_or(2) % 8;
+
+    match num {
+        3 => (Tau1500, false),
+        4 => (Tau2990, true),
+        5 => (Tau2990, false),
+        6 => (Tau5000, true),
+        7 => (Tau5000, false),
+        _ => (Tau1500, true)
+    }
+}
+
+pub fn multi_role_params_path(tau: &TauValue, num_roles: usize, use_poseidon: bool, poseidon_prf: bool, merkle_heights: &MerkleHeights) -> Result<PathBuf, Error> {
+    let tau: &str = tau.into();
+    let up = if use_poseidon { "" } else { "_no_poseidon" };
+    let prf = if poseidon_prf { "_poseidon_prf" } else { "" };
+    let heights = heights_suffix(merkle_heights, use_poseidon);
+    let param = format!("{}_multi{}{}{}{}", tau, num_roles, up, prf, heights);
+
+    match env::current_dir() {
+        Err(e) => Err(Error::CurrentDir(e)),
+        Ok(mut path) => {
+            path.push(format!("prover_params/{}.params", &param));
+            Ok(path)
+        }
+    }
+}
+
+pub fn get_multi_role(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let (tau, use_poseidon) = voting_circuit(matches);
+    let poseidon_prf = matches.is_present("poseidon_prf");
+
+    let num_roles = value_t!(matches, "num_roles", usize).unwrap_or(2).max(1);
+    let merkle_heights = get_merkle_heights(Some(matches), use_poseidon)?;
+
+    let path = multi_role_params_path(&tau, num_roles, use_poseidon, poseidon_prf, &merkle_heights)?;
+
+    let mut configs = vec![];
+
+    if !path.exists() {
+        println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+        println!("{} ", path.file_stem().unwrap().to_str().unwrap());
+        println!();
+
+        configs.push(RunConfig {
+            tau: tau.clone(),
+            is_bp: false,
+            merkle_heights,
+            test_constraint_system: true,
+            check_params: false,
+            mode: RunMode::MultiRoleGenParams(path.clone(), num_roles),
+            use_poseidon,
+            delegated: false,
+            poseidon_prf,
+            threads: None,
+            seed: None,
+        });
+    }
+
+    let trials = value_t!(matches, "trials", u32).unwrap_or(5);
+
+    let threads = value_t!(matches, "threads", usize).unwrap_or(num_cpus::get());
+    let version = matches.value_of("output").unwrap_or("0");
+
+    let output_file = match env::current_dir() {
+        Err(e) => return Err(Error::CurrentDir(e)),
+        Ok(mut output_file) => {
+            let param = path.file_stem().unwrap().to_str().unwrap();
+            output_file.push(format!("benchmarks/{}_{}_threads_multi_role_v{}.csv", param, threads, version));
+            output_file
+        }
+    };
+
+    configs.push(RunConfig {
+        tau,
+        is_bp: false,
+        merkle_heights,
+        test_constraint_system: false,
+        check_params: false,
+        mode: RunMode::MultiRoleSingle(path, output_file, trials, num_roles),
+        use_poseidon,
+        delegated: false,
+        poseidon_prf,
+        threads: Some(threads),
+        seed: None,
+    });
+
+    Ok(configs)
+}
+
+pub fn multi_coin_params_path(tau: &TauValue, num_coins: usize, use_poseidon: bool, poseidon_prf: bool, merkle_heights: &MerkleHeights) -> Result<PathBuf, Error> {
+    let tau: &str = tau.into();
+    let up = if use_poseidon { "" } else { "_no_poseidon" };
+    let prf = if poseidon_prf { "_poseidon_prf" } else { "" };
+    let heights = heights_suffix(merkle_heights, use_poseidon);
+    let param = format!("{}_coins{}{}{}{}", tau, num_coins, up, prf, heights);
+
+    match env::current_dir() {
+        Err(e) => Err(Error::CurrentDir(e)),
+        Ok(mut path) => {
+            path.push(format!("prover_params/{}.params", &param));
+            Ok(path)
+        }
+    }
+}
+
+pub fn get_multi_coin(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let (tau, use_poseidon) = voting_circuit(matches);
+    let poseidon_prf = matches.is_present("poseidon_prf");
+
+    let num_coins = value_t!(matches, "num_coins", usize).unwrap_or(2).max(1);
+    let merkle_heights = get_merkle_heights(Some(matches), use_poseidon)?;
+
+    let path = multi_coin_params_path(&tau, num_coins, use_poseidon, poseidon_prf, &merkle_heights)?;
+
+    let mut configs = vec![];
+
+    if !path.exists() {
+        println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+        println!("{} ", path.file_stem().unwrap().to_str().unwrap());
+        println!();
+
+        configs.push(RunConfig {
+            tau: tau.clone(),
+            is_bp: false,
+            merkle_heights,
+            test_constraint_system: true,
+            check_params: false,
+            mode: RunMode::MultiCoinGenParams(path.clone(), num_coins),
+            use_poseidon,
+            delegated: false,
+            poseidon_prf,
+            threads: None,
+            seed: None,
+        });
+    }
+
+    configs.push(RunConfig {
+        tau,
+        is_bp: false,
+        merkle_heights,
+        test_constraint_system: true,
+        check_params: false,
+        mode: RunMode::MultiCoinSample(path, num_coins),
+        use_poseidon,
+        delegated: false,
+        poseidon_prf,
+        threads: None,
+        seed: None,
+    });
+
+    Ok(configs)
+}
+
+pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, Error> {
     let is_batch;
 
     let single_batch = {
@@ -240,44 +862,35 @@ pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, C
     };
 
     if let Some(matches) = matches.subcommand_matches(single_batch) {
-        let (tau, is_bp, use_poseidon) = {
-            let num: u32 = value_t!(matches, "role", u32).unwrap_or(0) % 8;
-
-            match num {
-                0 => (Tau20, true, true),
-                1 => (Tau20, true, false),
-                2 => (Tau1500, false, true),
-                3 => (Tau1500, false, false),
-                4 => (Tau2990, false, true),
-                5 => (Tau2990, false, false),
-                6 => (Tau5000, false, true),
-                7 => (Tau5000, false, false),
-                _ => (Tau2000, false, false)
-            }
-        };
+        let (tau, is_bp, use_poseidon) = sortition_circuit(matches);
+
+        let merkle_heights = get_merkle_heights(Some(matches), use_poseidon)?;
 
-        let merkle_height = if use_poseidon { 10 } else { 29 };
+        let delegated = matches.is_present("delegated");
+        let poseidon_prf = matches.is_present("poseidon_prf");
 
         let trials = value_t!(matches, "trials", u32).unwrap_or(5);
 
         let threads = value_t!(matches, "threads", usize).unwrap_or(num_cpus::get());
         let version = matches.value_of("output").unwrap_or("0");
 
-        implementation::NUM_CPUS.store(threads, Ordering::SeqCst);
-        implementation::HAS_LOADED.store(true, Ordering::SeqCst);
+        let mut configs = vec![];
 
         let mode = {
             let param = {
                 let tau: &str = (&tau).into();
                 let bp = if is_bp { "_block_proposer" } else { "" };
                 let up = if use_poseidon { "" } else { "_no_poseidon" };
-                format!("{}{}{}", tau, bp, up)
+                let del = if delegated { "_delegated" } else { "" };
+                let prf = if poseidon_prf { "_poseidon_prf" } else { "" };
+                let heights = heights_suffix(&merkle_heights, use_poseidon);
+                format!("{}{}{}{}{}{}", tau, bp, up, del, prf, heights)
             };
 
             let (path, output_file) = {
                 let path = env::current_dir();
                 let path = match path {
-                    Err(_) => return Err(CLIError::CannotAccessCWD),
+                    Err(e) => return Err(Error::CurrentDir(e)),
                     Ok(mut path) => {
                         path.push(format!("prover_params/{}.params", &param));
                         path
@@ -286,7 +899,7 @@ pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, C
 
                 let output_file = env::current_dir();
                 let output_file = match output_file {
-                    Err(_) => return Err(CLIError::CannotAccessCWD),
+                    Err(e) => return Err(Error::CurrentDir(e)),
                     Ok(mut path) => {
                         path.push(format!("benchmarks/{}_{}_threads_{}_v{}.csv", &param, threads, single_batch, version));
                         path
@@ -296,6 +909,27 @@ pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, C
                 (path, output_file)
             };
 
+            //get_params_gen(None) only generates the parameters for coins that are not delegated and use mimc, at the default heights
+            if (delegated || poseidon_prf || has_heights(Some(matches))) && !path.exists() {
+                println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+                println!("{} ", param);
+                println!();
+
+                configs.push(RunConfig {
+                    tau: tau.clone(),
+                    is_bp,
+                    merkle_heights,
+                    test_constraint_system: true,
+                    check_params: false,
+                    mode: RunMode::OnlyGenParams(path.clone()),
+                    use_poseidon,
+                    delegated,
+                    poseidon_prf,
+                    threads: None,
+                    seed: None,
+                });
+            }
+
             if is_batch {
                 let num_batch = value_t!(matches, "num_batch", u32).unwrap_or(24);
                 RunMode::Batch(path, output_file, trials, num_batch)
@@ -308,14 +942,19 @@ pub fn read_command_line_params(matches: ArgMatches) -> Result<Vec<RunConfig>, C
         let config = RunConfig {
             tau,
             is_bp,
-            merkle_height: merkle_height,
+            merkle_heights: merkle_heights,
             test_constraint_system: false,
             check_params: false,
             mode: mode,
             use_poseidon,
+            delegated,
+            poseidon_prf,
+            threads: Some(threads),
+            seed: None,
         };
 
-        return Ok(vec![config]);
+        configs.push(config);
+        return Ok(configs);
     }
     else {
         return Ok(vec![]);
@@ -1,5 +1,7 @@
 use ff::{PrimeField, ScalarEngine};
 
+use crate::constants::PrfDomain;
+
 pub type MiMCConstant<E> = [<E as ScalarEngine>::Fr; 162];
 
 pub struct MiMCConstants<E: ScalarEngine> {
@@ -64,4 +66,18 @@ impl<E: ScalarEngine> MiMCConstants<E> {
             exponent: 5
         }
     }
-}
\ No newline at end of file
+}
+
+impl<E: ScalarEngine> MiMCConstants<E> {
+    pub fn domain(&self, domain: PrfDomain) -> &MiMCConstant<E> {
+        match domain {
+            PrfDomain::Addr => &self.prf_addr,
+            PrfDomain::Sn => &self.prf_sn,
+            PrfDomain::Pk => &self.prf_pk,
+            PrfDomain::Tsn => &self.prf_tsn,
+            PrfDomain::Priority => &self.prf_priority,
+            PrfDomain::Seed => &self.prf_seed,
+            PrfDomain::Sel(i) => &self.prf_sel[i],
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use blake2b_simd::Params;
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+
+use crate::constants::mimc_constants::{MiMCConstant, MiMCConstants};
+use crate::constants::poseidon_generator::log2_modulus;
+use crate::constants::PrfDomain;
+
+/*
+nothing up my sleeve mimc round constants
+round constant i of a prf is blake2b-512(domain string || i as 4 little endian bytes) reduced modulo the field,
+e.g. "anonstake.mimc.prf_sel.7" for the 8th selection prf
+round constant 0 is always zero, like in the committed constants (see scripts/mimc_constants.py)
+
+the committed constants in mimc_constants.rs are thread_rng output, they are not reproduced by this, see compare()
+*/
+
+const MIMC_PERSONALIZATION: &[u8; 16] = b"AnonStake_MiMC__";
+
+pub const ALL_DOMAINS: [PrfDomain; 6] = [PrfDomain::Addr, PrfDomain::Sn, PrfDomain::Pk, PrfDomain::Tsn, PrfDomain::Priority, PrfDomain::Seed];
+
+pub fn domain_string(domain: PrfDomain) -> String {
+    match domain {
+        PrfDomain::Addr => "anonstake.mimc.prf_addr".to_owned(),
+        PrfDomain::Sn => "anonstake.mimc.prf_sn".to_owned(),
+        PrfDomain::Pk => "anonstake.mimc.prf_pk".to_owned(),
+        PrfDomain::Tsn => "anonstake.mimc.prf_tsn".to_owned(),
+        PrfDomain::Priority => "anonstake.mimc.prf_priority".to_owned(),
+        PrfDomain::Seed => "anonstake.mimc.prf_seed".to_owned(),
+        PrfDomain::Sel(i) => format!("anonstake.mimc.prf_sel.{}", i),
+    }
+}
+
+//little endian bytes reduced modulo the field
+fn from_bytes_wide<F: PrimeField>(bytes: &[u8]) -> F {
+    let mut byte_size = F::one();
+    for _ in 0..8 {
+        byte_size.double();
+    }
+
+    let mut elem = F::zero();
+    for byte in bytes.iter().rev() {
+        elem.mul_assign(&byte_size);
+
+        let mut repr = F::Repr::default();
+        repr.as_mut()[0] = *byte as u64;
+        elem.add_assign(&F::from_repr(repr).unwrap());
+    }
+
+    elem
+}
+
+pub fn derive_constant<F: PrimeField>(domain: &str, round: usize) -> F {
+    if round == 0 {
+        return F::zero();
+    }
+
+    let hash = Params::new()
+        .hash_length(64)
+        .personal(MIMC_PERSONALIZATION)
+        .to_state()
+        .update(domain.as_bytes())
+        .update(&(round as u32).to_le_bytes())
+        .finalize();
+
+    from_bytes_wide(hash.as_bytes())
+}
+
+pub fn derive_constants<E: ScalarEngine>(domain: PrfDomain) -> MiMCConstant<E> {
+    let domain = domain_string(domain);
+    let mut constants = [E::Fr::zero(); 162];
+
+    for i in 0..162 {
+        constants[i] = derive_constant(&domain, i);
+    }
+
+    constants
+}
+
+//(p - 1) mod d, to check that x^d is a permutation
+fn modulus_minus_one_rem<F: PrimeField>(d: u64) -> u64 {
+    let mut rem: u128 = 0;
+    for limb in F::char().as_ref().iter().rev() {
+        rem = ((rem << 64) + *limb as u128) % d as u128;
+    }
+
+    ((rem + d as u128 - 1) % d as u128) as u64
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/*
+the circuit computes x^(2^exponent - 1) per round with exponent squarings and one multiplication,
+which is only a permutation if gcd(2^exponent - 1, p - 1) = 1
+the number of rounds is ceil(log_d(p)) with d = 2^exponent - 1, so that the degree of the whole prf is at least p
+returns None if x^d is not a permutation or needs more rounds than there are constants
+*/
+pub fn num_rounds<F: PrimeField>(exponent: usize) -> Option<usize> {
+    if exponent < 2 || exponent > 63 {
+        return None;
+    }
+
+    let d = (1u64 << exponent) - 1;
+    if gcd(d, modulus_minus_one_rem::<F>(d)) != 1 {
+        return None;
+    }
+
+    let rounds = (log2_modulus::<F>() / (d as f64).log2()).ceil() as usize;
+    if rounds > 162 {
+        return None;
+    }
+
+    Some(rounds)
+}
+
+//constraints of one mimc prf, see mimc_round
+pub fn num_constraints(num_rounds: usize, exponent: usize) -> usize {
+    num_rounds * (exponent + 1)
+}
+
+impl<E: ScalarEngine> MiMCConstants<E> {
+    pub fn derived(num_rounds: usize, exponent: usize) -> MiMCConstants<E> {
+        let mut prf_sel = [[E::Fr::zero(); 162]; 20];
+        for i in 0..20 {
+            prf_sel[i] = derive_constants::<E>(PrfDomain::Sel(i));
+        }
+
+        MiMCConstants {
+            prf_addr: derive_constants::<E>(PrfDomain::Addr),
+            prf_sn: derive_constants::<E>(PrfDomain::Sn),
+            prf_pk: derive_constants::<E>(PrfDomain::Pk),
+            prf_tsn: derive_constants::<E>(PrfDomain::Tsn),
+            prf_priority: derive_constants::<E>(PrfDomain::Priority),
+            prf_seed: derive_constants::<E>(PrfDomain::Seed),
+            prf_sel,
+            num_rounds,
+            exponent,
+        }
+    }
+}
+
+//how the committed constants differ from the derived ones, empty if they are the same
+pub fn compare<E: ScalarEngine>(committed: &MiMCConstants<E>, derived: &MiMCConstants<E>) -> Vec<String> {
+    let mut report = vec![];
+
+    if committed.num_rounds != derived.num_rounds || committed.exponent != derived.exponent {
+        report.push(format!("rounds: committed {} rounds of x^(2^{} - 1), derived {} rounds of x^(2^{} - 1)",
+                            committed.num_rounds, committed.exponent, derived.num_rounds, derived.exponent));
+    }
+
+    let domains = ALL_DOMAINS.iter().cloned().chain((0..20).map(PrfDomain::Sel));
+    for domain in domains {
+        let a = committed.domain(domain);
+        let b = derived.domain(domain);
+
+        let different: Vec<usize> = (0..162).filter(|&i| a[i] != b[i]).collect();
+        if different.len() != 0 {
+            report.push(format!("{}: {} of 162 constants differ, first at {}", domain_string(domain), different.len(), different[0]));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use super::*;
+
+    #[test]
+    fn committed_round_numbers() {
+        let committed = MiMCConstants::<Bls12>::get();
+
+        assert_eq!(num_rounds::<Fr>(committed.exponent), Some(committed.num_rounds));
+        assert_eq!(num_rounds::<Fr>(3), Some(91));
+
+        //3 divides p - 1, so x^3 and x^15 are not permutations
+        assert_eq!(num_rounds::<Fr>(2), None);
+        assert_eq!(num_rounds::<Fr>(4), None);
+    }
+
+    #[test]
+    fn derived_constants() {
+        assert_eq!(derive_constant::<Fr>("anonstake.mimc.prf_addr", 0), Fr::zero());
+        assert_eq!(derive_constant::<Fr>("anonstake.mimc.prf_addr", 1),
+                   Fr::from_str("27011396117030781314185939284495020170841359053191082931455699157103517412476").unwrap());
+        assert_eq!(derive_constants::<Bls12>(PrfDomain::Sel(7))[161],
+                   Fr::from_str("12569301496398862808240473947772312528504288277709704247059096290695520725346").unwrap());
+    }
+
+    #[test]
+    fn committed_constants_are_not_derived() {
+        let committed = MiMCConstants::<Bls12>::get();
+        let derived = MiMCConstants::<Bls12>::derived(committed.num_rounds, committed.exponent);
+
+        //only the zero constants agree
+        let report = compare(&committed, &derived);
+        assert_eq!(report.len(), 26);
+        assert!(report[0].starts_with("anonstake.mimc.prf_addr: 161 of 162 constants differ, first at 1"));
+
+        assert_eq!(compare(&derived, &MiMCConstants::<Bls12>::derived(52, 5)).len(), 0);
+    }
+}
@@ -3,12 +3,62 @@ use zcash_primitives::jubjub::{JubjubEngine};
 use crate::constants::mimc_constants::MiMCConstants;
 
 pub mod mimc_constants;
+pub mod mimc_generator;
 pub mod binomial_constants;
 pub mod poseidon_constants;
+pub mod poseidon_generator;
 pub mod binomial_constants_impl;
 
+//first bits of the full_pk hash, a delegated full_pk is hashed in its own domain
+pub const FULL_PK_TAG: u64 = 1;
+pub const FULL_PK_DELEGATED_TAG: u64 = 2;
+pub const FULL_PK_TAG_BITS: usize = 8;
+
+/*
+which prf is used for a_pk, sn, tsn, h, priority, seed_comp and the selection prfs
+mimc is the prf of the paper, 52 rounds of x^31
+the poseidon prf is PRF_key(x) = poseidon([key, domain tag, x, 0, 0, 0, 0, 0]) with the same 9 wide permutation as the hash
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrfKind {
+    MiMC,
+    Poseidon,
+}
+
+//separates the prfs from each other: selects the mimc round constants or the poseidon domain tag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrfDomain {
+    Addr,
+    Sn,
+    Pk,
+    Tsn,
+    Priority,
+    Seed,
+    Sel(usize),
+}
+
+impl PrfDomain {
+    pub fn tag(&self) -> u64 {
+        match self {
+            PrfDomain::Addr => 1,
+            PrfDomain::Sn => 2,
+            PrfDomain::Pk => 3,
+            PrfDomain::Tsn => 4,
+            PrfDomain::Priority => 5,
+            PrfDomain::Seed => 6,
+            PrfDomain::Sel(i) => 7 + *i as u64,
+        }
+    }
+
+    //domain of the poseidon sponge for the keyed poseidon prf, the capacity tag keeps it apart from the fixed length hash
+    pub fn sponge_domain(&self) -> u32 {
+        self.tag() as u32
+    }
+}
+
 pub struct Constants<'a, E: JubjubEngine>{
     pub mimc: mimc_constants::MiMCConstants<E>,
+    pub prf: PrfKind,
     pub jubjub: &'a E::Params,
     pub binomial: binomial_constants::BinomialConstants<E>,
     pub poseidon: poseidon_constants::PoseidonConstants<E>,
@@ -21,6 +71,7 @@ impl<'a, E: ScalarEngine + JubjubEngine> Constants<'_, E> {
     pub fn get(jubjub: &'a E::Params, tau_value: binomial_constants::TauValue) -> Constants<'_, E> {
         Constants {
             mimc: MiMCConstants::get(),
+            prf: PrfKind::MiMC,
             jubjub: jubjub,
             binomial: tau_value.new(),
             poseidon: poseidon_constants::PoseidonConstants::<E>::get(),
@@ -29,4 +80,9 @@ impl<'a, E: ScalarEngine + JubjubEngine> Constants<'_, E> {
         }
     }
 
+    pub fn with_prf(mut self, prf: PrfKind) -> Self {
+        self.prf = prf;
+        self
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,339 @@
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+
+use crate::constants::poseidon_constants::PoseidonConstants;
+
+/*
+generates poseidon parameters the same way as the reference scripts of the poseidon paper
+(generate_parameters_grain.sage and calc_round_numbers.py), only for prime fields and the x^5 sbox
+
+the grain lfsr is seeded with the field, sbox, field size, width, r_f and r_p
+it produces the round constants first (rejection sampling, big endian) and then the 2 * width
+elements x_i, y_j of the cauchy mds matrix 1 / (x_i + y_j)
+the reference script also checks the mds matrix for infinitely long invariant subspace trails,
+that check is not done here
+
+the committed width 9 tables in poseidon_constants.rs were made by scripts/gen_poseidon_round.py and
+scripts/genmds.py from random inputs, they are not reproduced by this, see compare()
+*/
+
+pub const ALPHA: u64 = 5;
+
+pub struct GrainLfsr {
+    state: [bool; 80],
+}
+
+impl GrainLfsr {
+    pub fn new(field_size: usize, width: usize, r_f: usize, r_p: usize) -> GrainLfsr {
+        let mut init = vec![];
+        let mut push = |value: usize, num_bits: usize| {
+            for i in (0..num_bits).rev() {
+                init.push((value >> i) & 1 == 1);
+            }
+        };
+
+        //prime field, x^alpha sbox
+        push(1, 2);
+        push(0, 4);
+        push(field_size, 12);
+        push(width, 12);
+        push(r_f, 10);
+        push(r_p, 10);
+        push((1 << 30) - 1, 30);
+
+        let mut state = [false; 80];
+        state.copy_from_slice(&init);
+
+        let mut lfsr = GrainLfsr { state };
+        for _ in 0..160 {
+            lfsr.update();
+        }
+
+        lfsr
+    }
+
+    fn update(&mut self) -> bool {
+        let s = &self.state;
+        let new_bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+
+        for i in 0..79 {
+            self.state[i] = self.state[i + 1];
+        }
+        self.state[79] = new_bit;
+
+        new_bit
+    }
+
+    //self shrinking: of every pair of bits the second one is output if the first one is 1
+    pub fn next_bit(&mut self) -> bool {
+        loop {
+            let first = self.update();
+            let second = self.update();
+
+            if first {
+                return second;
+            }
+        }
+    }
+
+    //big endian, rejects values that are not smaller than the modulus
+    pub fn next_field_element<F: PrimeField>(&mut self) -> F {
+        loop {
+            let mut repr = F::Repr::default();
+            for _ in 0..F::NUM_BITS {
+                repr.mul2();
+                if self.next_bit() {
+                    repr.as_mut()[0] |= 1;
+                }
+            }
+
+            if let Ok(elem) = F::from_repr(repr) {
+                return elem;
+            }
+        }
+    }
+
+    //big endian, reduced modulo the field like F(grain_random_bits(n)) in the reference script
+    pub fn next_field_element_reduced<F: PrimeField>(&mut self) -> F {
+        let mut elem = F::zero();
+        for _ in 0..F::NUM_BITS {
+            elem.double();
+            if self.next_bit() {
+                elem.add_assign(&F::one());
+            }
+        }
+
+        elem
+    }
+}
+
+pub fn log2_modulus<F: PrimeField>() -> f64 {
+    let mut p = 0f64;
+    for limb in F::char().as_ref().iter().rev() {
+        p = p * 2f64.powi(64) + *limb as f64;
+    }
+
+    p.log2()
+}
+
+fn log_base(x: f64, base: f64) -> f64 {
+    x.ln() / base.ln()
+}
+
+fn log2_binomial(n: f64, k: f64) -> f64 {
+    let mut result = 0f64;
+    let mut i = 0f64;
+    while i < k {
+        result += ((n - i) / (k - i)).log2();
+        i += 1.0;
+    }
+
+    result
+}
+
+//the statistical, interpolation and groebner basis bounds of the paper, plus the one from eprint 2023/537
+fn is_secure(log2_p: f64, field_size: usize, width: usize, r_f: usize, r_p: usize, security: usize) -> bool {
+    let (t, r_f, r_p, m, n) = (width as f64, r_f as f64, r_p as f64, security as f64, field_size as f64);
+    let alpha = ALPHA as f64;
+
+    let r_f_1 = if m <= (log2_p - (alpha - 1.0) / 2.0).floor() * (t + 1.0) { 6.0 } else { 10.0 };
+    let r_f_2 = 1.0 + (log_base(2.0, alpha) * m.min(n)).ceil() + log_base(t, alpha).ceil() - r_p;
+    let r_f_3 = log_base(2.0, alpha) * m.min(log2_p) - r_p;
+    let r_f_4 = t - 1.0 + log_base(2.0, alpha) * (m / (t + 1.0)).min(log2_p / 2.0) - r_p;
+    let r_f_5 = (t - 2.0 + m / (2.0 * alpha.log2()) - r_p) / (t - 1.0);
+
+    let r_f_max = [r_f_1, r_f_2, r_f_3, r_f_4, r_f_5].iter().fold(0f64, |max, r| max.max(r.ceil()));
+
+    let r_temp = (t / 3.0).floor();
+    let over = (r_f - 1.0) * t + r_p + r_temp + r_temp * (r_f / 2.0) + r_p + alpha;
+    let under = r_temp * (r_f / 2.0) + r_p + alpha;
+    let cost_gb4 = (2.0 * log2_binomial(over, under)).ceil();
+
+    r_f >= r_f_max && cost_gb4 >= m
+}
+
+//cheapest (r_f, r_p) in number of sboxes, with the security margin of the paper: r_f + 2 and r_p * 1.075
+pub fn round_numbers<F: PrimeField>(width: usize, security: usize) -> (usize, usize) {
+    let log2_p = log2_modulus::<F>();
+    let field_size = F::NUM_BITS as usize;
+
+    let mut best: Option<(usize, usize, usize)> = None;
+
+    for r_p in 1..500 {
+        for r_f in (4..100).step_by(2) {
+            if is_secure(log2_p, field_size, width, r_f, r_p, security) {
+                let cost = width * r_f + r_p;
+                if best.map_or(true, |(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, r_f, r_p));
+                }
+
+                break;
+            }
+        }
+    }
+
+    let (_, r_f, r_p) = best.expect("no secure round numbers");
+    (r_f + 2, (r_p as f64 * 1.075).ceil() as usize)
+}
+
+pub struct PoseidonParameters<F: PrimeField> {
+    pub width: usize,
+    pub security: usize,
+    pub r_f: usize,
+    pub r_p: usize,
+    //r_f + r_p rows of width constants
+    pub rounds: Vec<Vec<F>>,
+    pub mds: Vec<Vec<F>>,
+}
+
+pub fn generate<F: PrimeField>(width: usize, security: usize) -> PoseidonParameters<F> {
+    let (r_f, r_p) = round_numbers::<F>(width, security);
+    let mut grain = GrainLfsr::new(F::NUM_BITS as usize, width, r_f, r_p);
+
+    let rounds = (0..r_f + r_p)
+        .map(|_| (0..width).map(|_| grain.next_field_element::<F>()).collect())
+        .collect();
+
+    let mds = loop {
+        let mut elems: Vec<F> = (0..2 * width).map(|_| grain.next_field_element_reduced::<F>()).collect();
+
+        //all 2 * width elements have to be distinct, otherwise all of them are drawn again
+        while (0..elems.len()).any(|i| elems[i + 1..].contains(&elems[i])) {
+            elems = (0..2 * width).map(|_| grain.next_field_element_reduced::<F>()).collect();
+        }
+
+        let (xs, ys) = elems.split_at(width);
+
+        let mds: Vec<Vec<Option<F>>> = xs.iter()
+            .map(|x| ys.iter().map(|y| {
+                let mut sum = *x;
+                sum.add_assign(y);
+                sum.inverse()
+            }).collect())
+            .collect();
+
+        if mds.iter().all(|row| row.iter().all(|entry| entry.is_some())) {
+            break mds.into_iter().map(|row| row.into_iter().map(|entry| entry.unwrap()).collect()).collect();
+        }
+    };
+
+    PoseidonParameters {
+        width,
+        security,
+        r_f,
+        r_p,
+        rounds,
+        mds,
+    }
+}
+
+impl<E: ScalarEngine> PoseidonConstants<E> {
+    //only possible for the width 9, 65 round shape that the circuit uses
+    pub fn from_generated(params: &PoseidonParameters<E::Fr>) -> Option<PoseidonConstants<E>> {
+        if params.width != 9 || params.rounds.len() != 65 {
+            return None;
+        }
+
+        let mut constants = PoseidonConstants::<E>::get();
+        for i in 0..9 {
+            for j in 0..9 {
+                constants.mds[i][j] = params.mds[i][j];
+            }
+        }
+
+        for round in 0..65 {
+            for i in 0..9 {
+                constants.rounds[round][i] = params.rounds[round][i];
+            }
+        }
+
+        constants.r_f = params.r_f;
+        constants.r_p = params.r_p;
+
+        Some(constants)
+    }
+}
+
+//how the committed constants differ from the generated ones, empty if they are the same
+pub fn compare<E: ScalarEngine>(committed: &PoseidonConstants<E>, generated: &PoseidonParameters<E::Fr>) -> Vec<String> {
+    let mut report = vec![];
+
+    if generated.width != 9 {
+        report.push(format!("width: committed 9, generated {}", generated.width));
+        return report;
+    }
+
+    if committed.r_f != generated.r_f || committed.r_p != generated.r_p {
+        report.push(format!("round numbers: committed r_f = {}, r_p = {}, generated r_f = {}, r_p = {}",
+                            committed.r_f, committed.r_p, generated.r_f, generated.r_p));
+    }
+
+    let num_rounds = generated.rounds.len().min(committed.rounds.len());
+    let different_rounds: Vec<(usize, usize)> = (0..num_rounds)
+        .flat_map(|round| (0..9).map(move |i| (round, i)))
+        .filter(|&(round, i)| committed.rounds[round][i] != generated.rounds[round][i])
+        .collect();
+
+    if different_rounds.len() != 0 {
+        let (round, i) = different_rounds[0];
+        report.push(format!("round constants: {} of {} differ, first at round {} element {}: committed {}, generated {}",
+                            different_rounds.len(), num_rounds * 9, round, i,
+                            committed.rounds[round][i], generated.rounds[round][i]));
+    }
+
+    let different_mds = (0..9)
+        .flat_map(|i| (0..9).map(move |j| (i, j)))
+        .filter(|&(i, j)| committed.mds[i][j] != generated.mds[i][j])
+        .count();
+
+    if different_mds != 0 {
+        report.push(format!("mds matrix: {} of 81 entries differ", different_mds));
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use super::*;
+
+    #[test]
+    fn round_numbers_match_committed() {
+        let committed = PoseidonConstants::<Bls12>::get();
+
+        assert_eq!(round_numbers::<Fr>(9, 128), (committed.r_f, committed.r_p));
+        assert_eq!(round_numbers::<Fr>(3, 128), (8, 56));
+    }
+
+    #[test]
+    fn grain_round_constants() {
+        //first round constant of the reference poseidonperm_x5_255_3 parameters
+        let mut grain = GrainLfsr::new(255, 3, 8, 56);
+        assert_eq!(grain.next_field_element::<Fr>(),
+                   Fr::from_str("50207570499218320245539736680169582180207201335688461025883902752909290481781").unwrap());
+
+        let params = generate::<Fr>(9, 128);
+        assert_eq!(params.rounds[0][0],
+                   Fr::from_str("51456871630395278065627483917901523970718884366549119139144234240744684354360").unwrap());
+        assert_eq!(params.rounds[64][8],
+                   Fr::from_str("9233945803129615540035765907097123246038471343318051181717532686790918642333").unwrap());
+        assert_eq!(params.mds[0][0],
+                   Fr::from_str("12929023787467701044434927689422385731071756681420195282613396560814280256210").unwrap());
+    }
+
+    #[test]
+    fn committed_tables_are_not_grain() {
+        let committed = PoseidonConstants::<Bls12>::get();
+        let generated = generate::<Fr>(9, 128);
+
+        //same shape, different constants
+        let report = compare(&committed, &generated);
+        assert_eq!(report.len(), 2);
+        assert!(report[0].starts_with("round constants: 585 of 585 differ"));
+        assert!(report[1].starts_with("mds matrix: 81 of 81"));
+
+        let regenerated = PoseidonConstants::<Bls12>::from_generated(&generated).unwrap();
+        assert_eq!(compare(&regenerated, &generated).len(), 0);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fmt, io};
+use std::path::{Path, PathBuf};
+
+use bellman::SynthesisError;
+
+/*
+errors of the command line program, main prints them and exits with exit_code
+
+the exit codes follow sysexits.h so that benchmark scripts can tell a missing params file
+apart from a proof that does not verify
+*/
+#[derive(Debug)]
+pub enum Error {
+    //reading or writing a file other than a params file
+    Io(PathBuf, io::Error),
+    DirectoryCreation(PathBuf, io::Error),
+    CurrentDir(io::Error),
+    ParamsMissing(PathBuf),
+    ParamsRead(PathBuf, io::Error),
+    ParamsWrite(PathBuf, io::Error),
+    //the circuit could not be synthesized, e.g. a witness is missing
+    Synthesis(SynthesisError),
+    Proving(SynthesisError),
+    Verification(SynthesisError),
+    //verification ran but returned false
+    InvalidProof(String),
+    Config(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidProof(_) => 1,
+            Error::Config(_) => 64,
+            Error::ParamsRead(_, _) => 65,
+            Error::ParamsMissing(_) => 66,
+            Error::Synthesis(_) | Error::Proving(_) | Error::Verification(_) => 70,
+            Error::CurrentDir(_) => 71,
+            Error::DirectoryCreation(_, _) | Error::ParamsWrite(_, _) => 73,
+            Error::Io(_, _) => 74,
+        }
+    }
+
+    pub fn io(path: &Path) -> impl Fn(io::Error) -> Error {
+        let path = path.to_path_buf();
+        move |e| Error::Io(path.clone(), e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "could not read or write {}: {}", path.display(), e),
+            Error::DirectoryCreation(path, e) =>
+                write!(f, "could not create directory {}: {}, check the permissions of the current directory", path.display(), e),
+            Error::CurrentDir(e) => write!(f, "could not access the current directory: {}", e),
+            Error::ParamsMissing(path) =>
+                write!(f, "params file {} does not exist, run ./main (or transfer_gen_params for the transfer circuit) to generate the missing params first", path.display()),
+            Error::ParamsRead(path, e) =>
+                write!(f, "could not read params file {}: {}, it may be truncated or made for another circuit; delete it and generate it again", path.display(), e),
+            Error::ParamsWrite(path, e) =>
+                write!(f, "could not write params file {}: {}, check that ./prover_params is writable and the disk is not full", path.display(), e),
+            Error::Synthesis(e) => write!(f, "could not synthesize the circuit: {}", e),
+            Error::Proving(e) => write!(f, "could not create the proof: {}, the params file may not match the circuit; delete it and generate it again", e),
+            Error::Verification(e) => write!(f, "could not verify the proof: {}", e),
+            Error::InvalidProof(what) => write!(f, "{} failed to verify, run check_witness to see which constraint the witness does not satisfy", what),
+            Error::Config(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,77 @@
+use std::io::Cursor;
+use std::slice;
+
+use sha2::{Digest, Sha256};
+
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+use pairing::bls12_381::Bls12;
+use zcash_primitives::sapling::merkle_hash;
+
+use crate::constants::poseidon_constants::PoseidonConstants;
+use crate::poseidon::poseidon_hash;
+
+/*
+baseline for the forward secure tree: plain key erasure, the keys of all roles come from a hash chain
+and the tree is a poseidon merkle tree over the leaves (see the keyerasure binary)
+*/
+pub fn increase(input: &mut [u8], times: usize) {
+    for _i in 0..times {
+        let mut hasher = Sha256::new();
+        hasher.input(input.as_ref());
+        hasher.input([0x02]);
+        let result = hasher.result();
+
+        for i in 0..input.len() {
+            input[i] = result[i];
+        }
+    }
+}
+
+pub fn tree_leaves(base: <Bls12 as ScalarEngine>::Fr, num: usize) -> Vec<<<Bls12 as ScalarEngine>::Fr as PrimeField>::Repr> {
+    type Field = <Bls12 as ScalarEngine>::Fr;
+
+    let mut base_repr = base.into_repr();
+    let base_repr: &mut [u64] = base_repr.as_mut();
+
+    let cur: &mut [u8] = unsafe {
+        slice::from_raw_parts_mut(base_repr.as_ptr() as *mut u8, base_repr.len() * 8)
+    };
+
+    let mut ans = Vec::with_capacity(num);
+    let mut role: u64 = 0;
+
+    for _i in 0..num {
+        increase(cur.as_mut(), 1);
+        role += 1;
+
+        let mut a: <Field as PrimeField>::Repr = Field::zero().into_repr();
+        a.read_be(Cursor::new(cur.as_ref())).unwrap();
+        let b = <Field as PrimeField>::Repr::from(role);
+
+        let leaf = merkle_hash(0, &a, &b);
+
+        ans.push(leaf);
+    }
+
+    return ans;
+}
+
+pub fn reduce(constants: &PoseidonConstants<Bls12>, elems: Vec<<<Bls12 as ScalarEngine>::Fr as PrimeField>::Repr>) -> <Bls12 as ScalarEngine>::Fr {
+    type Fr = <Bls12 as ScalarEngine>::Fr;
+    let mut cur: Vec<Fr> = Vec::with_capacity(elems.len());
+    for i in 0..elems.len() {
+        cur.push(Fr::from_repr(elems[i]).unwrap());
+    }
+
+    while cur.len() != 1 {
+        let mut next = Vec::with_capacity(cur.len() / 8);
+
+        for i in 0..(cur.len() / 8) {
+            next.push(poseidon_hash(constants, &cur[8 * i..8 * i + 8]));
+        }
+
+        cur = next;
+    }
+
+    return cur[0].clone();
+}
@@ -0,0 +1,13 @@
+/*
+circuit, constants, poseidon and forward_secure are the building blocks, api.rs proves and verifies with them
+the benchmark program (cli.rs, plan.rs and runner.rs) is part of the binary in src/main.rs, not of the library
+*/
+pub mod api;
+pub mod circuit;
+pub mod constants;
+pub mod error;
+pub mod forward_secure;
+pub mod native;
+pub mod poseidon;
+pub mod protocol;
+pub mod wallet;
@@ -0,0 +1,280 @@
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use zcash_primitives::jubjub::{FixedGenerators, JubjubEngine, JubjubParams};
+use zcash_primitives::pedersen_hash::{pedersen_hash, Personalization};
+
+use crate::circuit::hash::{CircuitHash, NativeHashInput};
+use crate::circuit::public_inputs::u64_to_fr;
+use crate::constants::{Constants, FULL_PK_DELEGATED_TAG, FULL_PK_TAG, FULL_PK_TAG_BITS, PrfDomain, PrfKind};
+use crate::poseidon::poseidon_sponge;
+
+/*
+native versions of the values computed inside the AnonStake circuit
+these have to match the circuit bit for bit, see circuit/mod.rs
+*/
+
+pub fn u64_bits_le(value: u64, num_bits: usize) -> Vec<bool> {
+    (0..num_bits).map(|i| i < 64 && (value >> i) & 1 == 1).collect()
+}
+
+//same as AllocatedNum::to_bits_le, i.e. NUM_BITS little endian bits
+pub fn fr_bits_le<F: PrimeField>(value: &F) -> Vec<bool> {
+    let mut repr = value.into_repr();
+    let mut bits = Vec::with_capacity(F::NUM_BITS as usize);
+
+    for _ in 0..F::NUM_BITS {
+        bits.push(repr.is_odd());
+        repr.div2();
+    }
+
+    bits
+}
+
+pub fn crh<E: JubjubEngine>(constants: &Constants<E>, bits: &[bool]) -> E::Fr {
+    pedersen_hash::<E, _>(Personalization::NoteCommitment, bits.iter().cloned(), constants.jubjub).to_xy().0
+}
+
+pub fn poseidon_pad<E: JubjubEngine>(elems: &[E::Fr]) -> [E::Fr; 8] {
+    let mut padded = [E::Fr::zero(); 8];
+    for i in 0..elems.len() {
+        padded[i] = elems[i];
+    }
+
+    padded
+}
+
+pub fn mimc_prf<E: JubjubEngine>(constants: &Constants<E>, sk: &E::Fr, input: &E::Fr, mimc_constants: &[E::Fr; 162]) -> E::Fr {
+    let exponent = [(1u64 << constants.mimc.exponent) - 1];
+    let mut cur = *input;
+
+    for i in 0..constants.mimc.num_rounds {
+        let mut tmp = *sk;
+        tmp.add_assign(&cur);
+        tmp.add_assign(&mimc_constants[i]);
+        cur = tmp.pow(exponent);
+    }
+
+    cur.add_assign(sk);
+    cur
+}
+
+//sponge over [key, input] with the tag of the prf domain as the sponge domain, same as the poseidon_prf gadget
+pub fn poseidon_prf<E: JubjubEngine>(constants: &Constants<E>, key: &E::Fr, input: &E::Fr, domain: PrfDomain) -> E::Fr {
+    poseidon_sponge(&constants.poseidon, domain.sponge_domain(), &[*key, *input])
+}
+
+//same as the prf gadget, uses the prf chosen in the constants
+pub fn prf<E: JubjubEngine>(constants: &Constants<E>, key: &E::Fr, input: &E::Fr, domain: PrfDomain) -> E::Fr {
+    match constants.prf {
+        PrfKind::MiMC => mimc_prf(constants, key, input, constants.mimc.domain(domain)),
+        PrfKind::Poseidon => poseidon_prf(constants, key, input, domain),
+    }
+}
+
+pub fn a_pk<E: JubjubEngine>(constants: &Constants<E>, a_sk: &E::Fr) -> E::Fr {
+    prf(constants, a_sk, &E::Fr::zero(), PrfDomain::Addr)
+}
+
+pub fn sn<E: JubjubEngine>(constants: &Constants<E>, a_sk: &E::Fr, rho: &E::Fr) -> E::Fr {
+    prf(constants, a_sk, rho, PrfDomain::Sn)
+}
+
+//same layout as constrain_full_pk
+pub fn full_pk<E: JubjubEngine>(constants: &Constants<E>, fs_tree_start: u64, fs_pk: &E::Fr, a_pk: &E::Fr) -> E::Fr {
+    let mut bits = u64_bits_le(FULL_PK_TAG, FULL_PK_TAG_BITS);
+    bits.extend(u64_bits_le(fs_tree_start, 64));
+    bits.extend(fr_bits_le(fs_pk));
+    bits.extend(fr_bits_le(a_pk));
+
+    crh(constants, &bits)
+}
+
+//full_pk of a delegated coin, a_pk_del is the a_pk of the delegate's participation key
+pub fn full_pk_delegated<E: JubjubEngine>(constants: &Constants<E>, fs_tree_start: u64, fs_pk: &E::Fr, a_pk_owner: &E::Fr, a_pk_del: &E::Fr) -> E::Fr {
+    let mut bits = u64_bits_le(FULL_PK_DELEGATED_TAG, FULL_PK_TAG_BITS);
+    bits.extend(u64_bits_le(fs_tree_start, 64));
+    bits.extend(fr_bits_le(fs_pk));
+    bits.extend(fr_bits_le(a_pk_owner));
+    bits.extend(fr_bits_le(a_pk_del));
+
+    crh(constants, &bits)
+}
+
+//same layout as constrain_coin_commitment, returns the x coordinate which is the leaf of the commitment tree
+pub fn coin_commitment<E: JubjubEngine>(constants: &Constants<E>, full_pk: &E::Fr, value: u64, rho: &E::Fr, s: &E::Fs) -> E::Fr {
+    let mut bits = u64_bits_le(value, 64);
+    bits.extend(fr_bits_le(full_pk));
+    bits.extend(fr_bits_le(rho));
+
+    let cm = pedersen_hash::<E, _>(Personalization::NoteCommitment, bits, constants.jubjub);
+    let rcm = constants.jubjub.generator(FixedGenerators::NoteCommitmentRandomness).mul(*s, constants.jubjub);
+
+    cm.add(&rcm, constants.jubjub).to_xy().0
+}
+
+//rho of new coin j of a transfer, sn_0 is the serial number of the first spent coin, same as in Transfer
+pub fn output_rho<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, sn_0: &E::Fr, j: u64) -> E::Fr {
+    H::native(constants, &[NativeHashInput::elem(*sn_0), NativeHashInput::elem(u64_to_fr(j))])
+}
+
+pub fn hash_role_seed<E: JubjubEngine>(constants: &Constants<E>, role: u64, seed: &E::Fr) -> E::Fr {
+    let mut bits = u64_bits_le(role, 64);
+    bits.extend(fr_bits_le(seed));
+
+    crh(constants, &bits)
+}
+
+pub fn tsn<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, a_sk: &E::Fr, rho: &E::Fr, role: u64, j_i: u64) -> E::Fr {
+    let hash = H::native(constants, &[NativeHashInput::elem(*rho), NativeHashInput::u64(role, 64), NativeHashInput::u64(j_i, 11)]);
+
+    prf(constants, a_sk, &hash, PrfDomain::Tsn)
+}
+
+pub fn h<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, a_sk: &E::Fr, h_sig: &E::Fr, role: u64) -> E::Fr {
+    let hash = H::native(constants, &[NativeHashInput::elem(*h_sig), NativeHashInput::u64(role, 64)]);
+
+    prf(constants, a_sk, &hash, PrfDomain::Pk)
+}
+
+pub fn priority<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, a_sk: &E::Fr, role: u64, seed: &E::Fr, j_i: u64) -> E::Fr {
+    let hash = H::native(constants, &[NativeHashInput::u64(role, 64), NativeHashInput::elem(*seed), NativeHashInput::u64(j_i, 11)]);
+
+    prf(constants, a_sk, &hash, PrfDomain::Priority)
+}
+
+pub fn seed_comp<E: JubjubEngine>(constants: &Constants<E>, a_sk: &E::Fr, r: u64, j_i: u64) -> E::Fr {
+    let mut bits = u64_bits_le(r, 64);
+    bits.extend(u64_bits_le(j_i, 11));
+    let hash = crh(constants, &bits);
+
+    prf(constants, a_sk, &hash, PrfDomain::Seed)
+}
+
+//80 bit chunk of a prf output, as used by calc_num_selections
+fn random_chunk<F: PrimeField>(value: &F, chunk: usize) -> F::Repr {
+    let mut repr = value.into_repr();
+    repr.shr(80 * chunk as u32);
+
+    let limbs = repr.as_mut();
+    limbs[1] &= 0xffff;
+    for limb in limbs[2..].iter_mut() {
+        *limb = 0;
+    }
+
+    repr
+}
+
+pub fn num_selections<E: JubjubEngine>(constants: &Constants<E>, a_sk: &E::Fr, role: u64, seed: &E::Fr, value: u64) -> u64 {
+    let hash = hash_role_seed(constants, role, seed);
+
+    let rand_values: Vec<E::Fr> = (0..20)
+        .map(|i| prf(constants, a_sk, &hash, PrfDomain::Sel(i)))
+        .collect();
+
+    let mut total = E::Fr::zero();
+
+    for i in 0..60 {
+        if constants.binomial.0[i].len() == 0 || (value >> i) & 1 == 0 {
+            continue;
+        }
+
+        let rand = random_chunk(&rand_values[i / 3], i % 3);
+
+        let mut num = constants.binomial.1[i];
+        for c in &constants.binomial.0[i] {
+            if rand >= c.into_repr() {
+                num.add_assign(&E::Fr::one());
+            }
+        }
+
+        total.add_assign(&num);
+    }
+
+    total.into_repr().as_ref()[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::ConstraintSystem;
+    use bellman::gadgets::boolean::{AllocatedBit, Boolean};
+    use bellman::gadgets::num::AllocatedNum;
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, thread_rng};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::AnonStake;
+    use crate::circuit::hash::Poseidon;
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    //calc_num_selections against num_selections, for stakes that use the regular and the binary search sampling
+    fn num_selections_match_circuit(tau: TauValue, values: &[u64]) {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, tau);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 1);
+
+        for value in values {
+            let a_sk = Fr::random(rng);
+            let seed = Fr::random(rng);
+            let role: u64 = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let value_bits = u64_bits_le(*value, 64).into_iter().enumerate()
+                .map(|(i, b)| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("value bit {}", i)), Some(b)).unwrap()))
+                .collect::<Vec<_>>();
+            let hash = AllocatedNum::alloc(cs.namespace(|| "hash"), || Ok(hash_role_seed(&constants, role, &seed))).unwrap();
+            let a_sk_var = AllocatedNum::alloc(cs.namespace(|| "a_sk"), || Ok(a_sk)).unwrap();
+
+            let num = anonstake.calc_num_selections(cs.namespace(|| "num selections"), "num selections", &value_bits, &hash, &a_sk_var).unwrap();
+
+            assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+            assert_eq!(num.get_value().unwrap(), u64_to_fr(num_selections(&constants, &a_sk, role, &seed, *value)), "value {}", value);
+        }
+    }
+
+    //bits 56 to 59 of tau1500 have offsets 14, 66, 195 and 488
+    #[test]
+    fn num_selections_tau1500() {
+        num_selections_match_circuit(TauValue::Tau1500, &[0, 1 << 12 | 5, (1 << 59) | (1 << 56) | 12345, (1 << 60) - 1]);
+    }
+
+    #[test]
+    fn num_selections_tau5000() {
+        num_selections_match_circuit(TauValue::Tau5000, &[1 << 20, thread_rng().gen::<u64>() >> 4]);
+    }
+
+    //constrain_full_pk against full_pk and full_pk_delegated, the tag keeps the two apart
+    #[test]
+    fn full_pk_match_circuit() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+        let anonstake = AnonStake::<Bls12, Poseidon>::init_empty(&constants, false, 1);
+
+        let fs_tree_start: u64 = rng.gen();
+        let fs_pk = Fr::random(rng);
+        let a_pk = Fr::random(rng);
+        let a_pk_del = Fr::random(rng);
+
+        let regular = full_pk(&constants, fs_tree_start, &fs_pk, &a_pk);
+        let delegated = full_pk_delegated(&constants, fs_tree_start, &fs_pk, &a_pk, &a_pk_del);
+        assert_ne!(regular, delegated);
+
+        for (delegate, expected) in vec![(None, regular), (Some(a_pk_del), delegated)] {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let fs_start_bits = u64_bits_le(fs_tree_start, 64).into_iter().enumerate()
+                .map(|(i, b)| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("fs start bit {}", i)), Some(b)).unwrap()))
+                .collect::<Vec<_>>();
+            let fs_pk_var = AllocatedNum::alloc(cs.namespace(|| "fs_pk"), || Ok(fs_pk)).unwrap();
+            let a_pk_var = AllocatedNum::alloc(cs.namespace(|| "a_pk"), || Ok(a_pk)).unwrap();
+            let delegate_var = delegate.map(|value| AllocatedNum::alloc(cs.namespace(|| "a_pk_del"), || Ok(value)).unwrap());
+
+            let result = anonstake.constrain_full_pk(cs.namespace(|| "full_pk"), "full_pk", fs_start_bits, fs_pk_var, a_pk_var, delegate_var).unwrap();
+
+            assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+            assert_eq!(result.get_value().unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use anonstake::circuit::anonstake_inputs::MerkleHeights;
+use anonstake::constants::binomial_constants::TauValue;
+use anonstake::constants::binomial_constants::TauValue::{Tau1500, Tau20, Tau2990, Tau5000};
+use anonstake::error::Error;
+
+use crate::cli::{param_name, RunConfig, RunMode};
+
+/*
+benchmark plans: a toml (or json, by extension) file with a list of single or batch runs, e.g.
+
+[defaults]
+threads = 4
+trials = 5
+
+[[run]]
+variant = "tau1500"
+mode = "batch"
+num_batch = 16
+merkle_height = 12
+sn_height = 8
+seed = 1
+output = "benchmarks/tau1500_cm12_sn8.csv"
+
+every field of a run can also be given in [defaults], the run wins
+merkle_height sets the height of both trees, cm_height and sn_height override it for one tree
+the whole plan is checked before anything runs, and missing params are generated first
+*/
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    #[serde(default)]
+    pub defaults: PlanRun,
+    #[serde(default, rename = "run")]
+    pub runs: Vec<PlanRun>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlanRun {
+    //the name of the params file without _delegated or _poseidon_prf, e.g. tau20_block_proposer or tau2990_no_poseidon
+    pub variant: Option<String>,
+    //single or batch
+    pub mode: Option<String>,
+    pub threads: Option<usize>,
+    pub trials: Option<u32>,
+    pub num_batch: Option<u32>,
+    pub merkle_height: Option<usize>,
+    pub cm_height: Option<usize>,
+    pub sn_height: Option<usize>,
+    pub seed: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub delegated: Option<bool>,
+    pub poseidon_prf: Option<bool>,
+    pub check_params: Option<bool>,
+}
+
+impl PlanRun {
+    //fields of self, and of defaults where self does not set them
+    fn or(&self, defaults: &PlanRun) -> PlanRun {
+        PlanRun {
+            variant: self.variant.clone().or(defaults.variant.clone()),
+            mode: self.mode.clone().or(defaults.mode.clone()),
+            threads: self.threads.or(defaults.threads),
+            trials: self.trials.or(defaults.trials),
+            num_batch: self.num_batch.or(defaults.num_batch),
+            merkle_height: self.merkle_height.or(defaults.merkle_height),
+            cm_height: self.cm_height.or(defaults.cm_height),
+            sn_height: self.sn_height.or(defaults.sn_height),
+            seed: self.seed.or(defaults.seed),
+            output: self.output.clone().or(defaults.output.clone()),
+            delegated: self.delegated.or(defaults.delegated),
+            poseidon_prf: self.poseidon_prf.or(defaults.poseidon_prf),
+            check_params: self.check_params.or(defaults.check_params),
+        }
+    }
+}
+
+pub const VARIANTS: [&str; 4] = ["tau20_block_proposer", "tau1500", "tau2990", "tau5000"];
+
+//(tau, is_bp, use_poseidon)
+pub fn parse_variant(variant: &str) -> Option<(TauValue, bool, bool)> {
+    let (base, use_poseidon) = match variant.ends_with("_no_poseidon") {
+        true => (&variant[..variant.len() - "_no_poseidon".len()], false),
+        false => (variant, true)
+    };
+
+    match base {
+        "tau20_block_proposer" => Some((Tau20, true, use_poseidon)),
+        "tau1500" => Some((Tau1500, false, use_poseidon)),
+        "tau2990" => Some((Tau2990, false, use_poseidon)),
+        "tau5000" => Some((Tau5000, false, use_poseidon)),
+        _ => None
+    }
+}
+
+pub fn parse_plan(contents: &str, json: bool) -> Result<Plan, String> {
+    if json {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    }
+}
+
+pub fn read_plan(file: &Path) -> Result<Plan, Error> {
+    let contents = fs::read_to_string(file).map_err(Error::io(file))?;
+    let json = file.extension().map_or(false, |ext| ext == "json");
+
+    parse_plan(&contents, json).map_err(|e| Error::Config(format!("could not parse {}: {}", file.display(), e)))
+}
+
+/*
+one RunConfig per run, paths are relative to dir (the current directory)
+returns every problem of the plan instead of only the first one
+*/
+pub fn expand_plan(plan: &Plan, dir: &Path) -> Result<Vec<RunConfig>, Vec<String>> {
+    let mut configs = vec![];
+    let mut errors = vec![];
+    let mut outputs = HashSet::new();
+
+    if plan.runs.len() == 0 {
+        errors.push(String::from("the plan has no [[run]] entries"));
+    }
+
+    for (i, run) in plan.runs.iter().enumerate() {
+        let run = run.or(&plan.defaults);
+        let mut error = |msg: String| errors.push(format!("run {}: {}", i + 1, msg));
+
+        let circuit = match &run.variant {
+            None => {
+                error(String::from("variant is missing"));
+                None
+            }
+            Some(variant) => match parse_variant(variant) {
+                None => {
+                    error(format!("unknown variant {}, choose one of {:?} (with _no_poseidon for pedersen)", variant, VARIANTS));
+                    None
+                }
+                circuit => circuit
+            }
+        };
+
+        let mode = run.mode.clone().unwrap_or(String::from("single"));
+        if mode != "single" && mode != "batch" {
+            error(format!("unknown mode {}, choose single or batch", mode));
+        }
+
+        let threads = run.threads.unwrap_or(num_cpus::get());
+        if threads == 0 {
+            error(String::from("threads has to be at least 1"));
+        }
+
+        let trials = run.trials.unwrap_or(5);
+        if trials == 0 {
+            error(String::from("trials has to be at least 1"));
+        }
+
+        let num_batch = run.num_batch.unwrap_or(24);
+        if mode == "batch" && num_batch < 2 {
+            error(format!("num_batch is {}, it has to be at least 2 (the additional proof time is the average of the proofs after the first)", num_batch));
+        }
+
+        let (tau, is_bp, use_poseidon) = match circuit {
+            Some(circuit) => circuit,
+            None => continue,
+        };
+
+        let default_heights = MerkleHeights::default_for(use_poseidon);
+        let merkle_heights = MerkleHeights::new(
+            run.cm_height.or(run.merkle_height).unwrap_or(default_heights.cm),
+            run.sn_height.or(run.merkle_height).unwrap_or(default_heights.sn),
+        );
+        if let Err(msg) = merkle_heights.check(use_poseidon) {
+            error(msg);
+        }
+
+        let mut config = RunConfig {
+            tau,
+            is_bp,
+            merkle_heights,
+            test_constraint_system: false,
+            check_params: run.check_params.unwrap_or(false),
+            mode: RunMode::OutputCircuitInfo,
+            use_poseidon,
+            delegated: run.delegated.unwrap_or(false),
+            poseidon_prf: run.poseidon_prf.unwrap_or(false),
+            threads: Some(threads),
+            seed: run.seed,
+        };
+
+        let param = param_name(&config);
+        let params_file = dir.join(format!("prover_params/{}.params", param));
+        let output_file = match &run.output {
+            Some(output) => dir.join(output),
+            None => dir.join(format!("benchmarks/{}_{}_threads_{}_plan{}.csv", param, threads, mode, i + 1)),
+        };
+
+        if !outputs.insert(output_file.clone()) {
+            error(format!("output {} is also the output of an earlier run", output_file.display()));
+        }
+
+        config.mode = match mode.as_str() {
+            "batch" => RunMode::Batch(params_file, output_file, trials, num_batch),
+            _ => RunMode::Single(params_file, output_file, trials),
+        };
+
+        configs.push(config);
+    }
+
+    if errors.len() != 0 {
+        return Err(errors);
+    }
+
+    Ok(configs)
+}
+
+fn files(config: &RunConfig) -> Option<(&PathBuf, &PathBuf)> {
+    match &config.mode {
+        RunMode::Single(params_file, output_file, _) => Some((params_file, output_file)),
+        RunMode::Batch(params_file, output_file, _, _) => Some((params_file, output_file)),
+        _ => None
+    }
+}
+
+pub fn get_plan(matches: &ArgMatches) -> Result<Vec<RunConfig>, Error> {
+    let file = PathBuf::from(matches.value_of("file").unwrap_or("plan.toml"));
+    let plan = read_plan(&file)?;
+    let dir = env::current_dir().map_err(Error::CurrentDir)?;
+
+    let problems = |errors: Vec<String>| Error::Config(format!("{} has {} problem(s):\n  {}", file.display(), errors.len(), errors.join("\n  ")));
+
+    let runs = expand_plan(&plan, &dir).map_err(|errors| problems(errors))?;
+
+    let mut errors = vec![];
+    for (i, config) in runs.iter().enumerate() {
+        if let Some((_, output_file)) = files(config) {
+            if let Some(parent) = output_file.parent() {
+                if !parent.is_dir() {
+                    errors.push(format!("run {}: the directory {} of the output does not exist", i + 1, parent.display()));
+                }
+            }
+        }
+    }
+
+    if errors.len() != 0 {
+        return Err(problems(errors));
+    }
+
+    let mut configs = vec![];
+    let mut generated = HashSet::new();
+
+    for config in &runs {
+        if let Some((params_file, _)) = files(config) {
+            if !params_file.exists() && generated.insert(params_file.clone()) {
+                let mut gen_params = config.clone();
+                gen_params.mode = RunMode::OnlyGenParams(params_file.clone());
+                gen_params.test_constraint_system = true;
+                gen_params.threads = None;
+                configs.push(gen_params);
+            }
+        }
+    }
+
+    if generated.len() != 0 {
+        println!("The following parameters for the following zk-SNARK circuit need to be generated: ");
+        for config in &configs {
+            println!("{} ", param_name(config));
+        }
+        println!();
+    }
+
+    if matches.is_present("dry_run") {
+        for (i, config) in runs.iter().enumerate() {
+            if let Some((_, output_file)) = files(config) {
+                let (mode, trials) = match &config.mode {
+                    RunMode::Batch(_, _, trials, num_batch) => (format!("batch of {}", num_batch), trials),
+                    RunMode::Single(_, _, trials) => (String::from("single"), trials),
+                    _ => continue,
+                };
+
+                println!("run {}: {} | {} | {} trials, {} threads, seed {:?} | {}",
+                         i + 1, param_name(config), mode, trials, config.threads.unwrap_or(0), config.seed, output_file.display());
+            }
+        }
+
+        return Ok(vec![]);
+    }
+
+    configs.extend(runs);
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{expand_plan, parse_plan};
+    use anonstake::circuit::anonstake_inputs::MerkleHeights;
+    use crate::cli::RunMode;
+
+    const PLAN: &str = r#"
+        [defaults]
+        threads = 4
+        trials = 3
+
+        [[run]]
+        variant = "tau1500"
+
+        [[run]]
+        variant = "tau20_block_proposer_no_poseidon"
+        mode = "batch"
+        num_batch = 8
+        merkle_height = 12
+        sn_height = 8
+        seed = 7
+        trials = 2
+        output = "out/batch.csv"
+    "#;
+
+    #[test]
+    fn expand() {
+        let plan = parse_plan(PLAN, false).ok().unwrap();
+        let configs = expand_plan(&plan, Path::new("/tmp")).ok().unwrap();
+        assert_eq!(configs.len(), 2);
+
+        assert!(configs[0].use_poseidon && !configs[0].is_bp);
+        assert_eq!(configs[0].merkle_heights, MerkleHeights::new(10, 10));
+        assert_eq!(configs[0].threads, Some(4));
+        assert_eq!(configs[0].seed, None);
+        match &configs[0].mode {
+            RunMode::Single(params_file, output_file, 3) => {
+                assert_eq!(params_file, Path::new("/tmp/prover_params/tau1500.params"));
+                assert_eq!(output_file, Path::new("/tmp/benchmarks/tau1500_4_threads_single_plan1.csv"));
+            }
+            _ => panic!("run 1 has to be a single run with 3 trials"),
+        }
+
+        assert!(!configs[1].use_poseidon && configs[1].is_bp);
+        assert_eq!(configs[1].merkle_heights, MerkleHeights::new(12, 8));
+        assert_eq!(configs[1].seed, Some(7));
+        match &configs[1].mode {
+            RunMode::Batch(params_file, output_file, 2, 8) => {
+                assert_eq!(params_file, Path::new("/tmp/prover_params/tau20_block_proposer_no_poseidon_cm12_sn8.params"));
+                assert_eq!(output_file, Path::new("/tmp/out/batch.csv"));
+            }
+            _ => panic!("run 2 has to be a batch run with 2 trials of 8 proofs"),
+        }
+    }
+
+    #[test]
+    fn json_plan() {
+        let json = r#"{"defaults": {"threads": 4, "trials": 3}, "run": [{"variant": "tau1500"}]}"#;
+        let plan = parse_plan(json, true).ok().unwrap();
+        let configs = expand_plan(&plan, Path::new("/tmp")).ok().unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].threads, Some(4));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let plan = r#"
+            [[run]]
+            variant = "tau1234"
+
+            [[run]]
+            variant = "tau2990"
+            mode = "batch"
+            num_batch = 1
+            threads = 0
+
+            [[run]]
+            variant = "tau5000"
+            merkle_height = 40
+            output = "a.csv"
+
+            [[run]]
+            variant = "tau5000_no_poseidon"
+            output = "a.csv"
+        "#;
+
+        let plan = parse_plan(plan, false).ok().unwrap();
+        let errors = expand_plan(&plan, Path::new("/tmp")).err().unwrap();
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].starts_with("run 1: unknown variant tau1234"));
+        assert!(errors[1].starts_with("run 2: threads"));
+        assert!(errors[2].starts_with("run 2: num_batch"));
+        assert!(errors[3].starts_with("run 3: cm tree height 40"));
+        assert!(errors[4].starts_with("run 4: output"));
+    }
+
+    #[test]
+    fn unknown_field() {
+        assert!(parse_plan("[[run]]\nvariant = \"tau1500\"\nthread = 4\n", false).is_err());
+        assert!(parse_plan("[[run]]\nvariant = \"tau1500\"\nthreads = \"4\"\n", false).is_err());
+    }
+}
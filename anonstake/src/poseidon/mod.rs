@@ -1,13 +1,18 @@
 use super::constants::poseidon_constants::PoseidonConstants;
-use ff::{Field, ScalarEngine};
+use ff::{Field, PrimeField, ScalarEngine};
 
+/*
+poseidon sponge with width 9: rate 8 (state[0..8]) and capacity 1 (state[8])
+the capacity starts out as a tag, the input is added to the rate 8 elements at a time with a permutation
+after every block, and the output is state[0]
 
-pub fn poseidon_hash<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, elems: &[E::Fr]) -> E::Fr {
-    let mut state: [E::Fr; 9] = [E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero()];
-    for i in 0..8 {
-        state[i] = elems[i].clone();
-    }
+poseidon_hash is the fixed length special case: exactly 8 elements, one block and tag 0
+variable length hashes use sponge_tag(domain, length) >= 2^64, so they never collide with poseidon_hash
+and the length in the tag makes padding the last block with zeros unambiguous
+the keyed poseidon prf uses the tags of PrfDomain as its sponge domains
+*/
 
+pub fn poseidon_permutation<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, mut state: [E::Fr; 9]) -> [E::Fr; 9] {
     for round in 0..poseidon.r_f + poseidon.r_p {
         if round < poseidon.r_f / 2 || round >= poseidon.r_p + poseidon.r_f / 2 {
             for i in 0..9 {
@@ -36,6 +41,122 @@ pub fn poseidon_hash<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, elems: &[
         }
     }
 
+    state
+}
+
+//capacity element for a variable length hash: (domain + 1) * 2^64 + length
+//domain is a u32 so domain + 1 can not overflow and the tag stays far below the modulus
+pub fn sponge_tag<F: PrimeField>(domain: u32, length: usize) -> F {
+    let mut tag = F::from_repr(F::Repr::from(u64::from(domain) + 1)).expect("u64 always fits in the field");
+    for _ in 0..64 {
+        tag.double();
+    }
+
+    tag.add_assign(&F::from_repr(F::Repr::from(length as u64)).unwrap());
+    tag
+}
+
+//number of permutations needed to absorb length elements, the empty input still gets one
+pub fn sponge_blocks(length: usize) -> usize {
+    if length == 0 { 1 } else { (length + 7) / 8 }
+}
+
+pub fn poseidon_sponge_with_tag<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, tag: E::Fr, elems: &[E::Fr]) -> E::Fr {
+    let mut state: [E::Fr; 9] = [E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), E::Fr::zero(), tag];
+
+    for block in 0..sponge_blocks(elems.len()) {
+        for (i, elem) in elems.iter().skip(8 * block).take(8).enumerate() {
+            state[i].add_assign(elem);
+        }
+
+        state = poseidon_permutation(poseidon, state);
+    }
+
+    state[0]
+}
+
+//hash of an arbitrary number of elements, domain separates different uses of the sponge
+pub fn poseidon_sponge<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, domain: u32, elems: &[E::Fr]) -> E::Fr {
+    poseidon_sponge_with_tag(poseidon, sponge_tag::<E::Fr>(domain, elems.len()), elems)
+}
+
+pub fn poseidon_hash<E: ScalarEngine>(poseidon: &PoseidonConstants<E>, elems: &[E::Fr]) -> E::Fr {
+    assert_eq!(elems.len(), 8, "poseidon_hash takes exactly 8 elements, use poseidon_sponge for other lengths");
+
+    poseidon_sponge_with_tag(poseidon, E::Fr::zero(), elems)
+}
+
+#[cfg(test)]
+mod tests {
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use crate::circuit::public_inputs::u64_to_fr;
+
+    use super::*;
+
+    fn elems(n: u64) -> Vec<Fr> {
+        (1..=n).map(|i| u64_to_fr(i)).collect()
+    }
+
+    fn fr(s: &str) -> Fr {
+        Fr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn fixed_length_vectors() {
+        let poseidon = PoseidonConstants::<Bls12>::get();
+
+        assert_eq!(poseidon_hash(&poseidon, &[Fr::zero(); 8]),
+                   fr("19054526007698935901655128609375353342880668760868666599672109870191135022390"));
+        assert_eq!(poseidon_hash(&poseidon, &elems(8)),
+                   fr("46530028252012842315194934807612791405017117102688914852138456661446965001302"));
+    }
+
+    #[test]
+    fn sponge_vectors() {
+        let poseidon = PoseidonConstants::<Bls12>::get();
+
+        assert_eq!(poseidon_sponge(&poseidon, 1, &[]),
+                   fr("6384504773146468850122542353700037908494595374832931837387885326670805015244"));
+        assert_eq!(poseidon_sponge(&poseidon, 1, &elems(3)),
+                   fr("33051094754675896090739076426307083829091854217677326509710659114936636595739"));
+        assert_eq!(poseidon_sponge(&poseidon, 1, &elems(8)),
+                   fr("10666138706742907272802161328965326033411153988588125750295914562084538905033"));
+        assert_eq!(poseidon_sponge(&poseidon, 2, &elems(8)),
+                   fr("5431512735315875613746946843301641530998423381962548633507611311761720002501"));
+        assert_eq!(poseidon_sponge(&poseidon, 1, &elems(11)),
+                   fr("33358634577633502925578172577410103841365739695475407725259981551264175313494"));
+    }
 
-    return state[0].clone();
-}
\ No newline at end of file
+    #[test]
+    fn sponge_separates_lengths() {
+        let poseidon = PoseidonConstants::<Bls12>::get();
+
+        //trailing zeros are not the same as a shorter input
+        let mut padded = elems(3);
+        padded.push(Fr::zero());
+
+        assert_ne!(poseidon_sponge(&poseidon, 1, &elems(3)), poseidon_sponge(&poseidon, 1, &padded));
+        assert_ne!(poseidon_sponge(&poseidon, 1, &elems(8)), poseidon_hash(&poseidon, &elems(8)));
+    }
+
+    //the largest domain does not overflow into the tag of another domain
+    #[test]
+    fn sponge_tag_largest_domain() {
+        let mut expected = u64_to_fr::<Fr>(1 << 32);
+        for _ in 0..64 {
+            expected.double();
+        }
+        expected.add_assign(&u64_to_fr(3));
+
+        assert_eq!(sponge_tag::<Fr>(u32::max_value(), 3), expected);
+        assert_ne!(sponge_tag::<Fr>(u32::max_value(), 3), sponge_tag::<Fr>(0, 3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn fixed_length_rejects_short_input() {
+        let poseidon = PoseidonConstants::<Bls12>::get();
+        poseidon_hash(&poseidon, &elems(3));
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use bellman::groth16::{PreparedVerifyingKey, Proof, verify_proof};
+use bellman::SynthesisError;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::public_inputs::PublicInputs;
+
+/*
+the circuit exposes the roots of the coin commitment tree ("anchor") and of the serial number tree
+("sn anchor") it computed from the merkle paths, so the verifier has to check that they are roots
+it actually knows about, otherwise a prover can make up its own tree
+
+check accepts any known root of the last max_age rounds, with epochs (epoch.rs) the anchors have to be
+exactly the roots of the snapshot round instead, Epochs keeps its roots in a registry and does that check itself
+*/
+#[derive(Clone)]
+pub struct AnchorConfig {
+    //number of roots of each tree that are remembered
+    pub window: usize,
+    //anchors from more than max_age rounds ago are rejected
+    pub max_age: u64,
+}
+
+impl Default for AnchorConfig {
+    fn default() -> Self {
+        AnchorConfig {
+            window: 16,
+            max_age: 16,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AnchorError {
+    MalformedInputs,
+    UnknownCmAnchor,
+    UnknownSnAnchor,
+    StaleCmAnchor { round: u64, current_round: u64 },
+    StaleSnAnchor { round: u64, current_round: u64 },
+    FutureCmAnchor { round: u64, current_round: u64 },
+    FutureSnAnchor { round: u64, current_round: u64 },
+    InvalidProof,
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for AnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnchorError::MalformedInputs => write!(f, "public inputs do not match the circuit"),
+            AnchorError::UnknownCmAnchor => write!(f, "coin commitment anchor is not a known root"),
+            AnchorError::UnknownSnAnchor => write!(f, "serial number anchor is not a known root"),
+            AnchorError::StaleCmAnchor { round, current_round } =>
+                write!(f, "coin commitment anchor from round {} is too old for round {}", round, current_round),
+            AnchorError::StaleSnAnchor { round, current_round } =>
+                write!(f, "serial number anchor from round {} is too old for round {}", round, current_round),
+            AnchorError::FutureCmAnchor { round, current_round } =>
+                write!(f, "coin commitment anchor from round {} is newer than round {}", round, current_round),
+            AnchorError::FutureSnAnchor { round, current_round } =>
+                write!(f, "serial number anchor from round {} is newer than round {}", round, current_round),
+            AnchorError::InvalidProof => write!(f, "proof did not verify"),
+            AnchorError::Synthesis(e) => write!(f, "error verifying proof: {}", e),
+        }
+    }
+}
+
+impl From<SynthesisError> for AnchorError {
+    fn from(e: SynthesisError) -> Self {
+        AnchorError::Synthesis(e)
+    }
+}
+
+pub struct AnchorHistory<E: JubjubEngine> {
+    window: usize,
+    roots: VecDeque<(u64, E::Fr)>,
+}
+
+impl<E: JubjubEngine> AnchorHistory<E> {
+    pub fn new(window: usize) -> AnchorHistory<E> {
+        AnchorHistory {
+            window,
+            roots: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn push(&mut self, round: u64, root: E::Fr) {
+        self.roots.push_back((round, root));
+
+        while self.roots.len() > self.window {
+            self.roots.pop_front();
+        }
+    }
+
+    /*
+    most recent round at or before current_round in which root was the root of the tree
+    a root can come back in a later round (e.g. nothing was added), that later round does not make it a future root
+    if root is only known from rounds after current_round the first of them is returned
+    */
+    pub fn round_of(&self, root: &E::Fr, current_round: u64) -> Option<u64> {
+        let mut rounds = self.roots.iter().filter(|(_, r)| r == root).map(|(round, _)| *round);
+
+        rounds.clone().filter(|round| *round <= current_round).last().or_else(|| rounds.next())
+    }
+
+    //root of the tree as of the given round, i.e. the last root pushed at or before it
+    pub fn root_at(&self, round: u64) -> Option<E::Fr> {
+        self.roots.iter().rev().find(|(r, _)| *r <= round).map(|(_, root)| *root)
+    }
+
+    pub fn latest(&self) -> Option<(u64, E::Fr)> {
+        self.roots.back().cloned()
+    }
+}
+
+pub struct AnchorRegistry<E: JubjubEngine> {
+    pub config: AnchorConfig,
+    pub cm: AnchorHistory<E>,
+    pub sn: AnchorHistory<E>,
+}
+
+impl<E: JubjubEngine> AnchorRegistry<E> {
+    pub fn new(config: AnchorConfig) -> AnchorRegistry<E> {
+        AnchorRegistry {
+            cm: AnchorHistory::new(config.window),
+            sn: AnchorHistory::new(config.window),
+            config,
+        }
+    }
+
+    pub fn push_cm_root(&mut self, round: u64, root: E::Fr) {
+        self.cm.push(round, root);
+    }
+
+    pub fn push_sn_root(&mut self, round: u64, root: E::Fr) {
+        self.sn.push(round, root);
+    }
+
+    pub fn check(&self, inputs: &PublicInputs<E>, current_round: u64) -> Result<(), AnchorError> {
+        //a root from a later round than the one being voted on is not valid either
+        let round = self.cm.round_of(&inputs.cm_anchor, current_round).ok_or(AnchorError::UnknownCmAnchor)?;
+        if round > current_round {
+            return Err(AnchorError::FutureCmAnchor { round, current_round });
+        }
+        if current_round - round > self.config.max_age {
+            return Err(AnchorError::StaleCmAnchor { round, current_round });
+        }
+
+        let round = self.sn.round_of(&inputs.sn_anchor, current_round).ok_or(AnchorError::UnknownSnAnchor)?;
+        if round > current_round {
+            return Err(AnchorError::FutureSnAnchor { round, current_round });
+        }
+        if current_round - round > self.config.max_age {
+            return Err(AnchorError::StaleSnAnchor { round, current_round });
+        }
+
+        Ok(())
+    }
+
+    //input does not contain the leading one, same as verify_proof
+    pub fn verify(&self, pvk: &PreparedVerifyingKey<E>, proof: &Proof<E>, input: &[E::Fr], is_bp: bool, current_round: u64) -> Result<PublicInputs<E>, AnchorError> {
+        let inputs = PublicInputs::from_inputs(input, is_bp).ok_or(AnchorError::MalformedInputs)?;
+        self.check(&inputs, current_round)?;
+
+        if !verify_proof(pvk, proof, input)? {
+            return Err(AnchorError::InvalidProof);
+        }
+
+        Ok(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use crate::circuit::public_inputs::u64_to_fr;
+
+    use super::*;
+
+    fn inputs(cm_anchor: u64, sn_anchor: u64) -> PublicInputs<Bls12> {
+        PublicInputs {
+            role: 1,
+            cm_anchor: u64_to_fr(cm_anchor),
+            seed: Fr::zero(),
+            sn_anchor: u64_to_fr(sn_anchor),
+            tsn: Fr::zero(),
+            h_sig: Fr::zero(),
+            h: Fr::zero(),
+            block_proposer: None,
+        }
+    }
+
+    //root r is pushed in round r for both trees
+    fn registry() -> AnchorRegistry<Bls12> {
+        let mut registry = AnchorRegistry::new(AnchorConfig {
+            window: 16,
+            max_age: 4,
+        });
+
+        for round in 10..20 {
+            registry.push_cm_root(round, u64_to_fr(round));
+            registry.push_sn_root(round, u64_to_fr(round));
+        }
+
+        registry
+    }
+
+    #[test]
+    fn fresh_anchor() {
+        let registry = registry();
+
+        assert!(registry.check(&inputs(15, 15), 15).is_ok());
+        assert!(registry.check(&inputs(15, 17), 19).is_ok());
+        assert!(registry.check(&inputs(19, 19), 23).is_ok());
+    }
+
+    #[test]
+    fn stale_anchor() {
+        let registry = registry();
+
+        match registry.check(&inputs(14, 19), 19) {
+            Err(AnchorError::StaleCmAnchor { round: 14, current_round: 19 }) => (),
+            e => panic!("expected stale cm anchor, got {:?}", e),
+        }
+        match registry.check(&inputs(19, 14), 19) {
+            Err(AnchorError::StaleSnAnchor { round: 14, current_round: 19 }) => (),
+            e => panic!("expected stale sn anchor, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn future_anchor() {
+        let registry = registry();
+
+        match registry.check(&inputs(16, 15), 15) {
+            Err(AnchorError::FutureCmAnchor { round: 16, current_round: 15 }) => (),
+            e => panic!("expected future cm anchor, got {:?}", e),
+        }
+        match registry.check(&inputs(15, 19), 15) {
+            Err(AnchorError::FutureSnAnchor { round: 19, current_round: 15 }) => (),
+            e => panic!("expected future sn anchor, got {:?}", e),
+        }
+    }
+
+    //the root of round 12 is the root again in round 18, it is not a future root for round 15
+    #[test]
+    fn repeated_root() {
+        let mut registry = registry();
+        registry.cm.roots.iter_mut().filter(|(round, _)| *round == 18).for_each(|(_, root)| *root = u64_to_fr(12));
+        registry.sn.roots.iter_mut().filter(|(round, _)| *round == 18).for_each(|(_, root)| *root = u64_to_fr(12));
+
+        assert_eq!(registry.cm.round_of(&u64_to_fr(12), 15), Some(12));
+        assert_eq!(registry.cm.round_of(&u64_to_fr(12), 19), Some(18));
+        assert_eq!(registry.cm.round_of(&u64_to_fr(18), 15), None);
+
+        assert!(registry.check(&inputs(12, 12), 15).is_ok());
+        assert!(registry.check(&inputs(12, 12), 20).is_ok());
+        match registry.check(&inputs(12, 12), 17) {
+            Err(AnchorError::StaleCmAnchor { round: 12, current_round: 17 }) => (),
+            e => panic!("expected stale cm anchor, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn unknown_anchor() {
+        let registry = registry();
+
+        match registry.check(&inputs(9, 15), 15) {
+            Err(AnchorError::UnknownCmAnchor) => (),
+            e => panic!("expected unknown cm anchor, got {:?}", e),
+        }
+        match registry.check(&inputs(15, 20), 15) {
+            Err(AnchorError::UnknownSnAnchor) => (),
+            e => panic!("expected unknown sn anchor, got {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use ff::PrimeField;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::hash::{CircuitHash, NativeHashInput};
+use crate::circuit::public_inputs::PublicInputs;
+use crate::constants::Constants;
+use crate::native;
+
+/*
+block proposal for role 0 style circuits (is_bp)
+every selected sub-user j_i of a proposer gets priority = PRF_{a_sk}(H(role, seed, j_i))
+the proposal with the lowest priority wins and its seed_comp is used to compute the seed of the next round
+*/
+
+//priorities of all the sub-users selected for this role, j_i goes from 1 to num_selections
+pub fn selected_priorities<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, a_sk: &E::Fr, role: u64, seed: &E::Fr, value: u64) -> Vec<(u64, E::Fr)> {
+    let num_selections = native::num_selections(constants, a_sk, role, seed, value);
+
+    (1..=num_selections)
+        .map(|j_i| (j_i, native::priority::<E, H>(constants, a_sk, role, seed, j_i)))
+        .collect()
+}
+
+//the j_i that a proposer should make a proof for, if it was selected at all
+pub fn best_priority<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, a_sk: &E::Fr, role: u64, seed: &E::Fr, value: u64) -> Option<(u64, E::Fr)> {
+    selected_priorities::<E, H>(constants, a_sk, role, seed, value)
+        .into_iter()
+        .min_by(|a, b| a.1.into_repr().cmp(&b.1.into_repr()))
+}
+
+/*
+lowest priority wins
+priorities are PRF outputs so ties should never happen, but if they do the lowest tsn wins
+so that every node picks the same proposal regardless of the order it received them in
+proposals without block proposer inputs are ignored
+*/
+pub fn winning_proposal<'b, E: JubjubEngine>(proposals: &'b [PublicInputs<E>]) -> Option<&'b PublicInputs<E>> {
+    proposals.iter()
+        .filter(|p| p.block_proposer.is_some())
+        .min_by(|a, b| {
+            let pa = a.block_proposer.as_ref().unwrap().priority.into_repr();
+            let pb = b.block_proposer.as_ref().unwrap().priority.into_repr();
+
+            pa.cmp(&pb).then_with(|| a.tsn.into_repr().cmp(&b.tsn.into_repr()))
+        })
+}
+
+fn seed_hash<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, value: &E::Fr, r: u64, fallback: bool) -> E::Fr {
+    H::native(constants, &[NativeHashInput::elem(*value), NativeHashInput::u64(r, 64), NativeHashInput::u64(fallback as u64, 1)])
+}
+
+/*
+seed for round r + 1
+uses the seed_comp of the winning proposal for round r
+if nobody proposed a block (or no proposal was received) the seed is derived from the previous seed,
+like in algorand, with a separate domain so the fallback cannot collide with a real seed_comp
+*/
+pub fn next_seed<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, seed: &E::Fr, r: u64, winner: Option<&PublicInputs<E>>) -> E::Fr {
+    match winner.and_then(|w| w.block_proposer.as_ref()) {
+        Some(bp) => seed_hash::<E, H>(constants, &bp.seed_comp, r, false),
+        None => seed_hash::<E, H>(constants, seed, r, true),
+    }
+}
+
+pub fn next_seed_from_proposals<E: JubjubEngine, H: CircuitHash<E>>(constants: &Constants<E>, seed: &E::Fr, r: u64, proposals: &[PublicInputs<E>]) -> E::Fr {
+    next_seed::<E, H>(constants, seed, r, winning_proposal(proposals))
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::hash::{Pedersen, Poseidon};
+    use crate::circuit::public_inputs::{BlockProposerPublicInputs, PublicInputs, u64_to_fr};
+    use crate::constants::binomial_constants::TauValue;
+    use crate::constants::Constants;
+
+    use super::*;
+
+    fn proposal(priority: u64, tsn: u64, seed_comp: u64) -> PublicInputs<Bls12> {
+        PublicInputs {
+            role: 0,
+            cm_anchor: Fr::zero(),
+            seed: Fr::zero(),
+            sn_anchor: Fr::zero(),
+            tsn: u64_to_fr(tsn),
+            h_sig: Fr::zero(),
+            h: Fr::zero(),
+            block_proposer: Some(BlockProposerPublicInputs {
+                priority: u64_to_fr(priority),
+                r: 7,
+                seed_comp: u64_to_fr(seed_comp),
+            }),
+        }
+    }
+
+    #[test]
+    fn lowest_priority_wins() {
+        let proposals = vec![proposal(5, 1, 10), proposal(3, 2, 20), proposal(9, 3, 30)];
+        let winner = winning_proposal(&proposals).unwrap();
+
+        assert_eq!(winner.tsn, u64_to_fr(2));
+    }
+
+    #[test]
+    fn ties_broken_by_tsn() {
+        let proposals = vec![proposal(3, 8, 10), proposal(3, 4, 20), proposal(4, 1, 30)];
+        let winner = winning_proposal(&proposals).unwrap();
+        assert_eq!(winner.tsn, u64_to_fr(4));
+
+        let mut reversed = proposals.clone();
+        reversed.reverse();
+        let winner = winning_proposal(&reversed).unwrap();
+        assert_eq!(winner.tsn, u64_to_fr(4));
+    }
+
+    #[test]
+    fn non_proposals_ignored() {
+        let mut not_bp = proposal(0, 0, 0);
+        not_bp.block_proposer = None;
+
+        assert!(winning_proposal::<Bls12>(&[not_bp.clone()]).is_none());
+
+        let proposals = vec![not_bp, proposal(6, 1, 10)];
+        assert_eq!(winning_proposal(&proposals).unwrap().tsn, u64_to_fr(1));
+    }
+
+    fn fallback_seed<H: CircuitHash<Bls12>>(constants: &Constants<Bls12>) {
+        let seed = u64_to_fr(1234);
+        let proposals = vec![proposal(3, 1, 1234)];
+
+        let fallback = next_seed_from_proposals::<Bls12, H>(constants, &seed, 7, &[]);
+        assert_eq!(fallback, next_seed::<Bls12, H>(constants, &seed, 7, None));
+
+        //deterministic, depends on the round and is separated from a proposal with seed_comp == seed
+        assert_eq!(fallback, next_seed_from_proposals::<Bls12, H>(constants, &seed, 7, &[]));
+        assert_ne!(fallback, next_seed_from_proposals::<Bls12, H>(constants, &seed, 8, &[]));
+        assert_ne!(fallback, next_seed_from_proposals::<Bls12, H>(constants, &seed, 7, &proposals));
+    }
+
+    #[test]
+    fn missing_proposers_fallback_seed() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+
+        fallback_seed::<Poseidon>(&constants);
+        fallback_seed::<Pedersen>(&constants);
+    }
+
+    #[test]
+    fn winner_seed_comp_determines_seed() {
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+
+        let a = vec![proposal(3, 1, 100), proposal(5, 2, 200)];
+        let b = vec![proposal(3, 1, 100), proposal(1, 2, 200)];
+
+        let seed_a = next_seed_from_proposals::<Bls12, Poseidon>(&constants, &Fr::zero(), 7, &a);
+        let seed_b = next_seed_from_proposals::<Bls12, Poseidon>(&constants, &Fr::one(), 7, &b);
+        let expected_b = next_seed::<Bls12, Poseidon>(&constants, &Fr::zero(), 7, Some(&b[1]));
+
+        assert_ne!(seed_a, seed_b);
+        assert_eq!(seed_b, expected_b);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::AnonStake;
+use crate::circuit::hash::CircuitHash;
+use crate::circuit::public_inputs::PublicInputs;
+use crate::constants::Constants;
+use crate::protocol::anchors::{AnchorConfig, AnchorRegistry};
+use crate::protocol::block_proposal;
+use crate::protocol::role::{Role, RoleError};
+
+/*
+which role, anchors and seed a proof for a given (round, step) has to use
+roles are encoded as in role.rs
+
+sortition for round r uses the commitment and serial number trees as they were at round r - lookback,
+so a coin has to be in the tree for lookback rounds before it can be selected
+and the proofs for a round can be made before the latest blocks are known
+
+the seed for round r + 1 comes from the winning block proposal of round r, see block_proposal::next_seed
+
+the roots are kept in an AnchorRegistry, check does not use the age check of the registry
+because the anchors have to be exactly the roots of the snapshot round
+*/
+#[derive(Clone)]
+pub struct EpochConfig {
+    //anchors for round r are the roots as of round r - lookback
+    pub lookback: u64,
+    //number of roots of each tree that are remembered, has to be more than lookback
+    pub window: usize,
+}
+
+impl Default for EpochConfig {
+    fn default() -> Self {
+        EpochConfig {
+            lookback: 4,
+            window: 16,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EpochError {
+    UnknownRound(u64),
+    WrongCmAnchor,
+    WrongSnAnchor,
+    WrongSeed,
+    WrongProposerRound,
+    ProposerMismatch,
+    InvalidRole(RoleError),
+    WindowTooSmall { window: usize, lookback: u64 },
+}
+
+impl fmt::Display for EpochError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpochError::UnknownRound(round) => write!(f, "no anchors or seed known for round {}", round),
+            EpochError::WrongCmAnchor => write!(f, "coin commitment anchor is not the snapshot for this round"),
+            EpochError::WrongSnAnchor => write!(f, "serial number anchor is not the snapshot for this round"),
+            EpochError::WrongSeed => write!(f, "seed is not the seed of this round"),
+            EpochError::WrongProposerRound => write!(f, "r of the block proposal is not the round of the role"),
+            EpochError::ProposerMismatch => write!(f, "proposer bit of the role does not match the circuit"),
+            EpochError::InvalidRole(e) => write!(f, "{}", e),
+            EpochError::WindowTooSmall { window, lookback } =>
+                write!(f, "window of {} roots does not reach back the lookback of {} rounds", window, lookback),
+        }
+    }
+}
+
+impl From<RoleError> for EpochError {
+    fn from(e: RoleError) -> Self {
+        EpochError::InvalidRole(e)
+    }
+}
+
+impl EpochConfig {
+    //with a root pushed every round, the root of round r - lookback has to be among the last window roots
+    pub fn validate(&self) -> Result<(), EpochError> {
+        if (self.window as u64) <= self.lookback {
+            return Err(EpochError::WindowTooSmall { window: self.window, lookback: self.lookback });
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Epochs<E: JubjubEngine> {
+    pub config: EpochConfig,
+    roots: AnchorRegistry<E>,
+    seeds: BTreeMap<u64, E::Fr>,
+}
+
+impl<E: JubjubEngine> Epochs<E> {
+    pub fn new(config: EpochConfig, genesis_seed: E::Fr) -> Result<Epochs<E>, EpochError> {
+        config.validate()?;
+
+        let mut seeds = BTreeMap::new();
+        seeds.insert(0, genesis_seed);
+
+        Ok(Epochs {
+            roots: AnchorRegistry::new(AnchorConfig {
+                window: config.window,
+                max_age: config.lookback,
+            }),
+            seeds,
+            config,
+        })
+    }
+
+    //roots of the trees after the block of round was added
+    pub fn push_roots(&mut self, round: u64, cm_root: E::Fr, sn_root: E::Fr) {
+        self.roots.push_cm_root(round, cm_root);
+        self.roots.push_sn_root(round, sn_root);
+    }
+
+    pub fn snapshot_round(&self, round: u64) -> u64 {
+        round.saturating_sub(self.config.lookback)
+    }
+
+    //(cm anchor, sn anchor) that proofs for round have to use
+    pub fn anchors(&self, round: u64) -> Option<(E::Fr, E::Fr)> {
+        let snapshot = self.snapshot_round(round);
+        Some((self.roots.cm.root_at(snapshot)?, self.roots.sn.root_at(snapshot)?))
+    }
+
+    pub fn seed(&self, round: u64) -> Option<E::Fr> {
+        self.seeds.get(&round).cloned()
+    }
+
+    //sets the seed of round + 1 from the block proposals seen in round, H has to be the hash of the proposal circuit
+    pub fn finish_round<H: CircuitHash<E>>(&mut self, constants: &Constants<E>, round: u64, proposals: &[PublicInputs<E>]) -> Option<E::Fr> {
+        let seed = self.seed(round)?;
+        let next = block_proposal::next_seed_from_proposals::<E, H>(constants, &seed, round, proposals);
+        self.seeds.insert(round + 1, next);
+
+        Some(next)
+    }
+
+    /*
+    prover side: fills in role, seed and r of a witness for (round, step)
+    the caller is responsible for merkle paths that end in the anchors returned by anchors(round)
+    */
+    pub fn witness<'a, H: CircuitHash<E>>(&self, mut anonstake: AnonStake<'a, E, H>, round: u64, step: u64) -> Result<AnonStake<'a, E, H>, EpochError> {
+        let role = Role::new(round, step, anonstake.is_bp)?;
+
+        //fails if the coin needs a new forward secure tree for this round
+        if let Some(fs_tree_start) = anonstake.aux_input.fs_tree_start {
+            role.fs_time(fs_tree_start)?;
+        }
+
+        anonstake.pub_input.role = Some(role.to_u64());
+        anonstake.pub_input.seed = Some(self.seed(round).ok_or(EpochError::UnknownRound(round))?);
+
+        if anonstake.is_bp {
+            anonstake.bp_pub_input.r = Some(round);
+        }
+
+        Ok(anonstake)
+    }
+
+    //verifier side: the anchors and seed of a proof have to be the ones of the round its role belongs to
+    pub fn check(&self, inputs: &PublicInputs<E>) -> Result<(), EpochError> {
+        let role = Role::from_u64(inputs.role);
+        let round = role.round;
+
+        if role.proposer != inputs.is_bp() {
+            return Err(EpochError::ProposerMismatch);
+        }
+
+        let (cm_anchor, sn_anchor) = self.anchors(round).ok_or(EpochError::UnknownRound(round))?;
+        if inputs.cm_anchor != cm_anchor {
+            return Err(EpochError::WrongCmAnchor);
+        }
+        if inputs.sn_anchor != sn_anchor {
+            return Err(EpochError::WrongSnAnchor);
+        }
+
+        let seed = self.seed(round).ok_or(EpochError::UnknownRound(round))?;
+        if inputs.seed != seed {
+            return Err(EpochError::WrongSeed);
+        }
+
+        if let Some(bp) = &inputs.block_proposer {
+            if bp.r != round {
+                return Err(EpochError::WrongProposerRound);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+
+    use crate::circuit::public_inputs::u64_to_fr;
+
+    use super::*;
+
+    fn voter(round: u64, step: u64) -> u64 {
+        Role::voter(round, step).unwrap().to_u64()
+    }
+
+    fn inputs(role: u64, cm_anchor: u64, sn_anchor: u64, seed: Fr) -> PublicInputs<Bls12> {
+        PublicInputs {
+            role,
+            cm_anchor: u64_to_fr(cm_anchor),
+            seed,
+            sn_anchor: u64_to_fr(sn_anchor),
+            tsn: Fr::zero(),
+            h_sig: Fr::zero(),
+            h: Fr::zero(),
+            block_proposer: None,
+        }
+    }
+
+    #[test]
+    fn anchors_use_lookback() {
+        let config = EpochConfig { lookback: 2, window: 16 };
+        let mut epochs = Epochs::<Bls12>::new(config, Fr::one()).unwrap();
+
+        for round in 0..6 {
+            epochs.push_roots(round, u64_to_fr(100 + round), u64_to_fr(200 + round));
+        }
+
+        assert_eq!(epochs.anchors(5), Some((u64_to_fr(103), u64_to_fr(203))));
+        assert_eq!(epochs.anchors(1), Some((u64_to_fr(100), u64_to_fr(200))));
+
+        assert_eq!(epochs.check(&inputs(voter(5, 1), 103, 203, Fr::one())), Err(EpochError::UnknownRound(5)));
+
+        epochs.seeds.insert(5, Fr::one());
+        assert_eq!(epochs.check(&inputs(voter(5, 1), 103, 203, Fr::one())), Ok(()));
+        assert_eq!(epochs.check(&inputs(voter(5, 1), 104, 203, Fr::one())), Err(EpochError::WrongCmAnchor));
+        assert_eq!(epochs.check(&inputs(voter(5, 1), 103, 204, Fr::one())), Err(EpochError::WrongSnAnchor));
+        assert_eq!(epochs.check(&inputs(voter(5, 1), 103, 203, Fr::zero())), Err(EpochError::WrongSeed));
+        assert_eq!(epochs.check(&inputs(Role::proposer(5).unwrap().to_u64(), 103, 203, Fr::one())), Err(EpochError::ProposerMismatch));
+    }
+
+    #[test]
+    fn window_covers_lookback() {
+        assert!(Epochs::<Bls12>::new(EpochConfig::default(), Fr::one()).is_ok());
+        assert!(Epochs::<Bls12>::new(EpochConfig { lookback: 4, window: 5 }, Fr::one()).is_ok());
+        assert_eq!(EpochConfig { lookback: 4, window: 4 }.validate(), Err(EpochError::WindowTooSmall { window: 4, lookback: 4 }));
+        assert!(Epochs::<Bls12>::new(EpochConfig { lookback: 4, window: 2 }, Fr::one()).is_err());
+    }
+}
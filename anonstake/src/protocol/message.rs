@@ -0,0 +1,275 @@
+use std::io::{self, Read, Write};
+
+use bellman::groth16::PreparedVerifyingKey;
+use bellman::SynthesisError;
+use blake2b_simd::Params;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use rand_core::{CryptoRng, RngCore};
+use zcash_primitives::jubjub::{FixedGenerators, JubjubEngine};
+use zcash_primitives::redjubjub::{PrivateKey, PublicKey, Signature};
+
+use crate::protocol::role::Role;
+use crate::protocol::votes::Vote;
+
+/*
+binds a vote to a sortition proof, in the style of h_sig in zcash and the pour signature in zerocash
+the voter picks a one-time redjubjub key, uses h_sig = H(vk) as the h_sig public input of the proof,
+and signs the vote content together with the proof under that key
+h = PRF_{a_sk}(H(h_sig, role)) ties h_sig to the owner of the coin, so only the prover knows the signing key
+and a proof cannot be replayed with a different block hash
+*/
+const H_SIG_PERSONALIZATION: &[u8; 16] = b"AnonStake_h_sig_";
+
+#[derive(Clone, PartialEq)]
+pub struct VotePayload {
+    pub block_hash: [u8; 32],
+    pub step: u64,
+}
+
+impl VotePayload {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.block_hash)?;
+        writer.write_u64::<LittleEndian>(self.step)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<VotePayload> {
+        let mut block_hash = [0u8; 32];
+        reader.read_exact(&mut block_hash)?;
+        let step = reader.read_u64::<LittleEndian>()?;
+
+        Ok(VotePayload { block_hash, step })
+    }
+}
+
+pub fn h_sig<E: JubjubEngine>(vk: &PublicKey<E>) -> E::Fr {
+    let mut vk_bytes = vec![];
+    vk.write(&mut vk_bytes).expect("writing to a vec does not fail");
+
+    let hash = Params::new()
+        .hash_length(32)
+        .personal(H_SIG_PERSONALIZATION)
+        .hash(&vk_bytes);
+
+    //drop the top two bits so the value is always below the modulus
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(hash.as_bytes());
+    bytes[31] &= 0x3f;
+
+    let mut repr = E::Fr::zero().into_repr();
+    repr.read_le(&bytes[..]).expect("reading 32 bytes does not fail");
+    E::Fr::from_repr(repr).expect("value is less than 2^254")
+}
+
+fn signed_message<E: JubjubEngine>(payload: &VotePayload, vote: &Vote<E>) -> io::Result<Vec<u8>> {
+    let mut message = vec![];
+    payload.write(&mut message)?;
+    vote.write(&mut message)?;
+
+    Ok(message)
+}
+
+pub struct VoteSigner<E: JubjubEngine> {
+    sk: PrivateKey<E>,
+    pub vk: PublicKey<E>,
+}
+
+impl<E: JubjubEngine> VoteSigner<E> {
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, params: &E::Params) -> VoteSigner<E> {
+        let sk = PrivateKey(E::Fs::random(rng));
+        let vk = PublicKey::from_private(&sk, FixedGenerators::SpendingKeyGenerator, params);
+
+        VoteSigner { sk, vk }
+    }
+
+    //value to use for pub_input.h_sig when creating the proof
+    pub fn h_sig(&self) -> E::Fr {
+        h_sig(&self.vk)
+    }
+
+    pub fn sign<R: RngCore + CryptoRng>(self, rng: &mut R, params: &E::Params, payload: VotePayload, vote: Vote<E>) -> io::Result<SignedVote<E>> {
+        let message = signed_message(&payload, &vote)?;
+        let signature = self.sk.sign(&message, rng, FixedGenerators::SpendingKeyGenerator, params);
+
+        Ok(SignedVote {
+            payload,
+            vk: self.vk,
+            signature,
+            vote,
+        })
+    }
+}
+
+pub struct SignedVote<E: JubjubEngine> {
+    pub payload: VotePayload,
+    pub vk: PublicKey<E>,
+    pub signature: Signature,
+    pub vote: Vote<E>,
+}
+
+impl<E: JubjubEngine> SignedVote<E> {
+    /*
+    the step of the payload must be the step of the role the proof is for, h_sig must come from vk,
+    the signature must cover the payload and the proof, and the proof must verify
+    */
+    pub fn verify(&self, params: &E::Params, pvk: &PreparedVerifyingKey<E>) -> Result<bool, SynthesisError> {
+        if self.payload.step != Role::from_u64(self.vote.inputs.role).step {
+            return Ok(false);
+        }
+
+        if self.vote.inputs.h_sig != h_sig(&self.vk) {
+            return Ok(false);
+        }
+
+        let message = signed_message(&self.payload, &self.vote)?;
+        if !self.vk.verify(&message, &self.signature, FixedGenerators::SpendingKeyGenerator, params) {
+            return Ok(false);
+        }
+
+        self.vote.verify(pvk)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.payload.write(&mut writer)?;
+        self.vk.write(&mut writer)?;
+        self.signature.write(&mut writer)?;
+        self.vote.write(&mut writer)
+    }
+
+    pub fn read<R: Read>(mut reader: R, params: &E::Params) -> io::Result<SignedVote<E>> {
+        let payload = VotePayload::read(&mut reader)?;
+        let vk = PublicKey::read(&mut reader, params)?;
+        let signature = Signature::read(&mut reader)?;
+        let vote = Vote::read(&mut reader)?;
+
+        Ok(SignedVote { payload, vk, signature, vote })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::{Circuit, ConstraintSystem, SynthesisError};
+    use bellman::groth16::{create_random_proof, generate_random_parameters, prepare_verifying_key, Parameters};
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::thread_rng;
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::public_inputs::{NUM_INPUTS, u64_to_fr};
+
+    use super::*;
+
+    //stands in for the sortition circuit, it only has the same public inputs
+    struct Inputs(Vec<Option<Fr>>);
+
+    impl Circuit<Bls12> for Inputs {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            for (i, value) in self.0.into_iter().enumerate() {
+                let input = cs.alloc_input(|| format!("input {}", i), || value.ok_or(SynthesisError::AssignmentMissing))?;
+                cs.enforce(|| format!("input {} times one", i), |lc| lc + input, |lc| lc + CS::one(), |lc| lc + input);
+            }
+
+            Ok(())
+        }
+    }
+
+    fn payload() -> VotePayload {
+        VotePayload {
+            block_hash: [7; 32],
+            step: 3,
+        }
+    }
+
+    //vote for a role in the step of payload()
+    fn make_vote(params: &Parameters<Bls12>, h_sig: Fr) -> Vote<Bls12> {
+        let rng = &mut thread_rng();
+        let mut input: Vec<Fr> = (0..NUM_INPUTS).map(|_| Fr::random(rng)).collect();
+        input[0] = u64_to_fr(Role::new(5, payload().step, false).unwrap().to_u64());
+        input[5] = h_sig;
+
+        let proof = create_random_proof(Inputs(input.iter().map(|x| Some(*x)).collect()), params, rng).unwrap();
+        Vote::new(proof, &input, false).unwrap()
+    }
+
+    fn setup() -> (JubjubBls12, Parameters<Bls12>) {
+        let params = generate_random_parameters::<Bls12, _, _>(Inputs(vec![None; NUM_INPUTS]), &mut thread_rng()).unwrap();
+        (JubjubBls12::new(), params)
+    }
+
+    #[test]
+    fn sign_verify() {
+        let rng = &mut thread_rng();
+        let (jubjub, params) = setup();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        let signer = VoteSigner::<Bls12>::new(rng, &jubjub);
+        let vote = make_vote(&params, signer.h_sig());
+        let signed = signer.sign(rng, &jubjub, payload(), vote).unwrap();
+        assert!(signed.verify(&jubjub, &pvk).unwrap());
+
+        let mut bytes = vec![];
+        signed.write(&mut bytes).unwrap();
+        let read = SignedVote::<Bls12>::read(&bytes[..], &jubjub).unwrap();
+        assert!(read.payload == signed.payload);
+        assert!(read.verify(&jubjub, &pvk).unwrap());
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let rng = &mut thread_rng();
+        let (jubjub, params) = setup();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        let signer = VoteSigner::<Bls12>::new(rng, &jubjub);
+        let vote = make_vote(&params, signer.h_sig());
+        let signed = signer.sign(rng, &jubjub, payload(), vote).unwrap();
+
+        let mut tampered = signed;
+        tampered.payload.step += 1;
+        assert!(!tampered.verify(&jubjub, &pvk).unwrap());
+
+        tampered.payload.step -= 1;
+        tampered.payload.block_hash[0] ^= 1;
+        assert!(!tampered.verify(&jubjub, &pvk).unwrap());
+    }
+
+    //a correctly signed payload for another step than the one of the role in the proof
+    #[test]
+    fn step_mismatch() {
+        let rng = &mut thread_rng();
+        let (jubjub, params) = setup();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        let signer = VoteSigner::<Bls12>::new(rng, &jubjub);
+        let vote = make_vote(&params, signer.h_sig());
+        let mut payload = payload();
+        payload.step += 1;
+        let signed = signer.sign(rng, &jubjub, payload, vote).unwrap();
+        assert!(!signed.verify(&jubjub, &pvk).unwrap());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let rng = &mut thread_rng();
+        let (jubjub, params) = setup();
+        let pvk = prepare_verifying_key(&params.vk);
+
+        let signer = VoteSigner::<Bls12>::new(rng, &jubjub);
+        let other = VoteSigner::<Bls12>::new(rng, &jubjub);
+        let vk = signer.vk.clone();
+        let other_vk = other.vk.clone();
+
+        //signed by another key than the one h_sig commits to
+        let vote = make_vote(&params, signer.h_sig());
+        let mut signed = other.sign(rng, &jubjub, payload(), vote).unwrap();
+        assert!(!signed.verify(&jubjub, &pvk).unwrap());
+        signed.vk = vk;
+        assert!(!signed.verify(&jubjub, &pvk).unwrap());
+
+        //right signature, but a vk that does not match h_sig
+        let vote = make_vote(&params, signer.h_sig());
+        let mut signed = signer.sign(rng, &jubjub, payload(), vote).unwrap();
+        signed.vk = other_vk;
+        assert!(!signed.verify(&jubjub, &pvk).unwrap());
+    }
+}
@@ -0,0 +1,21 @@
+use std::io::{self, Read, Write};
+
+use ff::{PrimeField, PrimeFieldRepr};
+
+pub mod anchors;
+pub mod block_proposal;
+pub mod epoch;
+pub mod message;
+pub mod role;
+pub mod votes;
+
+pub fn write_fr<F: PrimeField, W: Write>(value: &F, mut writer: W) -> io::Result<()> {
+    value.into_repr().write_le(&mut writer)
+}
+
+pub fn read_fr<F: PrimeField, R: Read>(mut reader: R) -> io::Result<F> {
+    let mut repr = F::Repr::default();
+    repr.read_le(&mut reader)?;
+
+    F::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
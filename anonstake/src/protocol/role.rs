@@ -0,0 +1,210 @@
+use std::fmt;
+
+/*
+typed version of the u64 role public input
+role = round << 8 | step << 1 | proposer
+step 0 with the proposer bit set is block proposal, the voting steps are 1 to 127
+
+the forward secure tree of a coin covers the roles fs_tree_start to fs_tree_start + 2^36 - 1,
+the circuit uses role - fs_tree_start as the time and only has 36 bits for it
+one round is 2^8 roles, so a tree lasts for 2^28 rounds
+*/
+pub const STEP_BITS: u64 = 7;
+pub const MAX_STEP: u64 = (1 << STEP_BITS) - 1;
+pub const MAX_ROUND: u64 = (1 << (64 - STEP_BITS - 1)) - 1;
+
+pub const FS_TIME_BITS: u64 = 36;
+pub const ROLES_PER_ROUND: u64 = 1 << (STEP_BITS + 1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Role {
+    pub round: u64,
+    pub step: u64,
+    pub proposer: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RoleError {
+    StepTooLarge(u64),
+    RoundTooLarge(u64),
+    BeforeTreeStart { role: u64, fs_tree_start: u64 },
+    AfterTreeEnd { role: u64, fs_tree_start: u64 },
+}
+
+impl fmt::Display for RoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoleError::StepTooLarge(step) => write!(f, "step {} is more than {}", step, MAX_STEP),
+            RoleError::RoundTooLarge(round) => write!(f, "round {} is more than {}", round, MAX_ROUND),
+            RoleError::BeforeTreeStart { role, fs_tree_start } =>
+                write!(f, "role {} is before the start {} of the forward secure tree", role, fs_tree_start),
+            RoleError::AfterTreeEnd { role, fs_tree_start } =>
+                write!(f, "role {} is after the end of the forward secure tree starting at {}", role, fs_tree_start),
+        }
+    }
+}
+
+impl Role {
+    pub fn new(round: u64, step: u64, proposer: bool) -> Result<Role, RoleError> {
+        if step > MAX_STEP {
+            return Err(RoleError::StepTooLarge(step));
+        }
+        if round > MAX_ROUND {
+            return Err(RoleError::RoundTooLarge(round));
+        }
+
+        Ok(Role { round, step, proposer })
+    }
+
+    pub fn proposer(round: u64) -> Result<Role, RoleError> {
+        Role::new(round, 0, true)
+    }
+
+    pub fn voter(round: u64, step: u64) -> Result<Role, RoleError> {
+        Role::new(round, step, false)
+    }
+
+    //first role of a round
+    pub fn round_start(round: u64) -> Result<Role, RoleError> {
+        Role::new(round, 0, false)
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        self.round << (STEP_BITS + 1) | self.step << 1 | self.proposer as u64
+    }
+
+    pub fn from_u64(role: u64) -> Role {
+        Role {
+            round: role >> (STEP_BITS + 1),
+            step: (role >> 1) & MAX_STEP,
+            proposer: role & 1 == 1,
+        }
+    }
+
+    //time in the forward secure tree, same as the time in forward_secure_tree
+    pub fn fs_time(&self, fs_tree_start: u64) -> Result<u64, RoleError> {
+        fs_time(self.to_u64(), fs_tree_start)
+    }
+}
+
+impl From<Role> for u64 {
+    fn from(role: Role) -> u64 {
+        role.to_u64()
+    }
+}
+
+pub fn fs_time(role: u64, fs_tree_start: u64) -> Result<u64, RoleError> {
+    if role < fs_tree_start {
+        return Err(RoleError::BeforeTreeStart { role, fs_tree_start });
+    }
+
+    let time = role - fs_tree_start;
+    if time >= 1 << FS_TIME_BITS {
+        return Err(RoleError::AfterTreeEnd { role, fs_tree_start });
+    }
+
+    Ok(time)
+}
+
+//last role a forward secure tree starting at fs_tree_start can be used for
+pub fn last_valid_role(fs_tree_start: u64) -> u64 {
+    fs_tree_start.saturating_add((1 << FS_TIME_BITS) - 1)
+}
+
+/*
+a new forward secure tree needs a new coin (full_pk commits to fs_tree_start and fs_pk),
+and a new coin can only be used for sortition once it is in the anchor snapshot,
+so the participant has to register it at least lookback rounds before the old tree runs out
+margin is the number of extra rounds to leave for the new coin to get into a block
+*/
+#[derive(Clone)]
+pub struct KeyRotation {
+    pub lookback: u64,
+    pub margin: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RotationStatus {
+    //the tree can still be used without registering a new one for this many rounds
+    Valid { rounds_left: u64 },
+    //register a new coin with this fs_tree_start now
+    Rotate { new_fs_tree_start: u64 },
+    //the tree cannot be used for this round
+    Invalid(RoleError),
+}
+
+impl KeyRotation {
+    pub fn status(&self, fs_tree_start: u64, current: Role) -> RotationStatus {
+        if let Err(e) = current.fs_time(fs_tree_start) {
+            return RotationStatus::Invalid(e);
+        }
+
+        let last_round = Role::from_u64(last_valid_role(fs_tree_start)).round;
+        let rounds_left = last_round - current.round;
+        let needed = self.lookback + self.margin;
+
+        if rounds_left > needed {
+            RotationStatus::Valid { rounds_left: rounds_left - needed }
+        } else {
+            //the new tree starts at the first round the new coin can be used in
+            let start_round = (current.round + self.lookback + 1).min(MAX_ROUND);
+            RotationStatus::Rotate { new_fs_tree_start: Role { round: start_round, step: 0, proposer: false }.to_u64() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for &(round, step, proposer) in &[(0, 0, true), (1, 1, false), (12345, MAX_STEP, false), (MAX_ROUND, 3, true)] {
+            let role = Role::new(round, step, proposer).unwrap();
+            assert_eq!(Role::from_u64(role.to_u64()), role);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert_eq!(Role::new(0, MAX_STEP + 1, false), Err(RoleError::StepTooLarge(MAX_STEP + 1)));
+        assert_eq!(Role::new(MAX_ROUND + 1, 0, false), Err(RoleError::RoundTooLarge(MAX_ROUND + 1)));
+    }
+
+    #[test]
+    fn ordered_like_u64() {
+        let a = Role::voter(5, MAX_STEP).unwrap();
+        let b = Role::proposer(6).unwrap();
+
+        assert!(a < b);
+        assert!(a.to_u64() < b.to_u64());
+    }
+
+    #[test]
+    fn fs_time_bounds() {
+        assert_eq!(fs_time(10, 10), Ok(0));
+        assert_eq!(fs_time(0, 0), Ok(0));
+        assert_eq!(fs_time(9, 10), Err(RoleError::BeforeTreeStart { role: 9, fs_tree_start: 10 }));
+        assert_eq!(fs_time(last_valid_role(10), 10), Ok((1 << FS_TIME_BITS) - 1));
+        assert_eq!(fs_time(last_valid_role(10) + 1, 10), Err(RoleError::AfterTreeEnd { role: last_valid_role(10) + 1, fs_tree_start: 10 }));
+    }
+
+    #[test]
+    fn rotation() {
+        let rotation = KeyRotation { lookback: 4, margin: 2 };
+        let start = Role::round_start(100).unwrap().to_u64();
+        let last_round = Role::from_u64(last_valid_role(start)).round;
+
+        assert_eq!(rotation.status(start, Role::voter(100, 1).unwrap()),
+                   RotationStatus::Valid { rounds_left: last_round - 100 - 6 });
+
+        let current = Role::voter(last_round - 6, 1).unwrap();
+        assert_eq!(rotation.status(start, current),
+                   RotationStatus::Rotate { new_fs_tree_start: Role::round_start(last_round - 1).unwrap().to_u64() });
+
+        match rotation.status(start, Role::voter(99, 1).unwrap()) {
+            RotationStatus::Invalid(RoleError::BeforeTreeStart { .. }) => {}
+            _ => panic!("role before the tree start should be invalid"),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use bellman::groth16::{PreparedVerifyingKey, Proof, verify_proof};
+use bellman::SynthesisError;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ff::PrimeField;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::public_inputs::PublicInputs;
+use crate::protocol::{read_fr, write_fr};
+
+/*
+tsn = PRF_{a_sk}(H(rho, role, j_i)) is unique per selected sub-user of a coin for a role,
+so two votes carrying the same (role, tsn) were made by the same sub-user
+if they differ in anything else, the sub-user voted twice
+*/
+#[derive(Clone)]
+pub struct Vote<E: JubjubEngine> {
+    pub proof: Proof<E>,
+    pub inputs: PublicInputs<E>,
+}
+
+impl<E: JubjubEngine> Vote<E> {
+    pub fn new(proof: Proof<E>, input: &[E::Fr], is_bp: bool) -> Option<Vote<E>> {
+        let inputs = PublicInputs::from_inputs(input, is_bp)?;
+        Some(Vote { proof, inputs })
+    }
+
+    pub fn verify(&self, pvk: &PreparedVerifyingKey<E>) -> Result<bool, SynthesisError> {
+        verify_proof(pvk, &self.proof, &self.inputs.to_inputs())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.proof.write(&mut writer)?;
+
+        let input = self.inputs.to_inputs();
+        writer.write_u8(self.inputs.is_bp() as u8)?;
+        for value in &input {
+            write_fr(value, &mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Vote<E>> {
+        let proof = Proof::read(&mut reader)?;
+
+        let is_bp = reader.read_u8()? == 1;
+        let num_inputs = if is_bp { crate::circuit::public_inputs::NUM_BP_INPUTS } else { crate::circuit::public_inputs::NUM_INPUTS };
+
+        let mut input = Vec::with_capacity(num_inputs);
+        for _ in 0..num_inputs {
+            input.push(read_fr(&mut reader)?);
+        }
+
+        Vote::new(proof, &input, is_bp)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid public inputs"))
+    }
+
+    //same sub-user and same statement, i.e. the same vote received twice
+    fn same_statement(&self, other: &Vote<E>) -> bool {
+        self.inputs.to_inputs() == other.inputs.to_inputs()
+    }
+}
+
+/*
+evidence that a sub-user voted twice for the same role
+both votes are kept in full so that anyone can re-verify the proofs and check
+that the role and tsn match without trusting whoever reported it
+*/
+#[derive(Clone)]
+pub struct Equivocation<E: JubjubEngine> {
+    pub first: Vote<E>,
+    pub second: Vote<E>,
+}
+
+impl<E: JubjubEngine> Equivocation<E> {
+    pub fn role(&self) -> u64 {
+        self.first.inputs.role
+    }
+
+    pub fn tsn(&self) -> E::Fr {
+        self.first.inputs.tsn
+    }
+
+    pub fn verify(&self, pvk: &PreparedVerifyingKey<E>) -> Result<bool, SynthesisError> {
+        if self.first.inputs.role != self.second.inputs.role || self.first.inputs.tsn != self.second.inputs.tsn {
+            return Ok(false);
+        }
+
+        if self.first.same_statement(&self.second) {
+            return Ok(false);
+        }
+
+        Ok(self.first.verify(pvk)? && self.second.verify(pvk)?)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u64::<LittleEndian>(self.role())?;
+        self.first.write(&mut writer)?;
+        self.second.write(&mut writer)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Equivocation<E>> {
+        let role = reader.read_u64::<LittleEndian>()?;
+        let first = Vote::read(&mut reader)?;
+        let second = Vote::read(&mut reader)?;
+
+        if first.inputs.role != role || second.inputs.role != role {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "role of evidence does not match votes"));
+        }
+
+        Ok(Equivocation { first, second })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum VoteStatus {
+    Accepted,
+    Duplicate,
+    Equivocation,
+}
+
+type VoteKey<E> = (u64, <<E as ff::ScalarEngine>::Fr as PrimeField>::Repr);
+
+/*
+votes indexed by (role, tsn)
+proofs should be verified before they are inserted, see Vote::verify
+*/
+pub struct VoteRegistry<E: JubjubEngine> {
+    votes: BTreeMap<VoteKey<E>, Vote<E>>,
+    evidence: Vec<Equivocation<E>>,
+}
+
+impl<E: JubjubEngine> Default for VoteRegistry<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: JubjubEngine> VoteRegistry<E> {
+    pub fn new() -> VoteRegistry<E> {
+        VoteRegistry {
+            votes: BTreeMap::new(),
+            evidence: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, vote: Vote<E>) -> VoteStatus {
+        let key = (vote.inputs.role, vote.inputs.tsn.into_repr());
+
+        if let Some(existing) = self.votes.get(&key) {
+            if existing.same_statement(&vote) {
+                return VoteStatus::Duplicate;
+            }
+
+            //only keep one piece of evidence for each equivocating sub-user
+            let known = self.evidence.iter().any(|e| e.role() == vote.inputs.role && e.tsn() == vote.inputs.tsn);
+            if !known {
+                self.evidence.push(Equivocation {
+                    first: existing.clone(),
+                    second: vote,
+                });
+            }
+
+            return VoteStatus::Equivocation;
+        }
+
+        self.votes.insert(key, vote);
+        VoteStatus::Accepted
+    }
+
+    pub fn contains(&self, role: u64, tsn: &E::Fr) -> bool {
+        self.votes.contains_key(&(role, tsn.into_repr()))
+    }
+
+    pub fn evidence(&self) -> &[Equivocation<E>] {
+        self.evidence.as_slice()
+    }
+
+    //each distinct tsn is one selected sub-user, so this is the weight of the votes for the role
+    pub fn weight(&self, role: u64) -> usize {
+        self.votes.range((role, Default::default())..)
+            .take_while(|((r, _), _)| *r == role)
+            .count()
+    }
+
+    //same as weight but only counts votes for the given h_sig
+    pub fn weight_for(&self, role: u64, h_sig: &E::Fr) -> usize {
+        self.votes.range((role, Default::default())..)
+            .take_while(|((r, _), _)| *r == role)
+            .filter(|(_, vote)| vote.inputs.h_sig == *h_sig)
+            .count()
+    }
+
+    pub fn reached_threshold(&self, role: u64, threshold: usize) -> bool {
+        self.weight(role) >= threshold
+    }
+
+    pub fn votes(&self, role: u64) -> Vec<&Vote<E>> {
+        self.votes.range((role, Default::default())..)
+            .take_while(|((r, _), _)| *r == role)
+            .map(|(_, vote)| vote)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::{Circuit, ConstraintSystem, SynthesisError};
+    use bellman::groth16::{create_random_proof, generate_random_parameters, Proof};
+    use ff::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::thread_rng;
+
+    use crate::circuit::public_inputs::{PublicInputs, u64_to_fr};
+
+    use super::*;
+
+    //insert does not verify proofs, so any proof will do
+    struct Trivial;
+
+    impl Circuit<Bls12> for Trivial {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let x = cs.alloc(|| "x", || Ok(Fr::one()))?;
+            cs.enforce(|| "x = 1", |lc| lc + x, |lc| lc + CS::one(), |lc| lc + CS::one());
+            Ok(())
+        }
+    }
+
+    fn proof() -> Proof<Bls12> {
+        let rng = &mut thread_rng();
+        let params = generate_random_parameters::<Bls12, _, _>(Trivial, rng).unwrap();
+        create_random_proof(Trivial, &params, rng).unwrap()
+    }
+
+    fn vote(proof: &Proof<Bls12>, role: u64, tsn: u64, h_sig: u64) -> Vote<Bls12> {
+        Vote {
+            proof: proof.clone(),
+            inputs: PublicInputs {
+                role,
+                cm_anchor: Fr::zero(),
+                seed: Fr::zero(),
+                sn_anchor: Fr::zero(),
+                tsn: u64_to_fr(tsn),
+                h_sig: u64_to_fr(h_sig),
+                h: Fr::zero(),
+                block_proposer: None,
+            },
+        }
+    }
+
+    #[test]
+    fn new_vote_accepted() {
+        let proof = proof();
+        let mut registry = VoteRegistry::<Bls12>::new();
+
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 5)), VoteStatus::Accepted);
+        //same tsn for another role is another sub-user
+        assert_eq!(registry.insert(vote(&proof, 3, 1, 5)), VoteStatus::Accepted);
+
+        assert!(registry.contains(2, &u64_to_fr(1)));
+        assert!(!registry.contains(2, &u64_to_fr(2)));
+        assert_eq!(registry.weight(2), 1);
+        assert!(registry.evidence().is_empty());
+    }
+
+    #[test]
+    fn duplicate_vote() {
+        let proof = proof();
+        let mut registry = VoteRegistry::<Bls12>::new();
+
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 5)), VoteStatus::Accepted);
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 5)), VoteStatus::Duplicate);
+
+        assert_eq!(registry.weight(2), 1);
+        assert!(registry.evidence().is_empty());
+    }
+
+    #[test]
+    fn equivocation_kept_once() {
+        let proof = proof();
+        let mut registry = VoteRegistry::<Bls12>::new();
+
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 5)), VoteStatus::Accepted);
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 6)), VoteStatus::Equivocation);
+        assert_eq!(registry.insert(vote(&proof, 2, 1, 7)), VoteStatus::Equivocation);
+
+        //the first vote is the one that counts
+        assert_eq!(registry.weight(2), 1);
+        assert_eq!(registry.weight_for(2, &u64_to_fr(5)), 1);
+        assert_eq!(registry.weight_for(2, &u64_to_fr(6)), 0);
+
+        assert_eq!(registry.evidence().len(), 1);
+        let evidence = &registry.evidence()[0];
+        assert_eq!(evidence.role(), 2);
+        assert_eq!(evidence.tsn(), u64_to_fr(1));
+        assert_eq!(evidence.first.inputs.h_sig, u64_to_fr(5));
+        assert_eq!(evidence.second.inputs.h_sig, u64_to_fr(6));
+    }
+
+    #[test]
+    fn threshold_reached() {
+        let proof = proof();
+        let mut registry = VoteRegistry::<Bls12>::new();
+
+        for tsn in 0..3 {
+            assert_eq!(registry.insert(vote(&proof, 4, tsn, 5)), VoteStatus::Accepted);
+            assert!(!registry.reached_threshold(4, 4));
+        }
+
+        //neither duplicates, equivocations nor votes for other roles add weight
+        registry.insert(vote(&proof, 4, 0, 5));
+        registry.insert(vote(&proof, 4, 1, 6));
+        registry.insert(vote(&proof, 5, 9, 5));
+        assert!(!registry.reached_threshold(4, 4));
+
+        assert_eq!(registry.insert(vote(&proof, 4, 3, 5)), VoteStatus::Accepted);
+        assert!(registry.reached_threshold(4, 4));
+        assert_eq!(registry.votes(4).len(), 4);
+        assert!(!registry.reached_threshold(5, 2));
+    }
+}
@@ -0,0 +1,654 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+
+use bellman::{Circuit, SynthesisError};
+use bellman::multicore::implementation;
+use bellman::gadgets::test::TestConstraintSystem;
+use bellman::groth16::{create_random_proof_with_input,
+                       finish_random_proof,
+                       Parameters,
+                       precompute_proof,
+                       prepare_verifying_key,
+                       Proof,
+                       verify_proof};
+use ff::{Field, PrimeField};
+use pairing::bls12_381::{Bls12, Fr};
+use zcash_primitives::jubjub::JubjubBls12;
+
+use anonstake::api::{generate_params, read_params, write_params};
+use anonstake::circuit::{AnonStake, diagnostics};
+use anonstake::circuit::exporter::{read_wtns, R1csExporter};
+use anonstake::circuit::hash::{CircuitHash, Pedersen, Poseidon};
+use anonstake::circuit::mint::{Mint, MintWitness};
+use anonstake::circuit::multi_coin::MultiCoin;
+use anonstake::circuit::multi_role::MultiRole;
+use anonstake::circuit::public_inputs::{fr_from_hex, fr_to_hex};
+use anonstake::circuit::profiler::ProfilingConstraintSystem;
+use anonstake::circuit::transfer::Transfer;
+use anonstake::constants::{Constants, PrfDomain, PrfKind};
+use anonstake::constants::{mimc_generator, poseidon_generator};
+use anonstake::constants::mimc_constants::MiMCConstants;
+use anonstake::error::Error;
+use anonstake::protocol::write_fr;
+
+use crate::cli::{heights_suffix, param_name, RunConfig, RunMode};
+
+/*
+runs one RunConfig of the command line program (src/main.rs), see cli.rs for the modes
+everything here prints its results, use api.rs from other code
+*/
+
+//sortition witness for a delegated coin if the config asks for it
+fn delegate<'a, H: CircuitHash<Bls12>, R: Rng>(config: &RunConfig, anonstake: AnonStake<'a, Bls12, H>, rng: &mut R) -> AnonStake<'a, Bls12, H> {
+    if config.delegated {
+        let a_pk_owner = anonstake.aux_input.a_sk.map(|_| Fr::random(rng));
+        anonstake.into_delegated(a_pk_owner)
+    } else {
+        anonstake
+    }
+}
+
+/*
+the mint proofs of a tree file are appended to the tree file name with .proofs added, one record per mint:
+the groth16 proof as written by Proof::write (192 bytes), then the public inputs value and cm
+as 32 byte little endian field elements, 256 bytes in total
+*/
+fn mint_proofs_path(tree_file: &Path) -> PathBuf {
+    let mut name = tree_file.as_os_str().to_owned();
+    name.push(".proofs");
+    PathBuf::from(name)
+}
+
+fn run_notification<H: CircuitHash<Bls12>>(config: &RunConfig, constants: &Constants<Bls12>) -> Result<(), Error> {
+    let param = param_name(config);
+    print!("params: {} | ", param);
+
+    if config.test_constraint_system {
+        print!("params: {} | ", param);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let anonstake = AnonStake::<Bls12, H>::init_pure_random_with_heights(&constants, config.is_bp, config.merkle_heights);
+        let anonstake = delegate(config, anonstake, &mut thread_rng());
+        anonstake.synthesize(&mut cs).map_err(Error::Synthesis)?;
+
+        println!("constraints: {}, inputs: {}, aux: {}", cs.num_constraints(), cs.num_inputs(), cs.num_aux());
+    } else {
+        println!("params: {}", param);
+    }
+
+    Ok(())
+}
+
+//writes (prefix).r1cs or (prefix).wtns and the json versions, see circuit/exporter.rs
+fn export<H: CircuitHash<Bls12>>(config: &RunConfig, constants: &Constants<Bls12>, prefix: &Option<PathBuf>, witness: bool) -> Result<(), Error> {
+    let prefix = prefix.clone().unwrap_or(PathBuf::from(param_name(config)));
+    let prefix = prefix.display();
+
+    let mut cs = R1csExporter::<Bls12>::new();
+    let anonstake = if witness {
+        AnonStake::<Bls12, H>::init_testing_with_rng(&constants, config.is_bp, config.merkle_heights, 1, &mut thread_rng())
+    } else {
+        AnonStake::<Bls12, H>::init_empty_with_heights(&constants, config.is_bp, config.merkle_heights)
+    };
+    let anonstake = delegate(config, anonstake, &mut thread_rng());
+    anonstake.synthesize(&mut cs).map_err(Error::Synthesis)?;
+
+    println!("params: {} | wires: {}, public inputs: {}, constraints: {}", param_name(config), cs.num_wires(), cs.inputs.len() - 1, cs.constraints.len());
+
+    let ext = if witness { "wtns" } else { "r1cs" };
+
+    let path = PathBuf::from(format!("{}.{}", prefix, ext));
+    let file = create_file(&path)?;
+    let result = if witness { cs.write_wtns(file) } else { cs.write_r1cs(file) };
+    result.map_err(Error::io(&path))?;
+    println!("wrote {}", path.display());
+
+    let path = PathBuf::from(format!("{}.{}.json", prefix, ext));
+    let file = create_file(&path)?;
+    let result = if witness { cs.write_wtns_json(file) } else { cs.write_r1cs_json(file) };
+    result.map_err(Error::io(&path))?;
+    println!("wrote {}", path.display());
+
+    //the public inputs on their own, for check_witness --inputs
+    if witness {
+        let path = PathBuf::from(format!("{}.inputs", prefix));
+        let mut file = create_file(&path)?;
+        for input in &cs.inputs[1..] {
+            writeln!(file, "{}", fr_to_hex(&input.unwrap())).map_err(Error::io(&path))?;
+        }
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+//public inputs for check_witness, one field element per line in the hex format of fr_to_hex
+fn read_inputs(path: &Path) -> Result<Vec<Fr>, Error> {
+    let contents = std::fs::read_to_string(path).map_err(Error::io(path))?;
+
+    contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| fr_from_hex(line).ok_or(Error::Config(format!("could not read public input {} in {}, it has to be a field element in hex like the printed values", line, path.display()))))
+        .collect()
+}
+
+fn multi_role_notification<H: CircuitHash<Bls12>>(config: &RunConfig, constants: &Constants<Bls12>, num_roles: usize) -> Result<(), Error> {
+    let tau: &str = (&config.tau).into();
+    let pos = if config.use_poseidon { "" } else { "_no_poseidon" };
+    let heights = heights_suffix(&config.merkle_heights, config.use_poseidon);
+    print!("params: {}_multi{}{}{} | ", tau, num_roles, pos, heights);
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let multi_role = MultiRole::<Bls12, H>::init_pure_random_with_heights(&constants, config.merkle_heights, num_roles);
+    multi_role.synthesize(&mut cs).map_err(Error::Synthesis)?;
+
+    let mut single_cs = TestConstraintSystem::<Bls12>::new();
+    let anonstake = AnonStake::<Bls12, H>::init_pure_random_with_heights(&constants, false, config.merkle_heights);
+    anonstake.synthesize(&mut single_cs).map_err(Error::Synthesis)?;
+
+    println!("constraints: {} ({} for {} separate proofs), inputs: {}, aux: {} ({} for {} separate proofs)",
+             cs.num_constraints(), single_cs.num_constraints() * num_roles, num_roles,
+             cs.num_inputs(), cs.num_aux(), single_cs.num_aux() * num_roles, num_roles);
+
+    Ok(())
+}
+
+fn create_file(path: &Path) -> Result<File, Error> {
+    File::create(path).map_err(Error::io(path))
+}
+
+//false from verify_proof is an error too, so that scripts can check the exit code
+fn verify_all(params: &Parameters<Bls12>, proofs: &[(Proof<Bls12>, Vec<Fr>)], what: &str) -> Result<(), Error> {
+    let pvk = prepare_verifying_key(&params.vk);
+    for (proof, input) in proofs {
+        let result = verify_proof(&pvk, &proof, &input[1..]).map_err(Error::Verification)?;
+        if !result {
+            return Err(Error::InvalidProof(what.to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_config(config: RunConfig) -> Result<(), Error> {
+    if config.use_poseidon {
+        run::<Poseidon>(config)
+    } else {
+        run::<Pedersen>(config)
+    }
+}
+
+fn run<H: CircuitHash<Bls12>>(config: RunConfig) -> Result<(), Error> {
+    if let Some(threads) = config.threads {
+        implementation::NUM_CPUS.store(threads, Ordering::SeqCst);
+        implementation::HAS_LOADED.store(true, Ordering::SeqCst);
+    }
+
+    //the seed only fixes the sortition witnesses and the proof randomness, not the other circuits' witnesses
+    let rng = &mut match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let jubjub = JubjubBls12::new();
+    let prf = if config.poseidon_prf { PrfKind::Poseidon } else { PrfKind::MiMC };
+    let constants = Constants::<Bls12>::get(&jubjub, config.tau.clone()).with_prf(prf);
+
+    match &config.mode {
+        RunMode::OnlyGenParams(params_file) => {
+            run_notification::<H>(&config, &constants)?;
+            println!("{}", params_file.display());
+
+            let params = {
+                let anonstake = AnonStake::<Bls12, H>::init_empty_with_heights(&constants, config.is_bp, config.merkle_heights);
+                let anonstake = delegate(&config, anonstake, rng);
+                generate_params(anonstake, rng)?
+            };
+
+            write_params(&params, params_file)?;
+        }
+        RunMode::OutputCircuitInfo => {
+            run_notification::<H>(&config, &constants)?;
+        }
+        RunMode::Sample(params_file) => {
+            run_notification::<H>(&config, &constants)?;
+
+            let params = {
+                println!("{}", params_file.display());
+                read_params(params_file, config.check_params)?
+            };
+
+            let anonstake = AnonStake::<Bls12, H>::init_testing_with_rng(&constants, config.is_bp, config.merkle_heights, 1, rng);
+            let anonstake = delegate(&config, anonstake, rng);
+            let (proof, input) = create_random_proof_with_input(anonstake, &params, rng).map_err(Error::Proving)?;
+
+            let pvk = prepare_verifying_key(&params.vk);
+
+            let result = verify_proof(&pvk, &proof, &input[1..]).map_err(Error::Verification)?;
+            println!("verification result: {} (should be true)", result);
+            if !result {
+                return Err(Error::InvalidProof("the sample proof".to_owned()));
+            }
+        }
+        RunMode::Single(params_file, output_file, trials) => {
+            let trials = *trials as usize;
+            run_notification::<H>(&config, &constants)?;
+
+            let params = read_params(params_file, config.check_params)?;
+
+            let mut proofs = Vec::with_capacity(trials);
+            let mut times = Vec::with_capacity(trials);
+
+            for _ in 0..trials {
+                let start = Instant::now();
+                let anonstake = AnonStake::<Bls12, H>::init_testing_with_rng(&constants, config.is_bp, config.merkle_heights, 1, rng);
+                let anonstake = delegate(&config, anonstake, rng);
+                let (proof, input) = create_random_proof_with_input(anonstake, &params, rng).map_err(Error::Proving)?;
+
+                times.push(start.elapsed().as_millis());
+                proofs.push((proof, input));
+            }
+
+            let mut output = create_file(output_file)?;
+
+            let mut total_time = 0;
+            for time in times{
+                output.write_all(format!("{}\n", time).as_ref()).map_err(Error::io(output_file))?;
+                total_time += time;
+            }
+
+            let avg_time = total_time as f64 / trials as f64;
+            output.write_all(format!("average proof time: {}\n", avg_time).as_ref()).map_err(Error::io(output_file))?;
+
+
+            verify_all(&params, &proofs, "some proofs")?;
+            println!("All proofs verified");
+        },
+        RunMode::Batch(params_file, output_file, trials, num_batch) => {
+            let trials = *trials as usize;
+            let num_batch = *num_batch as usize;
+
+            run_notification::<H>(&config, &constants)?;
+
+            let params = read_params(params_file, config.check_params)?;
+
+            let mut times = Vec::with_capacity(trials);
+            let mut proofs = Vec::with_capacity(trials);
+
+            for _ in 0..trials {
+                let start = Instant::now();
+                let anonstake = AnonStake::<Bls12, H>::init_testing_with_rng(&constants, config.is_bp, config.merkle_heights, 1, rng);
+                let mut iter = delegate(&config, anonstake, rng).into_iter();
+                let proof_kernel = precompute_proof(iter.get_copy().ok_or(Error::Proving(SynthesisError::AssignmentMissing))?, &params).map_err(Error::Proving)?;
+                let precomputation_time = start.elapsed().as_millis();
+
+                let mut batch_times = Vec::with_capacity(num_batch);
+
+                for _ in 0..num_batch {
+                    let start = Instant::now();
+                    let (proof, input) = finish_random_proof(iter.next().ok_or(Error::Proving(SynthesisError::AssignmentMissing))?, &params, rng, &proof_kernel).map_err(Error::Proving)?;
+
+                    batch_times.push(start.elapsed().as_millis());
+                    proofs.push((proof, input));
+                }
+
+                times.push((precomputation_time, batch_times));
+            }
+
+            let mut output = create_file(output_file)?;
+            let mut avg_first_proof_time = 0;
+            let mut avg_additional_proof_time: u128 = 0;
+
+            for trial_times in times {
+                output.write_all(format!("{}\n", trial_times.0).as_ref()).map_err(Error::io(output_file))?;
+                avg_first_proof_time += trial_times.0;
+                avg_first_proof_time += trial_times.1[0];
+
+
+                for (i, time) in trial_times.1.iter().enumerate() {
+                    if i != 0 {
+                        avg_additional_proof_time += *time;
+                    }
+
+                    let end = if i == (trial_times.1.len() - 1) {"\n"} else {", "};
+                    output.write_all(format!("{}{}", time, end).as_ref()).map_err(Error::io(output_file))?;
+                }
+            }
+
+            let avg_first_proof_time = avg_first_proof_time as f64 / trials as f64;
+            output.write_all(format!("first proof time: {}\n", avg_first_proof_time).as_ref()).map_err(Error::io(output_file))?;
+            let avg_additional_proof_time = avg_additional_proof_time as f64 / ((num_batch - 1) as f64 * (trials) as f64);
+            output.write_all(format!("additional proof time: {}", avg_additional_proof_time).as_ref()).map_err(Error::io(output_file))?;
+
+            verify_all(&params, &proofs, "some proofs")?;
+            println!("All proofs verified");
+        }
+        RunMode::TransferGenParams(params_file, num_inputs, delegated) => {
+            println!("{}", params_file.display());
+
+            let params = {
+                let transfer = Transfer::<Bls12, H>::init_empty_with_heights(&constants, config.merkle_heights, *num_inputs, *delegated);
+                generate_params(transfer, rng)?
+            };
+
+            write_params(&params, params_file)?;
+        }
+        RunMode::TransferSample(params_file, num_inputs, delegated) => {
+            let params = {
+                println!("{}", params_file.display());
+                read_params(params_file, config.check_params)?
+            };
+
+            let transfer = Transfer::<Bls12, H>::init_testing_with_heights(&constants, config.merkle_heights, *num_inputs, *delegated);
+
+            if config.test_constraint_system {
+                let mut cs = TestConstraintSystem::<Bls12>::new();
+                transfer.clone().synthesize(&mut cs).map_err(Error::Synthesis)?;
+                println!("constraints: {}, inputs: {}, aux: {}", cs.num_constraints(), cs.num_inputs(), cs.num_aux());
+            }
+
+            let (proof, input) = create_random_proof_with_input(transfer, &params, rng).map_err(Error::Proving)?;
+
+            let pvk = prepare_verifying_key(&params.vk);
+
+            let result = verify_proof(&pvk, &proof, &input[1..]).map_err(Error::Verification)?;
+            println!("verification result: {} (should be true)", result);
+            if !result {
+                return Err(Error::InvalidProof("the sample proof".to_owned()));
+            }
+        }
+        RunMode::MultiRoleInfo(num_roles) => {
+            multi_role_notification::<H>(&config, &constants, *num_roles)?;
+        }
+        RunMode::MultiRoleGenParams(params_file, num_roles) => {
+            multi_role_notification::<H>(&config, &constants, *num_roles)?;
+            println!("{}", params_file.display());
+
+            let params = {
+                let multi_role = MultiRole::<Bls12, H>::init_empty_with_heights(&constants, config.merkle_heights, *num_roles);
+                generate_params(multi_role, rng)?
+            };
+
+            write_params(&params, params_file)?;
+        }
+        RunMode::MultiRoleSingle(params_file, output_file, trials, num_roles) => {
+            let trials = *trials as usize;
+            println!("params: {}", params_file.file_stem().unwrap_or_default().to_string_lossy());
+
+            let params = read_params(params_file, config.check_params)?;
+
+            let mut proofs = Vec::with_capacity(trials);
+            let mut times = Vec::with_capacity(trials);
+
+            for _ in 0..trials {
+                let start = Instant::now();
+                let multi_role = MultiRole::<Bls12, H>::init_testing_with_heights(&constants, config.merkle_heights, *num_roles, 1);
+                let (proof, input) = create_random_proof_with_input(multi_role, &params, rng).map_err(Error::Proving)?;
+
+                times.push(start.elapsed().as_millis());
+                proofs.push((proof, input));
+            }
+
+            let mut output = create_file(output_file)?;
+
+            let mut total_time = 0;
+            for time in times {
+                output.write_all(format!("{}\n", time).as_ref()).map_err(Error::io(output_file))?;
+                total_time += time;
+            }
+
+            let avg_time = total_time as f64 / trials as f64;
+            output.write_all(format!("average proof time: {}\n", avg_time).as_ref()).map_err(Error::io(output_file))?;
+            output.write_all(format!("average time per role: {}\n", avg_time / *num_roles as f64).as_ref()).map_err(Error::io(output_file))?;
+
+            verify_all(&params, &proofs, "some proofs")?;
+            println!("All proofs verified");
+        }
+        RunMode::MultiCoinGenParams(params_file, num_coins) => {
+            println!("{}", params_file.display());
+
+            let params = {
+                let multi_coin = MultiCoin::<Bls12, H>::init_empty_with_heights(&constants, config.merkle_heights, *num_coins);
+                generate_params(multi_coin, rng)?
+            };
+
+            write_params(&params, params_file)?;
+        }
+        RunMode::MultiCoinSample(params_file, num_coins) => {
+            let params = {
+                println!("{}", params_file.display());
+                read_params(params_file, config.check_params)?
+            };
+
+            let multi_coin = MultiCoin::<Bls12, H>::init_testing_with_heights(&constants, config.merkle_heights, *num_coins, 1);
+
+            if config.test_constraint_system {
+                let mut cs = TestConstraintSystem::<Bls12>::new();
+                multi_coin.clone().synthesize(&mut cs).map_err(Error::Synthesis)?;
+                println!("constraints: {}, inputs: {}, aux: {}", cs.num_constraints(), cs.num_inputs(), cs.num_aux());
+            }
+
+            let (proof, input) = create_random_proof_with_input(multi_coin, &params, rng).map_err(Error::Proving)?;
+
+            let pvk = prepare_verifying_key(&params.vk);
+
+            let result = verify_proof(&pvk, &proof, &input[1..]).map_err(Error::Verification)?;
+            println!("verification result: {} (should be true)", result);
+            if !result {
+                return Err(Error::InvalidProof("the sample proof".to_owned()));
+            }
+        }
+        RunMode::MintGenParams(params_file) => {
+            println!("{}", params_file.display());
+
+            let params = {
+                let mint = Mint::<Bls12>::init_empty(&constants);
+                generate_params(mint, rng)?
+            };
+
+            write_params(&params, params_file)?;
+        }
+        RunMode::Mint(params_file, tree_file, value, full_pk) => {
+            if *value >= 1 << constants.max_value {
+                return Err(Error::Config(format!("value must be less than 2^{}", constants.max_value)));
+            }
+
+            let full_pk = match full_pk {
+                Some(full_pk) => match fr_from_hex(full_pk) {
+                    Some(full_pk) => full_pk,
+                    None => return Err(Error::Config(format!("could not read full_pk {}, it has to be a field element in hex like the printed values", full_pk))),
+                },
+                None => Fr::random(rng),
+            };
+
+            let params = read_params(params_file, config.check_params)?;
+
+            let witness = MintWitness::new(&constants, full_pk, *value);
+            let mint = Mint::from_witness(&constants, &witness);
+            let (proof, _) = create_random_proof_with_input(mint, &params, rng).map_err(Error::Proving)?;
+
+            let pvk = prepare_verifying_key(&params.vk);
+            let public_inputs = witness.public_inputs();
+            let result = verify_proof(&pvk, &proof, &public_inputs).map_err(Error::Verification)?;
+            if !result {
+                return Err(Error::InvalidProof("the mint proof (the coin commitment was not added)".to_owned()));
+            }
+
+            //the proof is written first, so every commitment in the tree has its proof
+            let proofs_file = mint_proofs_path(tree_file);
+            let mut record = vec![];
+            proof.write(&mut record).map_err(Error::io(&proofs_file))?;
+            for value in &public_inputs {
+                write_fr(value, &mut record).map_err(Error::io(&proofs_file))?;
+            }
+
+            let mut proofs = OpenOptions::new().create(true).append(true).open(&proofs_file).map_err(Error::io(&proofs_file))?;
+            proofs.write_all(&record).map_err(Error::io(&proofs_file))?;
+
+            let mut tree = OpenOptions::new().create(true).append(true).open(tree_file).map_err(Error::io(tree_file))?;
+            tree.write_all(format!("{}\n", fr_to_hex(&witness.cm)).as_ref()).map_err(Error::io(tree_file))?;
+
+            println!("added coin commitment {} to {}", fr_to_hex(&witness.cm), tree_file.display());
+            println!("added its mint proof to {}", proofs_file.display());
+            println!("value: {}", witness.value);
+            println!("full_pk: {}", fr_to_hex(&witness.full_pk));
+            println!("rho: {}", fr_to_hex(&witness.rho));
+            println!("s: {}", witness.s.into_repr());
+        }
+        RunMode::PoseidonConstants(width, security, print) => {
+            let generated = poseidon_generator::generate::<Fr>(*width, *security);
+            println!("width: {}, security: {} bits, r_f: {}, r_p: {}", width, security, generated.r_f, generated.r_p);
+
+            if *print {
+                println!("round constants:");
+                for round in &generated.rounds {
+                    for elem in round {
+                        println!("{}", elem.into_repr());
+                    }
+                }
+
+                println!("mds matrix:");
+                for row in &generated.mds {
+                    for elem in row {
+                        println!("{}", elem.into_repr());
+                    }
+                }
+            }
+
+            if *width == 9 {
+                let report = poseidon_generator::compare(&constants.poseidon, &generated);
+                if report.len() == 0 {
+                    println!("the committed poseidon constants are the same as the generated ones");
+                } else {
+                    println!("the committed poseidon constants differ from the generated ones:");
+                    for line in report {
+                        println!("{}", line);
+                    }
+                }
+            }
+        }
+        RunMode::ExportR1cs(prefix) => {
+            export::<H>(&config, &constants, prefix, false)?;
+        }
+        RunMode::ExportWitness(prefix) => {
+            export::<H>(&config, &constants, prefix, true)?;
+        }
+        RunMode::CheckWitness(corrupt, witness_file, inputs_file) => {
+            let expected = match inputs_file {
+                Some(path) => Some(read_inputs(path)?),
+                None => None,
+            };
+
+            let check = match witness_file {
+                Some(path) => {
+                    let file = File::open(path).map_err(Error::io(path))?;
+                    let witness = read_wtns::<Fr, _>(file).map_err(Error::io(path))?;
+
+                    //cli.yml makes --inputs required with --witness
+                    let expected = expected.ok_or(Error::Config("check_witness --witness needs the expected public inputs (--inputs)".to_owned()))?;
+
+                    let anonstake = AnonStake::<Bls12, H>::init_empty_with_heights(&constants, config.is_bp, config.merkle_heights);
+                    let anonstake = delegate(&config, anonstake, rng);
+
+                    print!("params: {} | ", param_name(&config));
+                    diagnostics::check_loaded_witness::<Bls12, _>(anonstake, &witness, &expected)?
+                }
+                None => {
+                    let anonstake = AnonStake::<Bls12, H>::init_testing_with_rng(&constants, config.is_bp, config.merkle_heights, 1, rng);
+                    let mut anonstake = delegate(&config, anonstake, rng);
+
+                    //what the verifier expects is what the witness gives before it is corrupted
+                    let expected = match expected {
+                        Some(expected) => expected,
+                        None => diagnostics::public_inputs::<Bls12, _>(anonstake.clone()).map_err(Error::Synthesis)?,
+                    };
+
+                    if let Some(part) = corrupt {
+                        if !diagnostics::corrupt_witness(&mut anonstake, part) {
+                            return Err(Error::Config(format!("cannot corrupt {}, choose one of {:?}", part, diagnostics::CORRUPTIBLE)));
+                        }
+                    }
+
+                    print!("params: {} | ", param_name(&config));
+                    diagnostics::check_witness::<Bls12, _>(anonstake, &expected).map_err(Error::Synthesis)?
+                }
+            };
+
+            match &check.unsatisfied {
+                None => println!("the witness satisfies all {} constraints", check.num_constraints),
+                Some(unsatisfied) => {
+                    println!("the witness does not satisfy {} (of {} constraints)", unsatisfied.component, check.num_constraints);
+                    println!("constraint: {}", unsatisfied.path);
+                }
+            }
+
+            if check.inputs_match {
+                println!("the public inputs are the expected ones");
+            } else {
+                //e.g. a wrong merkle path satisfies the circuit but gives another anchor
+                println!("the public inputs of the witness are not the expected ones, the proof would not verify");
+            }
+        }
+        RunMode::Profile(depth, csv) => {
+            let mut cs = ProfilingConstraintSystem::<Bls12>::new();
+            let anonstake = AnonStake::<Bls12, H>::init_empty_with_heights(&constants, config.is_bp, config.merkle_heights);
+            let anonstake = delegate(&config, anonstake, rng);
+            anonstake.synthesize(&mut cs).map_err(Error::Synthesis)?;
+
+            let param = param_name(&config);
+
+            if *csv {
+                let path = PathBuf::from(format!("benchmarks/profile_{}.csv", param));
+                let file = create_file(&path)?;
+                cs.write_csv(file, &param, *depth).map_err(Error::io(&path))?;
+
+                println!("params: {} | wrote {}", param, path.display());
+            } else {
+                println!("params: {}", param);
+                cs.print_tree(*depth);
+                println!();
+            }
+        }
+        RunMode::MiMCConstants(print) => {
+            for exponent in 2..12 {
+                let committed = if exponent == constants.mimc.exponent { " (committed)" } else { "" };
+
+                match mimc_generator::num_rounds::<Fr>(exponent) {
+                    Some(num_rounds) => println!("x^(2^{} - 1): {} rounds, {} constraints per prf{}",
+                                                 exponent, num_rounds, mimc_generator::num_constraints(num_rounds, exponent), committed),
+                    None => println!("x^(2^{} - 1): not a permutation{}", exponent, committed),
+                }
+            }
+
+            let derived = MiMCConstants::<Bls12>::derived(constants.mimc.num_rounds, constants.mimc.exponent);
+
+            if *print {
+                let domains = mimc_generator::ALL_DOMAINS.iter().cloned().chain((0..20).map(PrfDomain::Sel));
+                for domain in domains {
+                    println!("{}:", mimc_generator::domain_string(domain));
+                    for elem in derived.domain(domain).iter() {
+                        println!("{}", elem.into_repr());
+                    }
+                }
+            }
+
+            let report = mimc_generator::compare(&constants.mimc, &derived);
+            if report.len() == 0 {
+                println!("the committed mimc constants are the same as the derived ones");
+            } else {
+                println!("the committed mimc constants differ from the derived ones:");
+                for line in report {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
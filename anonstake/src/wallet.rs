@@ -0,0 +1,311 @@
+use ff::Field;
+use rand_core::RngCore;
+use zcash_primitives::jubjub::JubjubEngine;
+
+use crate::circuit::AnonStake;
+use crate::circuit::hash::CircuitHash;
+use crate::circuit::anonstake_inputs::Coin;
+use crate::circuit::transfer::TransferInput;
+use crate::constants::Constants;
+use crate::native;
+
+/*
+keys and coins of a staker
+a_sk is the spending key of the owner. a staker that runs its own node uses it for sortition as well
+a staker can delegate participation to another node: it creates a participation key a_sk_del,
+gives it (together with a_pk of the owner) to the node and moves its coins to the delegated full_pk
+H(fs_tree_start, fs_pk, a_pk_owner, a_pk_del)
+the node can then create sortition proofs for those coins, but every transfer still needs a_sk
+the forward secure tree belongs to whoever does the sortition, so the node picks fs_tree_start and fs_pk
+every coin remembers the participation key it was delegated to, so a new participation key does not lose older coins
+*/
+#[derive(Clone)]
+pub struct Keys<E: JubjubEngine> {
+    pub a_sk: E::Fr,
+    pub a_sk_del: Option<E::Fr>,
+}
+
+//what a (possibly delegated) participating node holds
+#[derive(Clone)]
+pub struct ParticipationKey<E: JubjubEngine> {
+    pub a_sk: E::Fr,
+    //only set for delegated participation
+    pub a_pk_owner: Option<E::Fr>,
+}
+
+#[derive(Clone)]
+pub struct WalletCoin<E: JubjubEngine> {
+    pub value: u64,
+    pub rho: E::Fr,
+    pub s: E::Fs,
+    pub fs_tree_start: u64,
+    pub fs_pk: E::Fr,
+    //participation key the coin was delegated to, None for coins of the owner
+    pub a_sk_del: Option<E::Fr>,
+    pub cm: E::Fr,
+}
+
+pub struct Wallet<E: JubjubEngine> {
+    pub keys: Keys<E>,
+    pub coins: Vec<WalletCoin<E>>,
+}
+
+impl<E: JubjubEngine> Keys<E> {
+    pub fn new<R: RngCore>(rng: &mut R) -> Keys<E> {
+        Keys {
+            a_sk: E::Fr::random(rng),
+            a_sk_del: None,
+        }
+    }
+
+    pub fn a_pk(&self, constants: &Constants<E>) -> E::Fr {
+        native::a_pk(constants, &self.a_sk)
+    }
+
+    pub fn a_pk_del(&self, constants: &Constants<E>) -> Option<E::Fr> {
+        self.a_sk_del.map(|a_sk_del| native::a_pk(constants, &a_sk_del))
+    }
+
+    //coins received before this keep their key, they have to be transferred to the new full_pk to be delegated to it
+    pub fn new_participation_key<R: RngCore>(&mut self, rng: &mut R) -> E::Fr {
+        let a_sk_del = E::Fr::random(rng);
+        self.a_sk_del = Some(a_sk_del);
+
+        a_sk_del
+    }
+
+    pub fn is_delegated(&self) -> bool {
+        self.a_sk_del.is_some()
+    }
+
+    pub fn participation_key(&self, constants: &Constants<E>) -> ParticipationKey<E> {
+        match self.a_sk_del {
+            Some(a_sk_del) => ParticipationKey {
+                a_sk: a_sk_del,
+                a_pk_owner: Some(self.a_pk(constants)),
+            },
+            None => ParticipationKey {
+                a_sk: self.a_sk,
+                a_pk_owner: None,
+            }
+        }
+    }
+
+    //full_pk to receive new coins at, given the forward secure tree of the participating node
+    pub fn full_pk(&self, constants: &Constants<E>, fs_tree_start: u64, fs_pk: &E::Fr) -> E::Fr {
+        self.participation_key(constants).full_pk(constants, fs_tree_start, fs_pk)
+    }
+}
+
+impl<E: JubjubEngine> ParticipationKey<E> {
+    pub fn is_delegated(&self) -> bool {
+        self.a_pk_owner.is_some()
+    }
+
+    pub fn full_pk(&self, constants: &Constants<E>, fs_tree_start: u64, fs_pk: &E::Fr) -> E::Fr {
+        let a_pk = native::a_pk(constants, &self.a_sk);
+
+        match self.a_pk_owner {
+            Some(a_pk_owner) => native::full_pk_delegated(constants, fs_tree_start, fs_pk, &a_pk_owner, &a_pk),
+            None => native::full_pk(constants, fs_tree_start, fs_pk, &a_pk),
+        }
+    }
+
+    //the serial number of a coin is always derived from the key used for sortition
+    pub fn sn(&self, constants: &Constants<E>, coin: &WalletCoin<E>) -> E::Fr {
+        native::sn(constants, &self.a_sk, &coin.rho)
+    }
+
+    //fills in the key and coin parts of a sortition witness
+    pub fn apply<'a, H: CircuitHash<E>>(&self, mut anonstake: AnonStake<'a, E, H>, coin: &WalletCoin<E>) -> AnonStake<'a, E, H> {
+        anonstake.aux_input.a_sk = Some(self.a_sk);
+        anonstake.aux_input.fs_tree_start = Some(coin.fs_tree_start);
+        anonstake.aux_input.coin = coin.coin();
+
+        if self.is_delegated() {
+            anonstake = anonstake.into_delegated(self.a_pk_owner);
+        }
+
+        anonstake
+    }
+}
+
+impl<E: JubjubEngine> WalletCoin<E> {
+    pub fn is_delegated(&self) -> bool {
+        self.a_sk_del.is_some()
+    }
+
+    pub fn coin(&self) -> Coin<E> {
+        Coin {
+            value: Some(self.value),
+            rho: Some(self.rho),
+            s: Some(self.s),
+        }
+    }
+}
+
+impl<E: JubjubEngine> Wallet<E> {
+    pub fn new<R: RngCore>(rng: &mut R) -> Wallet<E> {
+        Wallet {
+            keys: Keys::new(rng),
+            coins: vec![],
+        }
+    }
+
+    //records a coin received at keys.full_pk(fs_tree_start, fs_pk)
+    pub fn receive(&mut self, constants: &Constants<E>, value: u64, rho: E::Fr, s: E::Fs, fs_tree_start: u64, fs_pk: E::Fr) -> &WalletCoin<E> {
+        let full_pk = self.keys.full_pk(constants, fs_tree_start, &fs_pk);
+        let cm = native::coin_commitment(constants, &full_pk, value, &rho, &s);
+
+        self.coins.push(WalletCoin {
+            value,
+            rho,
+            s,
+            fs_tree_start,
+            fs_pk,
+            a_sk_del: self.keys.a_sk_del,
+            cm,
+        });
+
+        self.coins.last().unwrap()
+    }
+
+    pub fn total_value(&self) -> u64 {
+        self.coins.iter().map(|coin| coin.value).sum()
+    }
+
+    //key for sortition with coin, the participation key the coin was delegated to or a_sk
+    pub fn participation_key(&self, constants: &Constants<E>, coin: &WalletCoin<E>) -> ParticipationKey<E> {
+        match coin.a_sk_del {
+            Some(a_sk_del) => ParticipationKey {
+                a_sk: a_sk_del,
+                a_pk_owner: Some(self.keys.a_pk(constants)),
+            },
+            None => ParticipationKey {
+                a_sk: self.keys.a_sk,
+                a_pk_owner: None,
+            }
+        }
+    }
+
+    //the spending side of a coin, merkle paths are left empty for the caller
+    pub fn transfer_input(&self, coin: &WalletCoin<E>, merkle_height: usize) -> TransferInput<E> {
+        TransferInput {
+            a_sk: Some(self.keys.a_sk),
+            delegated: coin.is_delegated(),
+            a_sk_del: coin.a_sk_del,
+            coin: coin.coin(),
+            fs_tree_start: Some(coin.fs_tree_start),
+            fs_pk: Some(coin.fs_pk),
+            cm_merkle_path: vec![None; merkle_height],
+            cm_poseidon_path: vec![None; merkle_height],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellman::Circuit;
+    use bellman::gadgets::test::TestConstraintSystem;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, thread_rng};
+    use zcash_primitives::jubjub::JubjubBls12;
+
+    use crate::circuit::hash::Poseidon;
+    use crate::circuit::transfer::Transfer;
+    use crate::constants::binomial_constants::TauValue;
+
+    use super::*;
+
+    fn receive(wallet: &mut Wallet<Bls12>, constants: &Constants<Bls12>) -> WalletCoin<Bls12> {
+        let rng = &mut thread_rng();
+        let value = rng.gen::<u64>() % (1 << 59);
+
+        wallet.receive(constants, value, Fr::random(rng), <Bls12 as JubjubEngine>::Fs::random(rng), rng.gen(), Fr::random(rng)).clone()
+    }
+
+    #[test]
+    fn delegated_full_pk() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let mut keys = Keys::<Bls12>::new(rng);
+        let fs_tree_start: u64 = rng.gen();
+        let fs_pk = Fr::random(rng);
+        let a_pk = keys.a_pk(&constants);
+
+        let own = keys.full_pk(&constants, fs_tree_start, &fs_pk);
+        assert!(!keys.participation_key(&constants).is_delegated());
+        assert_eq!(own, native::full_pk(&constants, fs_tree_start, &fs_pk, &a_pk));
+
+        keys.new_participation_key(rng);
+        let participation_key = keys.participation_key(&constants);
+        assert!(participation_key.is_delegated());
+        assert_eq!(participation_key.a_pk_owner, Some(a_pk));
+
+        let delegated = keys.full_pk(&constants, fs_tree_start, &fs_pk);
+        assert_eq!(delegated, native::full_pk_delegated(&constants, fs_tree_start, &fs_pk, &a_pk, &keys.a_pk_del(&constants).unwrap()));
+        assert_ne!(own, delegated);
+    }
+
+    //spends coin with the transfer circuit
+    fn check_transfer(wallet: &Wallet<Bls12>, constants: &Constants<Bls12>, coin: &WalletCoin<Bls12>) {
+        //with an empty merkle path the anchor is the coin commitment itself
+        let mut transfer = Transfer::<Bls12, Poseidon>::init_testing(constants, 0, 1, coin.is_delegated());
+        transfer.inputs[0] = wallet.transfer_input(coin, 0);
+        transfer.outputs[0].coin.value = Some(coin.value);
+        transfer.outputs[1].coin.value = Some(0);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        transfer.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "{:?}", cs.which_is_unsatisfied());
+
+        let sn_key = wallet.participation_key(constants, coin).a_sk;
+        assert_eq!(cs.get("input 0/coin commitment membership/anchor/input variable"), coin.cm);
+        assert_eq!(cs.get("input 0/inputize sn/input variable"), native::sn(constants, &sn_key, &coin.rho));
+    }
+
+    //spends a coin from before the delegation and a delegated one with the transfer circuit
+    #[test]
+    fn transfer_delegated_coin() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let mut wallet = Wallet::<Bls12>::new(rng);
+        let own = receive(&mut wallet, &constants);
+        wallet.keys.new_participation_key(rng);
+        let delegated = receive(&mut wallet, &constants);
+        assert!(!own.is_delegated());
+        assert!(delegated.is_delegated());
+
+        check_transfer(&wallet, &constants, &own);
+        check_transfer(&wallet, &constants, &delegated);
+    }
+
+    //a coin delegated to an older participation key is still spendable and keeps its key for sortition
+    #[test]
+    fn new_participation_key_keeps_coins() {
+        let rng = &mut thread_rng();
+        let jubjub = JubjubBls12::new();
+        let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+        let mut wallet = Wallet::<Bls12>::new(rng);
+        let first_key = wallet.keys.new_participation_key(rng);
+        let old = receive(&mut wallet, &constants);
+        let second_key = wallet.keys.new_participation_key(rng);
+        let new = receive(&mut wallet, &constants);
+        assert_ne!(first_key, second_key);
+
+        assert_eq!(wallet.transfer_input(&old, 4).a_sk_del, Some(first_key));
+        assert_eq!(wallet.transfer_input(&new, 4).a_sk_del, Some(second_key));
+
+        let participation_key = wallet.participation_key(&constants, &old);
+        assert_eq!(participation_key.a_sk, first_key);
+        assert_eq!(participation_key.full_pk(&constants, old.fs_tree_start, &old.fs_pk), native::full_pk_delegated(&constants, old.fs_tree_start, &old.fs_pk, &wallet.keys.a_pk(&constants), &native::a_pk(&constants, &first_key)));
+
+        check_transfer(&wallet, &constants, &old);
+        check_transfer(&wallet, &constants, &new);
+    }
+}
@@ -0,0 +1,89 @@
+use std::env;
+
+use rand::{Rng, thread_rng};
+
+use anonstake::api::{self, Bls12, Fr};
+use anonstake::circuit::AnonStake;
+use anonstake::circuit::diagnostics::{check_witness, public_inputs};
+use anonstake::circuit::hash::Poseidon;
+use anonstake::circuit::mint::{Mint, MintWitness};
+use anonstake::constants::Constants;
+use anonstake::constants::binomial_constants::TauValue;
+use anonstake::constants::poseidon_constants::PoseidonConstants;
+use anonstake::error::Error;
+use anonstake::forward_secure;
+use anonstake::forward_secure::{BasicSig, ForwardSecureSignatureScheme, SumCompositionEight};
+use ff::Field;
+use zcash_primitives::jubjub::JubjubBls12;
+
+//only uses the public api of the crate, like another crate depending on anonstake would
+
+#[test]
+fn mint_params_prove_verify() {
+    let rng = &mut thread_rng();
+    let jubjub = JubjubBls12::new();
+    let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau20);
+
+    let params = api::generate_params(Mint::init_empty(&constants), rng).unwrap();
+
+    let path = env::temp_dir().join(format!("anonstake_api_mint_{}.params", rng.gen::<u64>()));
+    api::write_params(&params, &path).unwrap();
+    let params = api::read_params(&path, true).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let witness = MintWitness::new(&constants, Fr::random(rng), 1000);
+    let (proof, input) = api::prove(&params, Mint::from_witness(&constants, &witness), rng).unwrap();
+    assert!(input == witness.public_inputs());
+
+    let pvk = api::prepare_verifying_key(&params.vk);
+    api::verify(&pvk, &proof, &input).unwrap();
+
+    let mut wrong_value = input.clone();
+    wrong_value[0].add_assign(&Fr::one());
+    match api::verify(&pvk, &proof, &wrong_value) {
+        Err(Error::InvalidProof(_)) => {}
+        _ => panic!("a proof for another value verified"),
+    }
+}
+
+#[test]
+fn missing_params() {
+    let path = env::temp_dir().join("anonstake_api_does_not_exist.params");
+    match api::read_params(&path, false) {
+        Err(e @ Error::ParamsMissing(_)) => assert_eq!(e.exit_code(), 66),
+        _ => panic!("reading a missing params file has to fail with ParamsMissing"),
+    }
+}
+
+#[test]
+fn sortition_witness_satisfied() {
+    let jubjub = JubjubBls12::new();
+    let constants = Constants::<Bls12>::get(&jubjub, TauValue::Tau1500);
+
+    let anonstake = AnonStake::<Bls12, Poseidon>::init_testing(&constants, false, 10, 1);
+    let inputs = public_inputs::<Bls12, _>(anonstake.clone()).unwrap();
+    let check = check_witness::<Bls12, _>(anonstake, &inputs).unwrap();
+    assert!(check.num_constraints > 0);
+    assert!(check.is_valid());
+}
+
+#[test]
+fn forward_secure_sign_verify() {
+    type Scheme = SumCompositionEight<BasicSig<Bls12>>;
+
+    let constants = forward_secure::Constants {
+        jubjub: JubjubBls12::new(),
+        poseidon: PoseidonConstants::<Bls12>::get(),
+    };
+
+    let m = Fr::random(&mut thread_rng());
+    let (sk, pk) = Scheme::key_gen(&constants, &[7; 32]);
+
+    let sig = Scheme::sign(&constants, 0, &sk, &m);
+    assert!(Scheme::verify(&constants, &pk, &m, &sig, 0));
+
+    let sk = Scheme::update(&constants, 3, sk);
+    let sig = Scheme::sign(&constants, 3, &sk, &m);
+    assert!(Scheme::verify(&constants, &pk, &m, &sig, 3));
+    assert!(!Scheme::verify(&constants, &pk, &m, &sig, 2));
+}